@@ -158,6 +158,18 @@ impl<'a, T> Node<'a, T> {
         }
     }
 
+    /// Detach a node from its parent and siblings, moving its children into its place.  A node
+    /// without a parent has no place to move them to, so is left as it is.
+    pub fn unwrap_children(&'a self) {
+        if self.parent().is_none() {
+            return;
+        }
+        while let Some(child) = self.first_child() {
+            self.insert_before(child);
+        }
+        self.detach();
+    }
+
     /// Append a new child to this node, after existing children.
     pub fn append(&'a self, new_child: &'a Node<'a, T>) {
         new_child.detach();
@@ -1,11 +1,11 @@
 use ctype::{isspace, isdigit, isalpha};
 use nodes;
-use nodes::{NodeValue, ListType, ListDelimType, NodeLink, AstNode};
+use nodes::{NodeValue, ListType, ListDelimType, NodeLink, NodeCodeBlock, AstNode};
 use nodes::TableAlignment;
 use parser::ComrakOptions;
 use scanners;
 use std;
-use std::cmp::max;
+use std::cmp::{max, min};
 use std::io::Write;
 
 /// Formats an AST as CommonMark, modified by the given options.
@@ -15,6 +15,9 @@ pub fn format_document<'a>(root: &'a AstNode<'a>, options: &ComrakOptions) -> St
     if !f.v.is_empty() && f.v[f.v.len() - 1] != b'\n' {
         f.v.push(b'\n');
     }
+    if options.strip_trailing_newline {
+        f.v.pop();
+    }
     String::from_utf8(f.v).unwrap()
 }
 
@@ -38,6 +41,7 @@ enum Escaping {
     Literal,
     Normal,
     URL,
+    PointyURL,
     Title,
 }
 
@@ -84,7 +88,13 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
             } else {
                 self.v.push(b'\n');
                 if self.need_cr > 1 {
-                    self.v.extend(&self.prefix);
+                    let mut len = self.prefix.len();
+                    if self.options.preserve_source_style {
+                        while len > 0 && self.prefix[len - 1] == b' ' {
+                            len -= 1;
+                        }
+                    }
+                    self.v.extend(&self.prefix[..len]);
                 }
             }
             self.column = 0;
@@ -105,6 +115,7 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
             }
 
             let nextc = buf.get(i + 1);
+            let width = char_width(&buf[i..]);
             if buf[i] == b' ' && wrap {
                 if !self.begin_line {
                     let last_nonspace = self.v.len();
@@ -127,24 +138,27 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
                 self.last_breakable = 0;
             } else if escaping == Escaping::Literal {
                 self.v.push(buf[i]);
-                self.column += 1;
+                self.column += width;
                 self.begin_line = false;
                 self.begin_content = self.begin_content && isdigit(buf[i]);
             } else {
-                self.outc(buf[i], escaping, nextc);
+                self.outc(buf[i], escaping, nextc, width);
                 self.begin_line = false;
                 self.begin_content = self.begin_content && isdigit(buf[i]);
             }
 
+            // Text moved onto a new line must not be read back as the start of a block.
+            let char_end = nextc.map_or(true, |&c| c & 0xc0 != 0x80);
             if self.options.width > 0 && self.column > self.options.width && !self.begin_line &&
-                self.last_breakable > 0
+                self.last_breakable > 0 && char_end &&
+                self.v.get(self.last_breakable + 1).map_or(false, |&c| !may_start_block(c))
             {
                 let remainder = self.v[self.last_breakable + 1..].to_vec();
                 self.v.truncate(self.last_breakable);
                 self.v.push(b'\n');
                 self.v.extend(&self.prefix);
                 self.v.extend(&remainder);
-                self.column = self.prefix.len() + remainder.len();
+                self.column = self.prefix.len() + display_width(&remainder);
                 self.last_breakable = 0;
                 self.begin_line = false;
                 self.begin_content = false;
@@ -154,7 +168,7 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
         }
     }
 
-    fn outc(&mut self, c: u8, escaping: Escaping, nextc: Option<&u8>) {
+    fn outc(&mut self, c: u8, escaping: Escaping, nextc: Option<&u8>, width: usize) {
         let follows_digit = !self.v.is_empty() && isdigit(self.v[self.v.len() - 1]);
 
         let nextc = nextc.map_or(0, |&c| c);
@@ -173,6 +187,8 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
                  (escaping == Escaping::URL &&
                       (c == b'`' || c == b'<' || c == b'>' || isspace(c) || c == b'\\' ||
                            c == b')' || c == b'(')) ||
+                 (escaping == Escaping::PointyURL &&
+                      (c == b'<' || c == b'>' || isspace(c) || c == b'\\')) ||
                  (escaping == Escaping::Title &&
                       (c == b'`' || c == b'<' || c == b'>' || c == b'"' || c == b'\\')));
 
@@ -186,7 +202,19 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
             }
         } else {
             self.v.push(c);
-            self.column += 1;
+            self.column += width;
+        }
+    }
+
+    fn output_destination(&mut self, url: &str) {
+        // Pointy destinations may not contain spaces either, so those are percent-encoded in both
+        // forms.
+        if url.bytes().any(|c| c == b'(' || c == b')') {
+            write!(self, "<").unwrap();
+            self.output(url.as_bytes(), false, Escaping::PointyURL);
+            write!(self, ">").unwrap();
+        } else {
+            self.output(url.as_bytes(), false, Escaping::URL);
         }
     }
 
@@ -238,6 +266,49 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
         false
     }
 
+    /// Whether a code block is written in indented rather than fenced form.
+    fn indented_code_block(&self, node: &'a AstNode<'a>) -> bool {
+        let ast = node.data.borrow();
+        let ncb = match ast.value {
+            NodeValue::CodeBlock(ref ncb) => ncb,
+            _ => return false,
+        };
+
+        // An indented code block directly after another would be read back as part of it.
+        let fence_required = first_in_list_item(node) ||
+            (ncb.fenced && self.options.preserve_source_style) ||
+            node.previous_sibling().map_or(false, |previous| self.indented_code_block(previous));
+
+        code_block_info(ncb).is_empty() &&
+            (ncb.literal.len() > 2 && !isspace(ncb.literal.as_bytes()[0]) &&
+                 !(isspace(ncb.literal.as_bytes()[ncb.literal.len() - 1]) &&
+                       isspace(ncb.literal.as_bytes()[ncb.literal.len() - 2]))) &&
+            !fence_required
+    }
+
+    /// Keeps a run of more than one blank line between a block and the one before it.
+    fn preserve_blank_lines(&mut self, node: &'a AstNode<'a>) {
+        let mut previous = match node.previous_sibling() {
+            Some(previous) => previous,
+            None => return,
+        };
+        // Lists and their items end after any blank lines which follow them, so their last line
+        // is that of their last child.
+        while let Some(last_child) = previous.last_child() {
+            match previous.data.borrow().value {
+                NodeValue::List(..) |
+                NodeValue::Item(..) => (),
+                _ => break,
+            }
+            previous = last_child;
+        }
+        let ast = node.data.borrow();
+        let end_line = previous.data.borrow().end_line;
+        if ast.value.block() && end_line > 0 && ast.start_line > end_line + 2 {
+            self.need_cr = max(self.need_cr, min(ast.start_line - end_line, 255) as u8);
+        }
+    }
+
     fn format_node(&mut self, node: &'a AstNode<'a>, entering: bool) -> bool {
         self.node = node;
         let allow_wrap = self.options.width > 0 && !self.options.hardbreaks;
@@ -250,6 +321,10 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
             self.in_tight_list_item = self.get_in_tight_list_item(node);
         }
 
+        if entering && self.options.preserve_source_style {
+            self.preserve_blank_lines(node);
+        }
+
         match node.data.borrow().value {
             NodeValue::Document => (),
             NodeValue::BlockQuote => {
@@ -263,13 +338,23 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
                     self.blankline();
                 }
             }
-            NodeValue::List(..) => {
+            NodeValue::List(ref nl) => {
+                // Only a list with the same markers as written, or an indented code block, would
+                // be read back as part of this list.  Bullets are all written as `-` unless the
+                // source style is kept.
+                let preserve = self.options.preserve_source_style;
                 if !entering &&
                     match node.next_sibling() {
                         Some(next_sibling) => {
                             match next_sibling.data.borrow().value {
-                                NodeValue::CodeBlock(..) |
-                                NodeValue::List(..) => true,
+                                NodeValue::CodeBlock(..) => {
+                                    !preserve || self.indented_code_block(next_sibling)
+                                }
+                                NodeValue::List(ref next) => {
+                                    next.list_type == nl.list_type &&
+                                        next.delimiter == nl.delimiter &&
+                                        (!preserve || next.bullet_char == nl.bullet_char)
+                                }
                                 _ => false,
                             }
                         }
@@ -281,7 +366,7 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
                     self.blankline();
                 }
             }
-            NodeValue::Item(..) => {
+            NodeValue::Item(ref item) => {
                 let parent = match node.parent().unwrap().data.borrow().value {
                     NodeValue::List(ref nl) => *nl,
                     _ => unreachable!(),
@@ -289,8 +374,12 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
 
                 let mut listmarker = vec![];
 
-                let marker_width = if parent.list_type == ListType::Bullet {
-                    4
+                if parent.list_type == ListType::Bullet {
+                    if self.options.preserve_source_style {
+                        listmarker.push(parent.bullet_char);
+                    } else {
+                        listmarker.extend_from_slice(b"  - ");
+                    }
                 } else {
                     let mut list_number = parent.start;
                     let list_delim = parent.delimiter;
@@ -301,24 +390,27 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
                     }
                     write!(
                         listmarker,
-                        "{}{}{}",
+                        "{}{}",
                         list_number,
                         if list_delim == ListDelimType::Paren {
                             ")"
                         } else {
                             "."
-                        },
-                        if list_number < 10 { "  " } else { " " }
+                        }
                     ).unwrap();
-                    listmarker.len()
-                };
+                    if !self.options.preserve_source_style {
+                        listmarker.extend_from_slice(if list_number < 10 { b"  " } else { b" " });
+                    }
+                }
+
+                if self.options.preserve_source_style {
+                    let padding = max(item.padding, listmarker.len() + 1);
+                    listmarker.resize(padding, b' ');
+                }
+                let marker_width = listmarker.len();
 
                 if entering {
-                    if parent.list_type == ListType::Bullet {
-                        write!(self, "  - ").unwrap();
-                    } else {
-                        self.write_all(&listmarker).unwrap();
-                    }
+                    self.write_all(&listmarker).unwrap();
                     self.begin_content = true;
                     for _ in 0..marker_width {
                         write!(self.prefix, " ").unwrap();
@@ -330,60 +422,66 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
                 }
             }
             NodeValue::Heading(ref nch) => {
+                let setext = nch.setext && self.options.preserve_source_style;
                 if entering {
-                    for _ in 0..nch.level {
-                        write!(self, "#").unwrap();
+                    if !setext {
+                        for _ in 0..nch.level {
+                            write!(self, "#").unwrap();
+                        }
+                        write!(self, " ").unwrap();
                     }
-                    write!(self, " ").unwrap();
                     self.begin_content = true;
                     self.no_linebreaks = true;
                 } else {
                     self.no_linebreaks = false;
+                    let attributes = attribute_block(&node.data.borrow().attributes);
+                    if !attributes.is_empty() {
+                        write!(self, " {}", attributes).unwrap();
+                    }
+                    if setext {
+                        let underline_width = max(3, self.column - self.prefix.len());
+                        self.cr();
+                        let underline = if nch.level == 1 { "=" } else { "-" };
+                        write!(self, "{}", underline.repeat(underline_width)).unwrap();
+                    }
                     self.blankline();
                 }
             }
             NodeValue::CodeBlock(ref ncb) => {
                 if entering {
-                    let first_in_list_item = node.previous_sibling().is_none() &&
-                        match node.parent() {
-                            Some(parent) => {
-                                match parent.data.borrow().value {
-                                    NodeValue::Item(..) => true,
-                                    _ => false,
-                                }
-                            }
-                            _ => false,
-                        };
-
-                    if !first_in_list_item {
+                    if !first_in_list_item(node) {
                         self.blankline();
                     }
 
-                    if ncb.info.is_empty() &&
-                        (ncb.literal.len() > 2 && !isspace(ncb.literal.as_bytes()[0]) &&
-                             !(isspace(ncb.literal.as_bytes()[ncb.literal.len() - 1]) &&
-                                   isspace(ncb.literal.as_bytes()[ncb.literal.len() - 2]))) &&
-                        !first_in_list_item
-                    {
+                    let info = code_block_info(ncb);
+                    if self.indented_code_block(node) {
                         write!(self, "    ").unwrap();
                         write!(self.prefix, "    ").unwrap();
                         write!(self, "{}", ncb.literal).unwrap();
                         let new_len = self.prefix.len() - 4;
                         self.prefix.truncate(new_len);
                     } else {
-                        let numticks = max(3, longest_backtick_sequence(&ncb.literal) + 1);
-                        for _ in 0..numticks {
-                            write!(self, "`").unwrap();
-                        }
-                        if !ncb.info.is_empty() {
-                            write!(self, " {}", ncb.info).unwrap();
+                        // A backtick fence cannot have backticks in its info string.
+                        let (fence_char, fence_length) = if ncb.fenced &&
+                            self.options.preserve_source_style &&
+                            !(ncb.fence_char == b'`' && info.contains('`'))
+                        {
+                            (ncb.fence_char, ncb.fence_length)
+                        } else {
+                            (b'`', 0)
+                        };
+                        let fence = [fence_char].repeat(max(
+                            max(max(3, self.options.min_fence_length), fence_length),
+                            longest_sequence(&ncb.literal, fence_char) + 1,
+                        ));
+                        self.write_all(&fence).unwrap();
+                        if !info.is_empty() {
+                            write!(self, " {}", info).unwrap();
                         }
                         self.cr();
                         write!(self, "{}", ncb.literal).unwrap();
                         self.cr();
-                        for _ in 0..numticks {
-                            write!(self, "`").unwrap();
-                        }
+                        self.write_all(&fence).unwrap();
                     }
                     self.blankline();
                 }
@@ -402,8 +500,126 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
                     self.blankline();
                 }
             }
+            NodeValue::Container(ref nc) => {
+                if entering {
+                    self.blankline();
+                    for _ in 0..nc.fence_length {
+                        write!(self, ":").unwrap();
+                    }
+                    write!(self, "{}", nc.name).unwrap();
+                    let attributes = attribute_block(&node.data.borrow().attributes);
+                    if !attributes.is_empty() {
+                        write!(self, " {}", attributes).unwrap();
+                    }
+                    if !nc.info.is_empty() {
+                        write!(self, " {}", nc.info).unwrap();
+                    }
+                    self.cr();
+                } else {
+                    self.cr();
+                    for _ in 0..nc.fence_length {
+                        write!(self, ":").unwrap();
+                    }
+                    self.blankline();
+                }
+            }
+            NodeValue::DetailsBlock { ref summary } => {
+                if entering {
+                    self.blankline();
+                    write!(self, "+++ {}", summary).unwrap();
+                    self.cr();
+                } else {
+                    self.cr();
+                    write!(self, "+++").unwrap();
+                    self.blankline();
+                }
+            }
+            NodeValue::DetailsSummary => return false,
+            NodeValue::ColumnBlock(..) => {
+                if entering {
+                    let sources = node.children()
+                        .map(|column| {
+                            let mut f = CommonMarkFormatter::new(column, self.options);
+                            f.format_children(column);
+                            String::from_utf8(f.v).unwrap()
+                        })
+                        .collect::<Vec<_>>();
+                    let rows = sources.iter().map(|s| s.lines().count()).max().unwrap_or(0);
+
+                    self.blankline();
+                    for i in 0..rows {
+                        write!(self, "|||").unwrap();
+                        for source in &sources {
+                            write!(self, " {} |||", source.lines().nth(i).unwrap_or("")).unwrap();
+                        }
+                        self.cr();
+                    }
+                    self.blankline();
+                }
+                return false;
+            }
+            NodeValue::Column => (),
+            NodeValue::Bibliography(..) => (),
+            NodeValue::BlockMacro { ref name, ref args } => {
+                if entering {
+                    self.blankline();
+                    write!(self, "@@{}", name).unwrap();
+                    if !args.is_empty() {
+                        write!(self, " {}", args).unwrap();
+                    }
+                    self.blankline();
+                }
+            }
+            NodeValue::ReferenceDef { ref label, ref url, ref title } => {
+                if entering {
+                    self.blankline();
+                    write!(self, "[{}]: ", label).unwrap();
+                    if url.is_empty() {
+                        write!(self, "<>").unwrap();
+                    } else {
+                        self.output_destination(url);
+                    }
+                    if !title.is_empty() {
+                        write!(self, " \"").unwrap();
+                        self.output(title.as_bytes(), false, Escaping::Title);
+                        write!(self, "\"").unwrap();
+                    }
+                    self.blankline();
+                }
+            }
+            NodeValue::AbbreviationDef { ref abbr, ref title } => {
+                if entering {
+                    self.blankline();
+                    write!(self, "*[{}]: {}", abbr, title).unwrap();
+                    self.blankline();
+                }
+            }
+            NodeValue::FootnoteDefinition(ref label) => {
+                if entering {
+                    self.blankline();
+                    write!(self, "[^{}]: ", label).unwrap();
+                    self.begin_content = true;
+                    write!(self.prefix, "    ").unwrap();
+                } else {
+                    let new_len = self.prefix.len() - 4;
+                    self.prefix.truncate(new_len);
+                    self.blankline();
+                }
+            }
             NodeValue::Paragraph => {
                 if !entering {
+                    let attributes = attribute_block(&node.data.borrow().attributes);
+                    if !attributes.is_empty() {
+                        self.cr();
+                        write!(self, "{}", attributes).unwrap();
+                    }
+                    self.blankline();
+                }
+            }
+            NodeValue::SimpleParagraph(ref literal) => {
+                if entering {
+                    self.output(literal.as_bytes(), allow_wrap, Escaping::Normal);
+                } else {
                     self.blankline();
                 }
             }
@@ -414,7 +630,9 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
             }
             NodeValue::LineBreak => {
                 if entering {
-                    if !self.options.hardbreaks {
+                    if self.options.ignore_trailing_space_breaks {
+                        write!(self, "\\").unwrap();
+                    } else if !self.options.hardbreaks {
                         write!(self, "  ").unwrap();
                     }
                     self.cr();
@@ -452,6 +670,27 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
                     self.write_all(literal.as_bytes()).unwrap();
                 }
             }
+            NodeValue::Cite(ref key) => {
+                if entering {
+                    write!(self, "[@{}]", key).unwrap();
+                }
+            }
+            NodeValue::Abbreviation(..) => (),
+            NodeValue::FootnoteReference(ref label) => {
+                if entering {
+                    write!(self, "[^{}]", label).unwrap();
+                }
+            }
+            NodeValue::InlineMacro { ref name, ref args } => {
+                if entering {
+                    write!(self, "{{{}:{}}}", name, args).unwrap();
+                }
+            }
+            NodeValue::TaskItem { checked, .. } => {
+                if entering {
+                    write!(self, "[{}]", if checked { 'x' } else { ' ' }).unwrap();
+                }
+            }
             NodeValue::Strong => {
                 if entering {
                     write!(self, "**").unwrap();
@@ -499,6 +738,13 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
                     write!(self, "^").unwrap();
                 }
             }
+            NodeValue::Highlight => {
+                if entering {
+                    write!(self, "==").unwrap();
+                } else {
+                    write!(self, "==").unwrap();
+                }
+            }
             NodeValue::Link(ref nl) => {
                 if is_autolink(node, nl) {
                     if entering {
@@ -515,7 +761,7 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
                     write!(self, "[").unwrap();
                 } else {
                     write!(self, "](").unwrap();
-                    self.output(nl.url.as_bytes(), false, Escaping::URL);
+                    self.output_destination(&nl.url);
                     if !nl.title.is_empty() {
                         write!(self, " \"").unwrap();
                         self.output(nl.title.as_bytes(), false, Escaping::Title);
@@ -529,7 +775,7 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
                     write!(self, "![").unwrap();
                 } else {
                     write!(self, "](").unwrap();
-                    self.output(nl.url.as_bytes(), false, Escaping::URL);
+                    self.output_destination(&nl.url);
                     if !nl.title.is_empty() {
                         self.output(&[b' ', b'"'], allow_wrap, Escaping::Literal);
                         self.output(nl.title.as_bytes(), false, Escaping::Title);
@@ -594,11 +840,33 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
     }
 }
 
-fn longest_backtick_sequence(literal: &str) -> usize {
+fn may_start_block(c: u8) -> bool {
+    isdigit(c) ||
+        match c {
+            b'-' | b'+' | b'*' | b'_' | b'=' | b'#' | b'>' | b'<' | b'`' | b'~' | b'|' | b':' |
+            b'@' => true,
+            _ => false,
+        }
+}
+
+fn first_in_list_item<'a>(node: &'a AstNode<'a>) -> bool {
+    node.previous_sibling().is_none() &&
+        match node.parent() {
+            Some(parent) => {
+                match parent.data.borrow().value {
+                    NodeValue::Item(..) => true,
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+}
+
+fn longest_sequence(literal: &str, f: u8) -> usize {
     let mut longest = 0;
     let mut current = 0;
     for c in literal.as_bytes() {
-        if *c == b'`' {
+        if *c == f {
             current += 1;
         } else {
             if current > longest {
@@ -674,3 +942,76 @@ fn table_escape<'a>(node: &'a AstNode<'a>, c: u8) -> bool {
         _ => c == b'|',
     }
 }
+
+fn code_block_info(ncb: &NodeCodeBlock) -> String {
+    if ncb.classes.is_empty() && ncb.id.is_none() {
+        return ncb.info.clone();
+    }
+
+    let mut attrs = vec![];
+    if !ncb.info.is_empty() {
+        attrs.push(format!(".{}", ncb.info));
+    }
+    for class in &ncb.classes {
+        attrs.push(format!(".{}", class));
+    }
+    if let Some(ref id) = ncb.id {
+        attrs.push(format!("#{}", id));
+    }
+    format!("{{{}}}", attrs.join(" "))
+}
+
+fn attribute_block(attributes: &[(String, String)]) -> String {
+    if attributes.is_empty() {
+        return String::new();
+    }
+
+    let attrs = attributes
+        .iter()
+        .map(|(name, value)| if value.is_empty() ||
+            value.contains(char::is_whitespace)
+        {
+            format!("{}=\"{}\"", name, value)
+        } else {
+            format!("{}={}", name, value)
+        })
+        .collect::<Vec<_>>();
+    format!("{{{}}}", attrs.join(" "))
+}
+
+/// The number of columns taken by the character starting at `buf[0]`, for wrapping.  Without the
+/// `unicode-width` feature every byte counts as one column.
+#[cfg(not(feature = "unicode-width"))]
+fn char_width(_buf: &[u8]) -> usize {
+    1
+}
+
+/// The number of columns taken by the character starting at `buf[0]`, for wrapping.  UTF-8
+/// continuation bytes take none, so the character's display width is counted once at its first
+/// byte.
+#[cfg(feature = "unicode-width")]
+fn char_width(buf: &[u8]) -> usize {
+    use unicode_width::UnicodeWidthChar;
+
+    let len = if buf[0] < 0x80 {
+        return 1;
+    } else if buf[0] < 0xc0 {
+        return 0;
+    } else if buf[0] < 0xe0 {
+        2
+    } else if buf[0] < 0xf0 {
+        3
+    } else {
+        4
+    };
+
+    std::str::from_utf8(&buf[..std::cmp::min(len, buf.len())])
+        .ok()
+        .and_then(|s| s.chars().next())
+        .and_then(|c| c.width())
+        .unwrap_or(0)
+}
+
+fn display_width(buf: &[u8]) -> usize {
+    (0..buf.len()).map(|i| char_width(&buf[i..])).sum()
+}
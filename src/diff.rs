@@ -0,0 +1,139 @@
+//! Structural diffing of two ASTs.
+
+use nodes::{AstNode, NodeCodeBlock, NodeValue};
+use std::cmp;
+use std::mem;
+
+/// A single entry in the structural diff between two ASTs, as returned by `diff_ast`.
+#[derive(Clone, Copy)]
+pub enum AstDiff<'a> {
+    /// A node present only in the new AST.
+    Added(&'a AstNode<'a>),
+
+    /// A node present only in the old AST.
+    Removed(&'a AstNode<'a>),
+
+    /// A node of the same kind in both ASTs, whose value or descendants differ.  The entry is
+    /// immediately followed by the diff of the two nodes' children.
+    Changed {
+        /// The node in the old AST.
+        old: &'a AstNode<'a>,
+
+        /// The node in the new AST.
+        new: &'a AstNode<'a>,
+    },
+
+    /// A node identical in both ASTs, including all of its descendants.  The node given is the one
+    /// from the new AST.
+    Unchanged(&'a AstNode<'a>),
+}
+
+/// Computes a structural diff between the children of two nodes, typically two document roots.
+///
+/// Children are aligned with a longest common subsequence over structurally equal nodes; a
+/// removed node and an added node of the same kind at the same point are reported as `Changed`,
+/// and their children are diffed in turn.  Source positions are not considered.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, diff_ast, AstDiff, ComrakOptions};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let options = ComrakOptions::default();
+/// let old = parse_document(&arena, "# Title\n\nSome text.\n", &options);
+/// let new = parse_document(&arena, "# Title\n\nOther text.\n", &options);
+///
+/// let diff = diff_ast(old, new);
+/// match (&diff[0], &diff[1]) {
+///     (&AstDiff::Unchanged(_), &AstDiff::Changed { .. }) => (),
+///     _ => panic!("unexpected diff"),
+/// }
+/// # }
+/// ```
+pub fn diff_ast<'a>(old: &'a AstNode<'a>, new: &'a AstNode<'a>) -> Vec<AstDiff<'a>> {
+    let mut diffs = vec![];
+    diff_children(old, new, &mut diffs);
+    diffs
+}
+
+fn diff_children<'a>(old: &'a AstNode<'a>, new: &'a AstNode<'a>, diffs: &mut Vec<AstDiff<'a>>) {
+    let olds = old.children().collect::<Vec<_>>();
+    let news = new.children().collect::<Vec<_>>();
+
+    let mut lcs = vec![vec![0usize; news.len() + 1]; olds.len() + 1];
+    for i in (0..olds.len()).rev() {
+        for j in (0..news.len()).rev() {
+            lcs[i][j] = if nodes_equal(olds[i], news[j]) {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                cmp::max(lcs[i + 1][j], lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < olds.len() || j < news.len() {
+        if i < olds.len() && j < news.len() {
+            if nodes_equal(olds[i], news[j]) {
+                diffs.push(AstDiff::Unchanged(news[j]));
+                i += 1;
+                j += 1;
+                continue;
+            }
+
+            if lcs[i + 1][j + 1] == lcs[i][j] && same_kind(olds[i], news[j]) {
+                diffs.push(AstDiff::Changed {
+                    old: olds[i],
+                    new: news[j],
+                });
+                diff_children(olds[i], news[j], diffs);
+                i += 1;
+                j += 1;
+                continue;
+            }
+        }
+
+        if i < olds.len() && (j == news.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            diffs.push(AstDiff::Removed(olds[i]));
+            i += 1;
+        } else {
+            diffs.push(AstDiff::Added(news[j]));
+            j += 1;
+        }
+    }
+}
+
+fn nodes_equal<'a>(a: &'a AstNode<'a>, b: &'a AstNode<'a>) -> bool {
+    if !values_equal(&a.data.borrow().value, &b.data.borrow().value) {
+        return false;
+    }
+
+    let mut a_children = a.children();
+    let mut b_children = b.children();
+    loop {
+        match (a_children.next(), b_children.next()) {
+            (None, None) => return true,
+            (Some(ac), Some(bc)) => {
+                if !nodes_equal(ac, bc) {
+                    return false;
+                }
+            }
+            _ => return false,
+        }
+    }
+}
+
+// Code blocks record the lines of their contents, which are source positions.
+fn values_equal(a: &NodeValue, b: &NodeValue) -> bool {
+    match (a, b) {
+        (NodeValue::CodeBlock(a), NodeValue::CodeBlock(b)) => {
+            NodeCodeBlock { content_lines: b.content_lines, ..a.clone() } == *b
+        }
+        _ => a == b,
+    }
+}
+
+fn same_kind<'a>(a: &'a AstNode<'a>, b: &'a AstNode<'a>) -> bool {
+    mem::discriminant(&a.data.borrow().value) == mem::discriminant(&b.data.borrow().value)
+}
@@ -1,17 +1,273 @@
 use ctype::isspace;
-use nodes::{TableAlignment, NodeValue, ListType, AstNode};
-use parser::ComrakOptions;
+use nodes::{self, TableAlignment, NodeValue, ListType, AstNode};
+use parser::{starts_footnote_section, ComrakOptions, FootnoteNumbering};
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::io::{self, BufWriter, Write};
+use std::ptr;
+use strings;
 
 /// Formats an AST as HTML, modified by the given options.
 pub fn format_document<'a>(root: &'a AstNode<'a>, options: &ComrakOptions) -> String {
-    let mut f = HtmlFormatter::new(options);
-    f.format(root, false);
-    f.s
+    let mut s = Vec::with_capacity(1024);
+    HtmlFormatter::new(options, &mut s, root).format(root, false).unwrap();
+    String::from_utf8(s).unwrap()
 }
 
-struct HtmlFormatter<'o> {
-    s: String,
+/// Formats an AST as HTML, modified by the given options, writing it directly to `output`.
+/// Small writes are batched through a `BufWriter`, which is flushed before returning.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, format_html_to, ComrakOptions};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let options = ComrakOptions::default();
+/// let root = parse_document(&arena, "Hello, *world*.\n", &options);
+///
+/// let mut html = vec![];
+/// format_html_to(root, &options, &mut html).unwrap();
+/// assert_eq!(String::from_utf8(html).unwrap(), "<p>Hello, <em>world</em>.</p>\n");
+/// # }
+/// ```
+pub fn format_document_to<'a>(
+    root: &'a AstNode<'a>,
+    options: &ComrakOptions,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    let mut f = HtmlFormatter::new(options, BufWriter::new(output), root);
+    f.format(root, false)?;
+    f.output.flush()
+}
+
+/// Formats one section of a document as HTML, modified by the given options.  The section begins
+/// with the `index`th top-level heading of the given `level`, counting from zero, and runs up to
+/// the next heading of the same or a higher level, or to the end of the document.  Returns `None`
+/// if there is no such heading.
+///
+/// Footnotes and citations are numbered as in the whole document, and the document's
+/// bibliography, if any, follows the section so that its citations have something to link to.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, format_html_section, ComrakOptions};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let options = ComrakOptions::default();
+/// let root = parse_document(&arena, "# A\n\nOne.\n\n# B\n\nTwo.\n", &options);
+///
+/// assert_eq!(format_html_section(root, 1, 1, &options).unwrap(), "<h1>B</h1>\n<p>Two.</p>\n");
+/// assert_eq!(format_html_section(root, 1, 2, &options), None);
+/// # }
+/// ```
+pub fn format_section<'a>(
+    root: &'a AstNode<'a>,
+    level: u32,
+    index: usize,
+    options: &ComrakOptions,
+) -> Option<String> {
+    let heading = root.children()
+        .filter(|node| nodes::node_heading_level(node) == Some(level))
+        .nth(index)?;
+
+    let section = nodes::section(heading);
+    let bibliography = root.children().find(|node| {
+        matches!(node.data.borrow().value, NodeValue::Bibliography(..))
+    });
+
+    let mut s = Vec::with_capacity(1024);
+    {
+        let mut f = HtmlFormatter::new(options, &mut s, root);
+        f.open_wrap_element().unwrap();
+        for node in &section {
+            f.format(node, false).unwrap();
+        }
+        if let Some(bibliography) = bibliography {
+            if !section.iter().any(|node| ptr::eq(*node, bibliography)) {
+                f.format(bibliography, false).unwrap();
+            }
+        }
+        f.close_sections().unwrap();
+        f.close_wrap_element().unwrap();
+    }
+    Some(String::from_utf8(s).unwrap())
+}
+
+/// A writer which remembers whether the last byte written was a newline.  With `hold_lf` set, a
+/// final newline is held back until something follows it, so the output never ends with one.
+struct WriteWithLast<W: Write> {
+    output: W,
+    last_was_lf: bool,
+    hold_lf: bool,
+    held_lf: bool,
+}
+
+impl<W: Write> Write for WriteWithLast<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.hold_lf {
+            self.write_all(buf)?;
+            return Ok(buf.len());
+        }
+
+        let n = self.output.write(buf)?;
+        if n > 0 {
+            self.last_was_lf = buf[n - 1] == b'\n';
+        }
+        Ok(n)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        let last = match buf.last() {
+            Some(&last) => last,
+            None => return Ok(()),
+        };
+        self.last_was_lf = last == b'\n';
+
+        if !self.hold_lf {
+            return self.output.write_all(buf);
+        }
+        if self.held_lf {
+            self.output.write_all(b"\n")?;
+        }
+        self.held_lf = self.last_was_lf;
+        self.output.write_all(if self.held_lf { &buf[..buf.len() - 1] } else { buf })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+struct HtmlFormatter<'o, W: Write> {
+    output: WriteWithLast<W>,
     options: &'o ComrakOptions,
+    sections: Vec<u32>,
+    citations: HashMap<String, usize>,
+    footnotes: HashMap<String, Footnote>,
+    footnote_ids: HashSet<String>,
+    footnote_backref: Option<Footnote>,
+    language_aliases: HashMap<String, &'o str>,
+}
+
+/// A footnote's number, the section numbering it if footnotes are numbered per section, and
+/// whether the text refers to it.
+#[derive(Clone, Copy)]
+struct Footnote {
+    number: usize,
+    section: Option<usize>,
+    referenced: bool,
+}
+
+impl Footnote {
+    /// The part of the footnote's HTML ids after `fn` or `fnref`.
+    fn anchor(&self) -> String {
+        match self.section {
+            Some(section) => format!("-section-{}-note-{}", section, self.number),
+            None => self.number.to_string(),
+        }
+    }
+}
+
+fn is_top_level<'a>(node: &'a AstNode<'a>) -> bool {
+    match node.parent() {
+        Some(parent) => matches!(parent.data.borrow().value, NodeValue::Document),
+        None => false,
+    }
+}
+
+fn is_only_child<'a>(node: &'a AstNode<'a>) -> bool {
+    node.previous_sibling().is_none() && node.next_sibling().is_none()
+}
+
+fn in_blockquote<'a>(node: &'a AstNode<'a>) -> bool {
+    match node.parent() {
+        Some(parent) => matches!(parent.data.borrow().value, NodeValue::BlockQuote),
+        None => false,
+    }
+}
+
+fn is_footnote_definition<'a>(node: Option<&'a AstNode<'a>>) -> bool {
+    node.map_or(false, |n| matches!(n.data.borrow().value, NodeValue::FootnoteDefinition(..)))
+}
+
+/// Whether a footnote's back-link belongs at the end of this paragraph, its definition's last
+/// block.
+fn ends_footnote<'a>(paragraph: &'a AstNode<'a>) -> bool {
+    paragraph.next_sibling().is_none() && is_footnote_definition(paragraph.parent())
+}
+
+fn in_tight_list<'a>(node: &'a AstNode<'a>) -> bool {
+    match node.parent().and_then(|n| n.parent()) {
+        Some(list) => {
+            match list.data.borrow().value {
+                NodeValue::List(ref nl) => nl.tight,
+                _ => false,
+            }
+        }
+        None => false,
+    }
+}
+
+fn is_figure<'a>(paragraph: &'a AstNode<'a>) -> bool {
+    match paragraph.data.borrow().value {
+        NodeValue::Paragraph => (),
+        _ => return false,
+    }
+
+    match paragraph.first_child() {
+        Some(image) if image.next_sibling().is_none() => {
+            match image.data.borrow().value {
+                NodeValue::Image(ref nl) => !nl.title.is_empty(),
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+fn in_paragraph<'a>(node: &'a AstNode<'a>) -> bool {
+    match nodes::containing_block(node) {
+        Some(block) => matches!(block.data.borrow().value, NodeValue::Paragraph),
+        None => false,
+    }
+}
+
+/// Maps the lowercased aliases in the `code_language_aliases` option, and the names they map
+/// to, to the names to output.
+fn language_aliases(aliases: &HashMap<String, String>) -> HashMap<String, &str> {
+    let mut map = HashMap::new();
+    for name in aliases.values() {
+        map.insert(name.to_ascii_lowercase(), name.as_str());
+    }
+    for (alias, name) in aliases {
+        map.insert(alias.to_ascii_lowercase(), name.as_str());
+    }
+    map
+}
+
+/// Parses line highlighting metadata such as `{1,3-5}` from a code block's info string into
+/// inclusive ranges of line numbers.  Malformed entries are skipped.
+fn highlighted_lines(info: &str) -> Vec<(usize, usize)> {
+    let spec = match info.split_whitespace().find(
+        |t| t.starts_with('{') && t.ends_with('}'),
+    ) {
+        Some(spec) => &spec[1..spec.len() - 1],
+        None => return vec![],
+    };
+
+    let mut ranges = vec![];
+    for part in spec.split(',') {
+        let mut bounds = part.splitn(2, '-').map(|b| b.trim().parse::<usize>());
+        let range = match (bounds.next(), bounds.next()) {
+            (Some(Ok(line)), None) => (line, line),
+            (Some(Ok(first)), Some(Ok(last))) if first <= last => (first, last),
+            _ => continue,
+        };
+        ranges.push(range);
+    }
+    ranges
 }
 
 fn tagfilter(literal: &str) -> bool {
@@ -42,7 +298,22 @@ fn tagfilter(literal: &str) -> bool {
     false
 }
 
-fn tagfilter_block(input: &str, mut o: &mut String) {
+fn is_bare_br(literal: &str) -> bool {
+    let lower = literal.to_ascii_lowercase();
+    let rest = match lower.strip_prefix("<br") {
+        Some(rest) => rest.trim_start(),
+        None => return false,
+    };
+    rest == ">" || rest == "/>"
+}
+
+// Whether `name` is safe to write unescaped as an element or attribute name.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() &&
+        name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b':' || b == b'-')
+}
+
+fn tagfilter_block(input: &str, o: &mut dyn Write) -> io::Result<()> {
     let src = input.as_bytes();
     let size = src.len();
     let mut i = 0;
@@ -54,7 +325,7 @@ fn tagfilter_block(input: &str, mut o: &mut String) {
         }
 
         if i > org {
-            *o += &input[org..i];
+            o.write_all(&input.as_bytes()[org..i])?;
         }
 
         if i >= size {
@@ -62,31 +333,218 @@ fn tagfilter_block(input: &str, mut o: &mut String) {
         }
 
         if tagfilter(&input[i..]) {
-            *o += "&lt;";
+            o.write_all(b"&lt;")?;
         } else {
-            o.push('<');
+            o.write_all(b"<")?;
         }
 
         i += 1;
     }
+    Ok(())
 }
 
-impl<'o> HtmlFormatter<'o> {
-    fn new(options: &'o ComrakOptions) -> Self {
-        HtmlFormatter {
-            s: String::with_capacity(1024),
-            options: options,
+impl<'o, W: Write> HtmlFormatter<'o, W> {
+    fn new<'a>(options: &'o ComrakOptions, output: W, root: &'a AstNode<'a>) -> Self {
+        let mut f = HtmlFormatter {
+            output: WriteWithLast {
+                output,
+                last_was_lf: true,
+                hold_lf: options.strip_trailing_newline,
+                held_lf: false,
+            },
+            options,
+            sections: vec![],
+            citations: HashMap::new(),
+            footnotes: HashMap::new(),
+            footnote_ids: HashSet::new(),
+            footnote_backref: None,
+            language_aliases: language_aliases(&options.code_language_aliases),
+        };
+        f.number_footnotes(root);
+        f.number_citations(root);
+        f
+    }
+
+    /// Numbers the citations of works in the bibliography in order of first citation, as they
+    /// are listed in the bibliography, so any part of the document rendered shares the numbering.
+    fn number_citations<'a>(&mut self, root: &'a AstNode<'a>) {
+        let bibliography = match self.options.bibliography {
+            Some(ref bibliography) => bibliography,
+            None => return,
+        };
+
+        for node in root.descendants() {
+            if let NodeValue::Cite(ref key) = node.data.borrow().value {
+                if bibliography.contains_key(key) {
+                    let next = self.citations.len() + 1;
+                    self.citations.entry(key.clone()).or_insert(next);
+                }
+            }
+        }
+    }
+
+    /// Numbers the footnotes in the whole of `root`, the referenced ones in order of first
+    /// reference and then the rest, so any part of the document rendered shares the numbering.
+    /// With per-section numbering, each section starts again at 1.
+    fn number_footnotes<'a>(&mut self, root: &'a AstNode<'a>) {
+        let per_section = match self.options.footnote_numbering {
+            FootnoteNumbering::Global => false,
+            FootnoteNumbering::PerSection { .. } => true,
+        };
+        let mut counts = vec![0];
+        for referencing in &[true, false] {
+            let mut section = 0;
+            for block in root.children() {
+                if starts_footnote_section(block, self.options.footnote_numbering) {
+                    section += 1;
+                    if counts.len() <= section {
+                        counts.push(0);
+                    }
+                }
+                for node in block.descendants() {
+                    let label = match node.data.borrow().value {
+                        NodeValue::FootnoteReference(ref label) if *referencing => label.clone(),
+                        NodeValue::FootnoteDefinition(ref label) if !*referencing => label.clone(),
+                        _ => continue,
+                    };
+                    let label = strings::normalize_reference_label(&label);
+                    if let Entry::Vacant(entry) = self.footnotes.entry(label) {
+                        counts[section] += 1;
+                        entry.insert(Footnote {
+                            number: counts[section],
+                            section: if per_section { Some(section) } else { None },
+                            referenced: *referencing,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    fn footnote(&mut self, label: &str) -> Footnote {
+        let next = self.footnotes.len() + 1;
+        *self.footnotes.entry(strings::normalize_reference_label(label)).or_insert(Footnote {
+            number: next,
+            section: None,
+            referenced: false,
+        })
+    }
+
+    fn open_section(&mut self, level: u32) -> io::Result<()> {
+        while self.sections.last().map_or(false, |&open| open >= level) {
+            self.sections.pop();
+            self.cr()?;
+            self.output.write_all(b"</section>\n")?;
+        }
+        self.sections.push(level);
+        self.cr()?;
+        self.output.write_all(b"<section>\n")?;
+        Ok(())
+    }
+
+    fn omit_paragraph_tags<'a>(&self, paragraph: &'a AstNode<'a>) -> bool {
+        in_tight_list(paragraph) ||
+            (self.options.unwrap_single_paragraph && is_top_level(paragraph) &&
+                 is_only_child(paragraph)) ||
+            (self.options.unwrap_blockquote_paragraph && in_blockquote(paragraph) &&
+                 is_only_child(paragraph))
+    }
+
+    /// Writes the link back from the current footnote to its first reference, if it has one not
+    /// yet written.
+    fn footnote_backref(&mut self, separator: &str) -> io::Result<()> {
+        if let Some(footnote) = self.footnote_backref.take() {
+            let options = self.options;
+            self.output.write_all(separator.as_bytes())?;
+            write!(
+                self.output,
+                "<a href=\"#fnref{}\" class=\"footnote-backref\" role=\"doc-backlink\" \
+                 aria-label=\"Back to reference {}\"",
+                footnote.anchor(),
+                footnote.number
+            )?;
+            if options.footnote_backref_hidden {
+                self.output.write_all(b" aria-hidden=\"true\" style=\"display:none\"")?;
+            }
+            self.output.write_all(b">")?;
+            self.escape(options.footnote_backref_symbol.as_ref().map_or("↩", |s| s.as_str()))?;
+            self.output.write_all(b"</a>")?;
+        }
+        Ok(())
+    }
+
+    fn open_wrap_element(&mut self) -> io::Result<()> {
+        if let Some((ref name, ref attrs)) = self.options.wrap_element {
+            if !is_valid_name(name) {
+                return Ok(());
+            }
+            write!(self.output, "<{}", name)?;
+            for (attr, value) in attrs.iter().filter(|&(attr, _)| is_valid_name(attr)) {
+                write!(self.output, " {}=\"", attr)?;
+                self.escape(value)?;
+                self.output.write_all(b"\"")?;
+            }
+            self.output.write_all(b">\n")?;
+        }
+        Ok(())
+    }
+
+    fn close_wrap_element(&mut self) -> io::Result<()> {
+        if let Some((ref name, _)) = self.options.wrap_element {
+            if !is_valid_name(name) {
+                return Ok(());
+            }
+            self.cr()?;
+            writeln!(self.output, "</{}>", name)?;
+        }
+        Ok(())
+    }
+
+    fn close_sections(&mut self) -> io::Result<()> {
+        while self.sections.pop().is_some() {
+            self.cr()?;
+            self.output.write_all(b"</section>\n")?;
+        }
+        Ok(())
+    }
+
+    fn inline_element(
+        &mut self,
+        entering: bool,
+        element: &Option<String>,
+        default: &str,
+        class: &Option<String>,
+    ) -> io::Result<()> {
+        let element = element.as_ref().map_or(default, |e| e.as_str());
+        if entering {
+            write!(self.output, "<{}", element)?;
+            if let Some(ref class) = *class {
+                self.output.write_all(b" class=\"")?;
+                self.escape(class)?;
+                self.output.write_all(b"\"")?;
+            }
+            self.output.write_all(b">")
+        } else {
+            write!(self.output, "</{}>", element)
+        }
+    }
+
+    fn close_void(&mut self) -> io::Result<()> {
+        if self.options.html5_void_elements {
+            self.output.write_all(b">")
+        } else {
+            self.output.write_all(b" />")
         }
     }
 
-    fn cr(&mut self) {
-        let l = self.s.len();
-        if l > 0 && self.s.as_bytes()[l - 1] != b'\n' {
-            self.s += "\n";
+    fn cr(&mut self) -> io::Result<()> {
+        if !self.output.last_was_lf {
+            self.output.write_all(b"\n")?;
         }
+        Ok(())
     }
 
-    fn escape(&mut self, buffer: &str) {
+    fn escape(&mut self, buffer: &str) -> io::Result<()> {
         lazy_static! {
             static ref NEEDS_ESCAPED: [bool; 256] = {
                 let mut sc = [false; 256];
@@ -108,7 +566,7 @@ impl<'o> HtmlFormatter<'o> {
             }
 
             if i > org {
-                self.s += &buffer[org..i];
+                self.output.write_all(&buffer.as_bytes()[org..i])?;
             }
 
             if i >= size {
@@ -116,22 +574,73 @@ impl<'o> HtmlFormatter<'o> {
             }
 
             match src[i] as char {
-                '"' => self.s += "&quot;",
-                '&' => self.s += "&amp;",
-                '<' => self.s += "&lt;",
-                '>' => self.s += "&gt;",
+                '"' => self.output.write_all(b"&quot;")?,
+                '&' => self.output.write_all(b"&amp;")?,
+                '<' => self.output.write_all(b"&lt;")?,
+                '>' => self.output.write_all(b"&gt;")?,
                 _ => unreachable!(),
             }
 
             i += 1;
         }
+        Ok(())
+    }
+
+    fn code_lines(
+        &mut self,
+        literal: &str,
+        numbered: bool,
+        highlights: &[(usize, usize)],
+    ) -> io::Result<()> {
+        for (i, line) in literal.lines().enumerate() {
+            let n = i + 1;
+            let highlighted = highlights.iter().any(|&(first, last)| first <= n && n <= last);
+            if numbered {
+                write!(
+                    self.output,
+                    "<span class=\"line{}\" data-line=\"{}\">",
+                    if highlighted { " highlighted" } else { "" },
+                    n
+                )?;
+            } else if highlighted {
+                self.output.write_all(b"<span class=\"highlighted\">")?;
+            }
+            self.escape(line)?;
+            if numbered || highlighted {
+                self.output.write_all(b"</span>")?;
+            }
+            self.output.write_all(b"\n")?;
+        }
+        Ok(())
     }
 
-    fn escape_href(&mut self, buffer: &str) {
+    fn attributes(&mut self, attributes: &[(String, String)]) -> io::Result<()> {
+        for (name, value) in attributes {
+            write!(self.output, " {}=\"", name)?;
+            self.escape(value)?;
+            self.output.write_all(b"\"")?;
+        }
+        Ok(())
+    }
+
+    /// Writes the destination of a link or image, as transformed by the `link_resolver` option.
+    fn link_href<'a>(&mut self, node: &'a AstNode<'a>, url: &str, title: &str) -> io::Result<()> {
+        let resolved = match self.options.link_resolver {
+            Some(ref resolver) => {
+                let mut text = String::new();
+                nodes::collect_text(node, &mut text);
+                resolver.resolve(url, title, &text)
+            }
+            None => None,
+        };
+        self.escape_href(resolved.as_ref().map_or(url, |u| u.as_str()))
+    }
+
+    fn escape_href(&mut self, buffer: &str) -> io::Result<()> {
         lazy_static! {
             static ref HREF_SAFE: [bool; 256] = {
                 let mut a = [false; 256];
-                for &c in b"-_.+!*'(),%#@?=;:/,+&$abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789".iter() {
+                for &c in b"-_.+!*(),%#@?=;:/,+$abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789".iter() {
                     a[c as usize] = true;
                 }
                 a
@@ -149,7 +658,7 @@ impl<'o> HtmlFormatter<'o> {
             }
 
             if i > org {
-                self.s += &buffer[org..i];
+                self.output.write_all(&buffer.as_bytes()[org..i])?;
             }
 
             if i >= size {
@@ -157,272 +666,566 @@ impl<'o> HtmlFormatter<'o> {
             }
 
             match src[i] as char {
-                '&' => self.s += "&amp;",
-                '\'' => self.s += "&#x27;",
-                _ => self.s += &format!("%{:02X}", src[i]),
+                '&' => self.output.write_all(b"&amp;")?,
+                '\'' => self.output.write_all(b"&#x27;")?,
+                _ => write!(self.output, "%{:02X}", src[i])?,
             }
 
             i += 1;
         }
+        Ok(())
     }
 
-    fn format_children<'a>(&mut self, node: &'a AstNode<'a>, plain: bool) {
+    fn format_children<'a>(&mut self, node: &'a AstNode<'a>, plain: bool) -> io::Result<()> {
         for n in node.children() {
-            self.format(n, plain);
+            self.format(n, plain)?;
         }
+        Ok(())
     }
 
-    fn format<'a>(&mut self, node: &'a AstNode<'a>, plain: bool) {
+    fn format<'a>(&mut self, node: &'a AstNode<'a>, plain: bool) -> io::Result<()> {
         if plain {
             match node.data.borrow().value {
                 NodeValue::Text(ref literal) |
                 NodeValue::Code(ref literal) |
-                NodeValue::HtmlInline(ref literal) => self.escape(literal),
-                NodeValue::LineBreak | NodeValue::SoftBreak => self.s.push(' '),
+                NodeValue::HtmlInline(ref literal) => self.escape(literal)?,
+                NodeValue::LineBreak | NodeValue::SoftBreak => self.output.write_all(b" ")?,
                 _ => (),
             }
-            self.format_children(node, true);
+            self.format_children(node, true)?;
         } else {
-            let new_plain = self.format_node(node, true);
-            self.format_children(node, new_plain);
-            self.format_node(node, false);
+            let new_plain = self.format_node(node, true)?;
+            self.format_children(node, new_plain)?;
+            self.format_node(node, false)?;
         }
+        Ok(())
     }
 
-    fn format_node<'a>(&mut self, node: &'a AstNode<'a>, entering: bool) -> bool {
+    fn format_node<'a>(&mut self, node: &'a AstNode<'a>, entering: bool) -> io::Result<bool> {
         match node.data.borrow().value {
-            NodeValue::Document => (),
+            NodeValue::Document => {
+                if entering {
+                    self.open_wrap_element()?;
+                } else {
+                    self.close_sections()?;
+                    self.close_wrap_element()?;
+                }
+            }
             NodeValue::BlockQuote => {
                 if entering {
-                    self.cr();
-                    self.s += "<blockquote>\n";
+                    self.cr()?;
+                    self.output.write_all(b"<blockquote>\n")?;
                 } else {
-                    self.cr();
-                    self.s += "</blockquote>\n";
+                    self.cr()?;
+                    self.output.write_all(b"</blockquote>\n")?;
                 }
             }
             NodeValue::List(ref nl) => {
                 if entering {
-                    self.cr();
+                    self.cr()?;
                     if nl.list_type == ListType::Bullet {
-                        self.s += "<ul>\n";
+                        self.output.write_all(b"<ul>\n")?;
                     } else if nl.start == 1 {
-                        self.s += "<ol>\n";
+                        self.output.write_all(b"<ol>\n")?;
                     } else {
-                        self.s += &format!("<ol start=\"{}\">\n", nl.start);
+                        writeln!(self.output, "<ol start=\"{}\">", nl.start)?;
                     }
                 } else if nl.list_type == ListType::Bullet {
-                    self.s += "</ul>\n";
+                    self.output.write_all(b"</ul>\n")?;
                 } else {
-                    self.s += "</ol>\n";
+                    self.output.write_all(b"</ol>\n")?;
                 }
             }
             NodeValue::Item(..) => {
                 if entering {
-                    self.cr();
-                    self.s += "<li>";
+                    self.cr()?;
+                    self.output.write_all(b"<li>")?;
                 } else {
-                    self.s += "</li>\n";
+                    self.output.write_all(b"</li>\n")?;
                 }
             }
             NodeValue::Heading(ref nch) => {
                 if entering {
-                    self.cr();
-                    self.s += &format!("<h{}>", nch.level);
+                    if self.options.ext_html_sections && is_top_level(node) {
+                        self.open_section(nch.level)?;
+                    }
+                    self.cr()?;
+                    write!(self.output, "<h{}", nch.level)?;
+                    self.attributes(&node.data.borrow().attributes)?;
+                    self.output.write_all(b">")?;
                 } else {
-                    self.s += &format!("</h{}>\n", nch.level);
+                    writeln!(self.output, "</h{}>", nch.level)?;
                 }
             }
             NodeValue::CodeBlock(ref ncb) => {
                 if entering {
-                    self.cr();
+                    self.cr()?;
 
-                    if ncb.info.is_empty() {
-                        self.s += "<pre><code>";
-                    } else {
-                        let mut first_tag = 0;
-                        while first_tag < ncb.info.len() &&
-                            !isspace(ncb.info.as_bytes()[first_tag])
-                        {
-                            first_tag += 1;
+                    let mut first_tag = 0;
+                    while first_tag < ncb.info.len() && !isspace(ncb.info.as_bytes()[first_tag]) {
+                        first_tag += 1;
+                    }
+
+                    let lang = match &ncb.info[..first_tag] {
+                        "linenos" if self.options.code_linenos_token => "",
+                        lang if lang.starts_with('{') => "",
+                        lang if self.options.github_pre_lang() => lang.split(',').next().unwrap(),
+                        lang => lang,
+                    };
+                    let lang = match self.language_aliases.get(&lang.to_ascii_lowercase()) {
+                        Some(name) => *name,
+                        None => lang,
+                    };
+                    let lang = match self.options.known_languages {
+                        Some(ref known) if !known.iter().any(|k| k.eq_ignore_ascii_case(lang)) => {
+                            ""
                         }
+                        _ => lang,
+                    };
 
-                        if self.options.github_pre_lang {
-                            self.s += "<pre lang=\"";
-                            self.escape(&ncb.info[..first_tag]);
-                            self.s += "\"><code>";
-                        } else {
-                            self.s += "<pre><code class=\"language-";
-                            self.escape(&ncb.info[..first_tag]);
-                            self.s += "\">";
+                    let lang_class = !lang.is_empty() && !self.options.github_pre_lang();
+                    if lang.is_empty() || lang_class {
+                        self.output.write_all(b"<pre><code")?;
+                    } else {
+                        self.output.write_all(b"<pre lang=\"")?;
+                        self.escape(lang)?;
+                        self.output.write_all(b"\"><code")?;
+                    }
+                    if lang_class || !ncb.classes.is_empty() {
+                        self.output.write_all(b" class=\"")?;
+                        if lang_class {
+                            self.output.write_all(b"language-")?;
+                            self.escape(lang)?;
                         }
+                        for (i, class) in ncb.classes.iter().enumerate() {
+                            if lang_class || i > 0 {
+                                self.output.write_all(b" ")?;
+                            }
+                            self.escape(class)?;
+                        }
+                        self.output.write_all(b"\"")?;
+                    }
+                    if let Some(ref id) = ncb.id {
+                        self.output.write_all(b" id=\"")?;
+                        self.escape(id)?;
+                        self.output.write_all(b"\"")?;
                     }
-                    self.escape(&ncb.literal);
-                    self.s += "</code></pre>\n";
+                    self.output.write_all(b">")?;
+
+                    let numbered = self.options.code_line_numbers ||
+                        (self.options.code_linenos_token &&
+                             ncb.info.split_whitespace().any(|t| t == "linenos"));
+                    let highlights = highlighted_lines(&ncb.info);
+                    if numbered || !highlights.is_empty() {
+                        self.code_lines(&ncb.literal, numbered, &highlights)?;
+                    } else {
+                        self.escape(&ncb.literal)?;
+                    }
+                    self.output.write_all(b"</code></pre>\n")?;
                 }
             }
             NodeValue::HtmlBlock(ref nhb) => {
                 if entering {
-                    self.cr();
-                    if self.options.ext_tagfilter {
-                        tagfilter_block(&nhb.literal, &mut self.s);
+                    self.cr()?;
+                    if self.options.ext_tagfilter() {
+                        tagfilter_block(&nhb.literal, &mut self.output)?;
                     } else {
-                        self.s += &nhb.literal;
+                        self.output.write_all(nhb.literal.as_bytes())?;
                     }
-                    self.cr();
+                    self.cr()?;
                 }
             }
             NodeValue::ThematicBreak => {
                 if entering {
-                    self.cr();
-                    self.s += "<hr />\n";
+                    self.cr()?;
+                    self.output.write_all(b"<hr")?;
+                    if let Some(ref class) = self.options.thematic_break_class {
+                        self.output.write_all(b" class=\"")?;
+                        self.escape(class)?;
+                        self.output.write_all(b"\"")?;
+                    }
+                    self.close_void()?;
+                    self.output.write_all(b"\n")?;
+                }
+            }
+            NodeValue::Container(ref nc) => {
+                let details = self.options.ext_collapsible && nc.name == "details";
+                if entering {
+                    self.cr()?;
+                    if details {
+                        self.output.write_all(b"<details>\n<summary>")?;
+                        self.escape(&nc.info)?;
+                        self.output.write_all(b"</summary>\n")?;
+                    } else {
+                        self.output.write_all(b"<div")?;
+                        if !nc.name.is_empty() {
+                            self.output.write_all(b" class=\"")?;
+                            self.escape(&nc.name)?;
+                            self.output.write_all(b"\"")?;
+                        }
+                        self.attributes(&node.data.borrow().attributes)?;
+                        self.output.write_all(b">\n")?;
+                    }
+                } else {
+                    self.cr()?;
+                    self.output.write_all(if details { b"</details>\n" } else { b"</div>\n" })?;
+                }
+            }
+            NodeValue::DetailsBlock { .. } => {
+                self.cr()?;
+                self.output.write_all(if entering { b"<details>\n" } else { b"</details>\n" })?;
+            }
+            NodeValue::DetailsSummary => {
+                if entering {
+                    self.output.write_all(b"<summary>")?;
+                } else {
+                    self.output.write_all(b"</summary>\n")?;
+                }
+            }
+            NodeValue::ColumnBlock(..) => {
+                if entering {
+                    self.cr()?;
+                    self.output.write_all(b"<div class=\"columns\">\n")?;
+                } else {
+                    self.cr()?;
+                    self.output.write_all(b"</div>\n")?;
+                }
+            }
+            NodeValue::Column => {
+                if entering {
+                    self.cr()?;
+                    self.output.write_all(b"<div class=\"column\">\n")?;
+                } else {
+                    self.cr()?;
+                    self.output.write_all(b"</div>\n")?;
+                }
+            }
+            NodeValue::Bibliography(ref keys) => {
+                if entering {
+                    self.close_sections()?;
+                    self.cr()?;
+                    self.output.write_all(b"<section class=\"bibliography\">\n<ol>\n")?;
+                    for key in keys {
+                        let entry = self.options.bibliography.as_ref().and_then(|b| b.get(key));
+                        self.output.write_all(b"<li id=\"ref-")?;
+                        self.escape(key)?;
+                        self.output.write_all(b"\">")?;
+                        if let Some(entry) = entry {
+                            self.escape(&entry.text)?;
+                        }
+                        self.output.write_all(b"</li>\n")?;
+                    }
+                    self.output.write_all(b"</ol>\n</section>\n")?;
+                }
+            }
+            NodeValue::ReferenceDef { .. } |
+            NodeValue::AbbreviationDef { .. } => (),
+            NodeValue::FootnoteDefinition(ref label) => {
+                if entering {
+                    if !is_footnote_definition(node.previous_sibling()) {
+                        self.close_sections()?;
+                        self.cr()?;
+                        self.output.write_all(
+                            b"<section class=\"footnotes\" role=\"doc-endnotes\">\n<ol>\n",
+                        )?;
+                    }
+                    let footnote = self.footnote(label);
+                    self.footnote_backref = if footnote.referenced {
+                        Some(footnote)
+                    } else {
+                        None
+                    };
+                    self.cr()?;
+                    writeln!(self.output, "<li id=\"fn{}\">", footnote.anchor())?;
+                } else {
+                    self.cr()?;
+                    self.footnote_backref("")?;
+                    self.cr()?;
+                    self.output.write_all(b"</li>\n")?;
+                    if !is_footnote_definition(node.next_sibling()) {
+                        self.output.write_all(b"</ol>\n</section>\n")?;
+                    }
+                }
+            }
+            NodeValue::BlockMacro { ref name, ref args } => {
+                if entering {
+                    self.cr()?;
+                    match self.options.block_macro_handlers.get(name) {
+                        Some(handler) => self.output.write_all(handler.render(args).as_bytes())?,
+                        None => {
+                            self.output.write_all(b"<!-- unknown macro: ")?;
+                            self.escape(name)?;
+                            self.output.write_all(b" -->\n")?;
+                        }
+                    }
+                }
+            }
+            NodeValue::Paragraph if self.options.image_figures && is_figure(node) => {
+                if entering {
+                    self.cr()?;
+                    self.output.write_all(b"<figure>\n")?;
+                } else {
+                    self.output.write_all(b"</figure>\n")?;
                 }
             }
             NodeValue::Paragraph => {
-                let tight = match node.parent().and_then(|n| n.parent()).map(|n| {
-                    n.data.borrow().value.clone()
-                }) {
-                    Some(NodeValue::List(nl)) => nl.tight,
-                    _ => false,
-                };
-
+                let tight = self.omit_paragraph_tags(node);
                 if entering {
                     if !tight {
-                        self.cr();
-                        self.s += "<p>";
+                        self.cr()?;
+                        self.output.write_all(b"<p")?;
+                        self.attributes(&node.data.borrow().attributes)?;
+                        self.output.write_all(b">")?;
                     }
                 } else if !tight {
-                    self.s += "</p>\n";
+                    if ends_footnote(node) {
+                        self.footnote_backref(" ")?;
+                    }
+                    self.output.write_all(b"</p>\n")?;
+                }
+            }
+            NodeValue::SimpleParagraph(ref literal) => {
+                if entering {
+                    let tight = self.omit_paragraph_tags(node);
+                    if !tight {
+                        self.cr()?;
+                        self.output.write_all(b"<p>")?;
+                    }
+                    self.escape(literal)?;
+                    if ends_footnote(node) {
+                        self.footnote_backref(" ")?;
+                    }
+                    if !tight {
+                        self.output.write_all(b"</p>\n")?;
+                    }
                 }
             }
             NodeValue::Text(ref literal) => {
                 if entering {
-                    self.escape(literal);
+                    self.escape(literal)?;
                 }
             }
             NodeValue::LineBreak => {
                 if entering {
-                    self.s += "<br />\n";
+                    self.output.write_all(b"<br")?;
+                    self.close_void()?;
+                    self.output.write_all(b"\n")?;
                 }
             }
             NodeValue::SoftBreak => {
                 if entering {
-                    if self.options.hardbreaks {
-                        self.s += "<br />\n";
+                    if self.options.hardbreaks &&
+                        (!self.options.hardbreaks_paragraph_only || in_paragraph(node))
+                    {
+                        self.output.write_all(b"<br")?;
+                        self.close_void()?;
+                        self.output.write_all(b"\n")?;
                     } else {
-                        self.s += "\n";
+                        self.output.write_all(b"\n")?;
                     }
                 }
             }
             NodeValue::Code(ref literal) => {
                 if entering {
-                    self.s += "<code>";
-                    self.escape(literal);
-                    self.s += "</code>";
+                    self.output.write_all(b"<code>")?;
+                    self.escape(literal)?;
+                    self.output.write_all(b"</code>")?;
                 }
             }
-            NodeValue::HtmlInline(ref literal) => {
+            NodeValue::TaskItem {
+                checked,
+                children_all_checked,
+            } => {
                 if entering {
-                    if self.options.ext_tagfilter && tagfilter(literal) {
-                        self.s += "&lt;";
-                        self.s += &literal[1..];
+                    self.output.write_all(b"<input type=\"checkbox\" disabled=\"\"")?;
+                    if checked {
+                        self.output.write_all(b" checked=\"\"")?;
+                    }
+                    if children_all_checked == Some(false) {
+                        self.output.write_all(b" data-partial=\"true\"")?;
+                    }
+                    self.close_void()?;
+                }
+            }
+            NodeValue::Cite(ref key) => {
+                if entering {
+                    if let Some(&number) = self.citations.get(key) {
+                        self.output.write_all(b"<cite><a href=\"#ref-")?;
+                        self.escape_href(key)?;
+                        write!(self.output, "\">[{}]</a></cite>", number)?;
                     } else {
-                        self.s += literal;
+                        self.output.write_all(b"<cite data-ref=\"")?;
+                        self.escape(key)?;
+                        self.output.write_all(b"\">[@")?;
+                        self.escape(key)?;
+                        self.output.write_all(b"]</cite>")?;
                     }
                 }
             }
-            NodeValue::Strong => {
+            NodeValue::FootnoteReference(ref label) => {
                 if entering {
-                    self.s += "<strong>";
-                } else {
-                    self.s += "</strong>";
+                    // Only the first reference rendered gets the id the back-link points to.
+                    let footnote = self.footnote(label);
+                    let anchor = footnote.anchor();
+                    write!(self.output, "<sup class=\"footnote-ref\"><a href=\"#fn{}\"", anchor)?;
+                    if self.footnote_ids.insert(strings::normalize_reference_label(label)) {
+                        write!(self.output, " id=\"fnref{}\"", anchor)?;
+                    }
+                    write!(self.output, " role=\"doc-noteref\">{}</a></sup>", footnote.number)?;
                 }
             }
-            NodeValue::Emph => {
+            NodeValue::Abbreviation(ref title) => {
                 if entering {
-                    self.s += "<em>";
+                    self.output.write_all(b"<abbr")?;
+                    if !title.is_empty() {
+                        self.output.write_all(b" title=\"")?;
+                        self.escape(title)?;
+                        self.output.write_all(b"\"")?;
+                    }
+                    self.output.write_all(b">")?;
                 } else {
-                    self.s += "</em>";
+                    self.output.write_all(b"</abbr>")?;
                 }
             }
-            NodeValue::Underline => {
+            NodeValue::InlineMacro { ref name, ref args } => {
                 if entering {
-                    self.s += "<u>";
-                } else {
-                    self.s += "</u>";
+                    match self.options.inline_macro_handlers.get(name) {
+                        Some(handler) => self.output.write_all(handler.render(args).as_bytes())?,
+                        None => {
+                            self.output.write_all(b"<!-- unknown macro: ")?;
+                            self.escape(name)?;
+                            self.output.write_all(b" -->")?;
+                        }
+                    }
                 }
             }
-            NodeValue::Strikethrough => {
+            NodeValue::HtmlInline(ref literal) => {
+                if entering {
+                    if self.options.ext_tagfilter() && tagfilter(literal) {
+                        self.output.write_all(b"&lt;")?;
+                        self.output.write_all(&literal.as_bytes()[1..])?;
+                    } else if self.options.normalize_html_breaks && is_bare_br(literal) {
+                        self.output.write_all(b"<br")?;
+                        self.close_void()?;
+                    } else {
+                        self.output.write_all(literal.as_bytes())?;
+                    }
+                }
+            }
+            NodeValue::Strong => {
                 if entering {
-                    self.s += "<del>";
+                    self.output.write_all(b"<strong>")?;
                 } else {
-                    self.s += "</del>";
+                    self.output.write_all(b"</strong>")?;
                 }
             }
-            NodeValue::Superscript => {
+            NodeValue::Emph => {
                 if entering {
-                    self.s += "<sup>";
+                    self.output.write_all(b"<em>")?;
                 } else {
-                    self.s += "</sup>";
+                    self.output.write_all(b"</em>")?;
                 }
             }
+            NodeValue::Underline => {
+                let options = self.options;
+                self.inline_element(
+                    entering,
+                    &options.underline_element,
+                    "u",
+                    &options.underline_class,
+                )?;
+            }
+            NodeValue::Strikethrough => {
+                let options = self.options;
+                self.inline_element(
+                    entering,
+                    &options.strikethrough_element,
+                    "del",
+                    &options.strikethrough_class,
+                )?;
+            }
+            NodeValue::Superscript => {
+                let options = self.options;
+                self.inline_element(
+                    entering,
+                    &options.superscript_element,
+                    "sup",
+                    &options.superscript_class,
+                )?;
+            }
+            NodeValue::Highlight => {
+                let options = self.options;
+                self.inline_element(
+                    entering,
+                    &options.highlight_element,
+                    "mark",
+                    &options.highlight_class,
+                )?;
+            }
             NodeValue::Link(ref nl) => {
                 if entering {
-                    self.s += "<a href=\"";
-                    self.escape_href(&nl.url);
+                    self.output.write_all(b"<a href=\"")?;
+                    self.link_href(node, &nl.url, &nl.title)?;
                     if !nl.title.is_empty() {
-                        self.s += "\" title=\"";
-                        self.escape(&nl.title);
+                        self.output.write_all(b"\" title=\"")?;
+                        self.escape(&nl.title)?;
                     }
-                    self.s += "\">";
+                    self.output.write_all(b"\">")?;
                 } else {
-                    self.s += "</a>";
+                    self.output.write_all(b"</a>")?;
                 }
             }
             NodeValue::Image(ref nl) => {
                 if entering {
-                    self.s += "<img src=\"";
-                    self.escape_href(&nl.url);
-                    self.s += "\" alt=\"";
-                    return true;
+                    self.output.write_all(b"<img src=\"")?;
+                    self.link_href(node, &nl.url, &nl.title)?;
+                    self.output.write_all(b"\" alt=\"")?;
+                    return Ok(true);
+                } else if self.options.image_figures && node.parent().map_or(false, is_figure) {
+                    self.output.write_all(b"\"")?;
+                    self.close_void()?;
+                    self.output.write_all(b"\n<figcaption>")?;
+                    self.escape(&nl.title)?;
+                    self.output.write_all(b"</figcaption>\n")?;
                 } else {
                     if !nl.title.is_empty() {
-                        self.s += "\" title=\"";
-                        self.escape(&nl.title);
+                        self.output.write_all(b"\" title=\"")?;
+                        self.escape(&nl.title)?;
                     }
-                    self.s += "\" />";
+                    self.output.write_all(b"\"")?;
+                    self.close_void()?;
                 }
             }
             NodeValue::Table(..) => {
                 if entering {
-                    self.cr();
-                    self.s += "<table>\n";
+                    self.cr()?;
+                    self.output.write_all(b"<table>\n")?;
                 } else {
                     if !node.last_child().unwrap().same_node(
                         node.first_child().unwrap(),
                     )
                     {
-                        self.s += "</tbody>";
+                        self.output.write_all(b"</tbody>")?;
                     }
-                    self.s += "</table>\n";
+                    self.output.write_all(b"</table>\n")?;
                 }
             }
             NodeValue::TableRow(header) => {
                 if entering {
-                    self.cr();
+                    self.cr()?;
                     if header {
-                        self.s += "<thead>";
-                        self.cr();
+                        self.output.write_all(b"<thead>")?;
+                        self.cr()?;
                     }
-                    self.s += "<tr>";
+                    self.output.write_all(b"<tr>")?;
                 } else {
-                    self.cr();
-                    self.s += "</tr>";
+                    self.cr()?;
+                    self.output.write_all(b"</tr>")?;
                     if header {
-                        self.cr();
-                        self.s += "</thead>";
-                        self.cr();
-                        self.s += "<tbody>";
+                        self.cr()?;
+                        self.output.write_all(b"</thead>")?;
+                        self.cr()?;
+                        self.output.write_all(b"<tbody>")?;
                     }
                 }
             }
@@ -440,11 +1243,11 @@ impl<'o> HtmlFormatter<'o> {
                 };
 
                 if entering {
-                    self.cr();
+                    self.cr()?;
                     if in_header {
-                        self.s += "<th";
+                        self.output.write_all(b"<th")?;
                     } else {
-                        self.s += "<td";
+                        self.output.write_all(b"<td")?;
                     }
 
                     let mut start = node.parent().unwrap().first_child().unwrap();
@@ -454,21 +1257,31 @@ impl<'o> HtmlFormatter<'o> {
                         start = start.next_sibling().unwrap();
                     }
 
-                    match alignments[i] {
-                        TableAlignment::Left => self.s += " align=\"left\"",
-                        TableAlignment::Right => self.s += " align=\"right\"",
-                        TableAlignment::Center => self.s += " align=\"center\"",
-                        TableAlignment::None => (),
+                    let align = match alignments[i] {
+                        TableAlignment::Left => Some("left"),
+                        TableAlignment::Right => Some("right"),
+                        TableAlignment::Center => Some("center"),
+                        TableAlignment::None => None,
+                    };
+                    if let Some(align) = align {
+                        match self.options.table_alignment_class_prefix {
+                            Some(ref prefix) => {
+                                self.output.write_all(b" class=\"")?;
+                                self.escape(prefix)?;
+                                write!(self.output, "{}\"", align)?;
+                            }
+                            None => write!(self.output, " align=\"{}\"", align)?,
+                        }
                     }
 
-                    self.s += ">";
+                    self.output.write_all(b">")?;
                 } else if in_header {
-                    self.s += "</th>";
+                    self.output.write_all(b"</th>")?;
                 } else {
-                    self.s += "</td>";
+                    self.output.write_all(b"</td>")?;
                 }
             }
         }
-        false
+        Ok(false)
     }
 }
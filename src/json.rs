@@ -0,0 +1,276 @@
+//! JSON output of the AST.
+
+use nodes::{AstNode, ListDelimType, ListType, NodeValue, TableAlignment};
+use parser::ComrakOptions;
+use std::fmt::Write;
+
+/// Formats an AST as JSON, for tools outside Rust.
+///
+/// Each node is an object whose `type` is the node's kind in snake case, as in cmark's XML
+/// output: `document`, `block_quote`, `list`, `item`, `code_block`, `html_block`, `paragraph`,
+/// `heading`, `thematic_break`, `text`, `softbreak`, `linebreak`, `code`, `html_inline`, `emph`,
+/// `strong`, `link`, `image` and so on for the extensions.  Blocks other than the document have a
+/// `sourcepos` of `[start_line, start_column, end_line, end_column]`.  The node's own data follows
+/// as further members:
+///
+/// * `list` and `item`: `list_type` (`"bullet"` or `"ordered"`), then `bullet_char`, or `start`
+///   and `delimiter` (`"period"` or `"paren"`); lists also have `tight`.
+/// * `code_block`: `fenced`, `info` and `literal`, and `classes`, `id` and `content_lines` where
+///   given.
+/// * `html_block`: `block_type`, the kind's number in the spec, and `literal`.
+/// * `heading`: `level` and `setext`.
+/// * `table`: `alignments`, each `"none"`, `"left"`, `"center"` or `"right"`.
+/// * `table_row`: `header`.
+/// * `text`, `code`, `html_inline` and `simple_paragraph`: `literal`.
+/// * `link` and `image`: `url` and `title`.
+/// * `task_item`: `checked`, and `children_all_checked` where known.
+///
+/// The extension nodes carry their data likewise: `cite` has `key`, `column_block` has `columns`,
+/// `reference_def` has `label`, `url` and `title`, the footnote nodes have `label`, and so on.
+/// Nodes given attributes by `ext_raw_attribute_passthrough` have an `attributes` object.  Every
+/// node ends with its `children`, an array which is empty for leaves.  The output is a single
+/// line.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, format_json, ComrakOptions};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let options = ComrakOptions::default();
+/// let root = parse_document(&arena, "Hi *there*\n", &options);
+/// assert_eq!(format_json(root, &options),
+///            "{\"type\":\"document\",\"children\":[\
+///             {\"type\":\"paragraph\",\"sourcepos\":[1,1,1,10],\"children\":[\
+///             {\"type\":\"text\",\"literal\":\"Hi \",\"children\":[]},\
+///             {\"type\":\"emph\",\"children\":[\
+///             {\"type\":\"text\",\"literal\":\"there\",\"children\":[]}]}]}]}\n");
+/// # }
+/// ```
+pub fn format_document<'a>(root: &'a AstNode<'a>, _options: &ComrakOptions) -> String {
+    let mut s = String::with_capacity(1024);
+    format_node(root, &mut s);
+    s.push('\n');
+    s
+}
+
+fn format_node<'a>(node: &'a AstNode<'a>, s: &mut String) {
+    {
+        let ast = node.data.borrow();
+        s.push_str("{\"type\":");
+        write_str(s, type_name(&ast.value));
+        if ast.start_line > 0 {
+            write!(
+                s,
+                ",\"sourcepos\":[{},{},{},{}]",
+                ast.start_line,
+                ast.start_column,
+                ast.end_line,
+                ast.end_column
+            ).unwrap();
+        }
+        format_value(&ast.value, s);
+
+        if !ast.attributes.is_empty() {
+            s.push_str(",\"attributes\":{");
+            for (i, (name, value)) in ast.attributes.iter().enumerate() {
+                if i > 0 {
+                    s.push(',');
+                }
+                write_str(s, name);
+                s.push(':');
+                write_str(s, value);
+            }
+            s.push('}');
+        }
+    }
+
+    s.push_str(",\"children\":[");
+    for (i, child) in node.children().enumerate() {
+        if i > 0 {
+            s.push(',');
+        }
+        format_node(child, s);
+    }
+    s.push_str("]}");
+}
+
+fn type_name(value: &NodeValue) -> &'static str {
+    match *value {
+        NodeValue::Document => "document",
+        NodeValue::BlockQuote => "block_quote",
+        NodeValue::List(..) => "list",
+        NodeValue::Item(..) => "item",
+        NodeValue::CodeBlock(..) => "code_block",
+        NodeValue::HtmlBlock(..) => "html_block",
+        NodeValue::Paragraph => "paragraph",
+        NodeValue::SimpleParagraph(..) => "simple_paragraph",
+        NodeValue::Heading(..) => "heading",
+        NodeValue::ThematicBreak => "thematic_break",
+        NodeValue::Table(..) => "table",
+        NodeValue::TableRow(..) => "table_row",
+        NodeValue::TableCell => "table_cell",
+        NodeValue::Container(..) => "container",
+        NodeValue::DetailsBlock { .. } => "details_block",
+        NodeValue::DetailsSummary => "details_summary",
+        NodeValue::ColumnBlock(..) => "column_block",
+        NodeValue::Column => "column",
+        NodeValue::Bibliography(..) => "bibliography",
+        NodeValue::BlockMacro { .. } => "block_macro",
+        NodeValue::ReferenceDef { .. } => "reference_def",
+        NodeValue::AbbreviationDef { .. } => "abbreviation_def",
+        NodeValue::FootnoteDefinition(..) => "footnote_definition",
+        NodeValue::Text(..) => "text",
+        NodeValue::SoftBreak => "softbreak",
+        NodeValue::LineBreak => "linebreak",
+        NodeValue::Code(..) => "code",
+        NodeValue::HtmlInline(..) => "html_inline",
+        NodeValue::Emph => "emph",
+        NodeValue::Strong => "strong",
+        NodeValue::Strikethrough => "strikethrough",
+        NodeValue::Superscript => "superscript",
+        NodeValue::Highlight => "highlight",
+        NodeValue::Link(..) => "link",
+        NodeValue::Image(..) => "image",
+        NodeValue::Underline => "underline",
+        NodeValue::Cite(..) => "cite",
+        NodeValue::Abbreviation(..) => "abbreviation",
+        NodeValue::FootnoteReference(..) => "footnote_reference",
+        NodeValue::InlineMacro { .. } => "inline_macro",
+        NodeValue::TaskItem { .. } => "task_item",
+    }
+}
+
+fn format_value(value: &NodeValue, s: &mut String) {
+    match *value {
+        NodeValue::List(ref nl) |
+        NodeValue::Item(ref nl) => {
+            if nl.list_type == ListType::Bullet {
+                s.push_str(",\"list_type\":\"bullet\",\"bullet_char\":");
+                write_str(s, &(nl.bullet_char as char).to_string());
+            } else {
+                let delimiter = if nl.delimiter == ListDelimType::Paren {
+                    "paren"
+                } else {
+                    "period"
+                };
+                write!(
+                    s,
+                    ",\"list_type\":\"ordered\",\"start\":{},\"delimiter\":\"{}\"",
+                    nl.start,
+                    delimiter
+                ).unwrap();
+            }
+            if let NodeValue::List(..) = *value {
+                write!(s, ",\"tight\":{}", nl.tight).unwrap();
+            }
+        }
+        NodeValue::CodeBlock(ref ncb) => {
+            write!(s, ",\"fenced\":{}", ncb.fenced).unwrap();
+            write_member(s, "info", &ncb.info);
+            write_member(s, "literal", &ncb.literal);
+            if !ncb.classes.is_empty() {
+                write_list(s, "classes", &ncb.classes);
+            }
+            if let Some(ref id) = ncb.id {
+                write_member(s, "id", id);
+            }
+            if let Some((first, last)) = ncb.content_lines {
+                write!(s, ",\"content_lines\":[{},{}]", first, last).unwrap();
+            }
+        }
+        NodeValue::HtmlBlock(ref nhb) => {
+            write!(s, ",\"block_type\":{}", nhb.block_type as u8).unwrap();
+            write_member(s, "literal", &nhb.literal);
+        }
+        NodeValue::Heading(ref nh) => {
+            write!(s, ",\"level\":{},\"setext\":{}", nh.level, nh.setext).unwrap();
+        }
+        NodeValue::Table(ref alignments) => {
+            let alignments = alignments
+                .iter()
+                .map(|a| match *a {
+                    TableAlignment::None => "none",
+                    TableAlignment::Left => "left",
+                    TableAlignment::Center => "center",
+                    TableAlignment::Right => "right",
+                })
+                .collect::<Vec<_>>();
+            write_list(s, "alignments", &alignments);
+        }
+        NodeValue::TableRow(header) => write!(s, ",\"header\":{}", header).unwrap(),
+        NodeValue::Container(ref nc) => {
+            write_member(s, "name", &nc.name);
+            write_member(s, "info", &nc.info);
+        }
+        NodeValue::DetailsBlock { ref summary } => write_member(s, "summary", summary),
+        NodeValue::ColumnBlock(columns) => write!(s, ",\"columns\":{}", columns).unwrap(),
+        NodeValue::Bibliography(ref keys) => write_list(s, "keys", keys),
+        NodeValue::BlockMacro { ref name, ref args } |
+        NodeValue::InlineMacro { ref name, ref args } => {
+            write_member(s, "name", name);
+            write_member(s, "args", args);
+        }
+        NodeValue::ReferenceDef { ref label, ref url, ref title } => {
+            write_member(s, "label", label);
+            write_member(s, "url", url);
+            write_member(s, "title", title);
+        }
+        NodeValue::AbbreviationDef { ref abbr, ref title } => {
+            write_member(s, "abbr", abbr);
+            write_member(s, "title", title);
+        }
+        NodeValue::SimpleParagraph(ref literal) |
+        NodeValue::Text(ref literal) |
+        NodeValue::Code(ref literal) |
+        NodeValue::HtmlInline(ref literal) => write_member(s, "literal", literal),
+        NodeValue::Link(ref nl) |
+        NodeValue::Image(ref nl) => {
+            write_member(s, "url", &nl.url);
+            write_member(s, "title", &nl.title);
+        }
+        NodeValue::Cite(ref key) => write_member(s, "key", key),
+        NodeValue::FootnoteDefinition(ref label) |
+        NodeValue::FootnoteReference(ref label) => write_member(s, "label", label),
+        NodeValue::Abbreviation(ref title) => write_member(s, "title", title),
+        NodeValue::TaskItem { checked, children_all_checked } => {
+            write!(s, ",\"checked\":{}", checked).unwrap();
+            if let Some(all_checked) = children_all_checked {
+                write!(s, ",\"children_all_checked\":{}", all_checked).unwrap();
+            }
+        }
+        _ => (),
+    }
+}
+
+fn write_member(s: &mut String, name: &str, value: &str) {
+    write!(s, ",\"{}\":", name).unwrap();
+    write_str(s, value);
+}
+
+fn write_list<T: AsRef<str>>(s: &mut String, name: &str, values: &[T]) {
+    write!(s, ",\"{}\":[", name).unwrap();
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            s.push(',');
+        }
+        write_str(s, value.as_ref());
+    }
+    s.push(']');
+}
+
+fn write_str(s: &mut String, value: &str) {
+    s.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            '\r' => s.push_str("\\r"),
+            '\t' => s.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(s, "\\u{:04x}", c as u32).unwrap(),
+            c => s.push(c),
+        }
+    }
+    s.push('"');
+}
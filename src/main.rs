@@ -20,31 +20,26 @@
 #![cfg_attr(rustbuild, feature(staged_api, rustc_private))]
 #![cfg_attr(rustbuild, unstable(feature = "rustc_private", issue = "27812"))]
 
-extern crate entities;
+extern crate comrak;
 #[macro_use]
 extern crate clap;
-extern crate unicode_categories;
 extern crate typed_arena;
-extern crate regex;
-#[macro_use]
-extern crate lazy_static;
-
-mod arena_tree;
-mod html;
-mod cm;
-mod parser;
-mod nodes;
-mod ctype;
-mod scanners;
-mod strings;
-mod entity;
-
-use std::collections::BTreeSet;
+
+use comrak::{parse_document, format_commonmark, format_html, format_json, AutolinkContext,
+             ComrakOptions, Extension};
+use comrak::nodes::AstNode;
+use std::env;
+use std::fmt::Write;
 use std::io::Read;
 use std::process;
 use typed_arena::Arena;
 
 fn main() {
+    let extensions = Extension::all()
+        .iter()
+        .map(|e| e.name())
+        .collect::<Vec<_>>();
+
     let matches = clap::App::new(crate_name!())
         .version(crate_version!())
         .author(crate_authors!())
@@ -60,11 +55,26 @@ fn main() {
         .arg(clap::Arg::with_name("hardbreaks").long("hardbreaks").help(
             "Treat newlines as hard line breaks",
         ))
+        .arg(
+            clap::Arg::with_name("hardbreaks-paragraph-only")
+                .long("hardbreaks-paragraph-only")
+                .help("Only treat newlines within paragraphs as hard line breaks"),
+        )
         .arg(
             clap::Arg::with_name("github-pre-lang")
                 .long("github-pre-lang")
                 .help("Use GitHub-style <pre lang> for code blocks"),
         )
+        .arg(
+            clap::Arg::with_name("line-numbers")
+                .long("line-numbers")
+                .help("Number the lines of code blocks"),
+        )
+        .arg(
+            clap::Arg::with_name("preserve-tabs")
+                .long("preserve-tabs")
+                .help("Preserve tabs in code blocks rather than expanding them"),
+        )
         .arg(
             clap::Arg::with_name("extension")
                 .short("e")
@@ -72,29 +82,52 @@ fn main() {
                 .takes_value(true)
                 .number_of_values(1)
                 .multiple(true)
-                .possible_values(
-                    &[
-                        "strikethrough",
-                        "tagfilter",
-                        "table",
-                        "autolink",
-                        "tasklist",
-                        "superscript",
-                    ],
-                )
+                .possible_values(&extensions)
                 .value_name("EXTENSION")
                 .help("Specify an extension name to use"),
         )
+        .arg(
+            clap::Arg::with_name("known-language")
+                .long("known-language")
+                .takes_value(true)
+                .number_of_values(1)
+                .multiple(true)
+                .value_name("LANGUAGE")
+                .help("Only output code block languages in this list"),
+        )
+        .arg(
+            clap::Arg::with_name("autolink-context")
+                .long("autolink-context")
+                .takes_value(true)
+                .number_of_values(1)
+                .multiple(true)
+                .possible_values(&["paragraph", "heading", "table-cell"])
+                .value_name("CONTEXT")
+                .help("Only autolink within these kinds of block"),
+        )
         .arg(
             clap::Arg::with_name("format")
                 .short("t")
                 .long("to")
                 .takes_value(true)
-                .possible_values(&["html", "commonmark"])
+                .possible_values(&["html", "commonmark", "json"])
                 .default_value("html")
                 .value_name("FORMAT")
                 .help("Specify output format"),
         )
+        .arg(
+            clap::Arg::with_name("dump-ast")
+                .long("dump-ast")
+                .help("Print the parsed AST as an indented tree instead of rendering"),
+        )
+        .arg(
+            clap::Arg::with_name("max-references")
+                .long("max-references")
+                .takes_value(true)
+                .value_name("COUNT")
+                .default_value("0")
+                .help("Limit the number of link reference definitions (0 = unlimited)"),
+        )
         .arg(
             clap::Arg::with_name("width")
                 .long("width")
@@ -103,28 +136,60 @@ fn main() {
                 .default_value("0")
                 .help("Specify wrap width (0 = nowrap)"),
         )
+        .after_help(
+            "Defaults may be set in the environment: COMRAK_EXTENSIONS as a comma-separated \
+             list, COMRAK_MAX_REFERENCES, COMRAK_WIDTH, and each flag as, for example, \
+             COMRAK_HARDBREAKS=1.  Command line options take precedence.",
+        )
         .get_matches();
 
-    let mut exts = matches.values_of("extension").map_or(
-        BTreeSet::new(),
-        |vals| vals.collect(),
-    );
-
-    let options = parser::ComrakOptions {
-        hardbreaks: matches.is_present("hardbreaks"),
-        github_pre_lang: matches.is_present("github-pre-lang"),
-        width: matches.value_of("width").unwrap_or("0").parse().unwrap_or(
-            0,
-        ),
-        ext_strikethrough: exts.remove("strikethrough"),
-        ext_tagfilter: exts.remove("tagfilter"),
-        ext_table: exts.remove("table"),
-        ext_autolink: exts.remove("autolink"),
-        ext_tasklist: exts.remove("tasklist"),
-        ext_superscript: exts.remove("superscript"),
+    // Extensions named on the command line are enabled along with those in the environment.
+    let mut extensions = env::var("COMRAK_EXTENSIONS").unwrap_or_default();
+    for name in matches.values_of("extension").into_iter().flat_map(|vals| vals) {
+        extensions.push(',');
+        extensions.push_str(name);
+    }
+    let vars = ComrakOptions::from_vars(|name| match name {
+        "COMRAK_EXTENSIONS" => Some(extensions.clone()),
+        _ => env::var(name).ok(),
+    });
+    let defaults = match vars {
+        Ok(defaults) => defaults,
+        Err(err) => {
+            eprintln!("COMRAK_EXTENSIONS: {}", err);
+            process::exit(1);
+        }
+    };
+
+    // Numeric options have a default value on the command line, so only count given ones.
+    let number = |name, default| if matches.occurrences_of(name) > 0 {
+        matches.value_of(name).unwrap().parse().unwrap_or(0)
+    } else {
+        default
     };
 
-    assert!(exts.is_empty());
+    let options = ComrakOptions {
+        hardbreaks: matches.is_present("hardbreaks") || defaults.hardbreaks,
+        hardbreaks_paragraph_only: matches.is_present("hardbreaks-paragraph-only") ||
+            defaults.hardbreaks_paragraph_only,
+        github_pre_lang: matches.is_present("github-pre-lang") || defaults.github_pre_lang,
+        code_line_numbers: matches.is_present("line-numbers") || defaults.code_line_numbers,
+        preserve_tabs: matches.is_present("preserve-tabs") || defaults.preserve_tabs,
+        known_languages: matches.values_of("known-language").map(|vals| {
+            vals.map(|v| v.to_string()).collect()
+        }),
+        width: number("width", defaults.width),
+        max_references: number("max-references", defaults.max_references),
+        autolink_contexts: matches.values_of("autolink-context").map(|vals| {
+            vals.map(|v| match v {
+                "paragraph" => AutolinkContext::Paragraph,
+                "heading" => AutolinkContext::Heading,
+                "table-cell" => AutolinkContext::TableCell,
+                _ => unreachable!(),
+            }).collect()
+        }),
+        ..defaults
+    };
 
     let mut s = String::with_capacity(2048);
 
@@ -141,11 +206,13 @@ fn main() {
     };
 
     let arena = Arena::new();
-    let root = parser::parse_document(&arena, &s, &options);
+    let root = parse_document(&arena, &s, &options);
 
     let formatter = match matches.value_of("format") {
-        Some("html") => html::format_document,
-        Some("commonmark") => cm::format_document,
+        _ if matches.is_present("dump-ast") => format_ast,
+        Some("html") => format_html,
+        Some("commonmark") => format_commonmark,
+        Some("json") => format_json,
         _ => panic!("unknown format"),
     };
 
@@ -153,3 +220,31 @@ fn main() {
 
     process::exit(0);
 }
+
+fn format_ast<'a>(root: &'a AstNode<'a>, _options: &ComrakOptions) -> String {
+    let mut s = String::new();
+    dump_ast(root, 0, &mut s);
+    s
+}
+
+fn dump_ast<'a>(node: &'a AstNode<'a>, depth: usize, output: &mut String) {
+    {
+        let ast = node.data.borrow();
+        write!(output, "{:width$}{:?}", "", ast.value, width = depth * 2).unwrap();
+        if ast.start_line > 0 {
+            write!(
+                output,
+                " [{}:{}-{}:{}]",
+                ast.start_line,
+                ast.start_column,
+                ast.end_line,
+                ast.end_column
+            ).unwrap();
+        }
+        output.push('\n');
+    }
+
+    for child in node.children() {
+        dump_ast(child, depth + 1, output);
+    }
+}
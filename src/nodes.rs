@@ -1,10 +1,11 @@
 //! The CommonMark AST.
 
 use arena_tree::Node;
-use std::cell::RefCell;
+use std::cell::{Ref, RefCell};
+use std::convert::TryFrom;
 
 /// The core AST node enum.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum NodeValue {
     /// The root of every CommonMark document.  Contains **blocks**.
     Document,
@@ -45,6 +46,11 @@ pub enum NodeValue {
     /// **Block**. A [paragraph](https://github.github.com/gfm/#paragraphs).  Contains **inlines**.
     Paragraph,
 
+    /// **Block**.  A paragraph consisting of nothing but plain text, which it holds in place of
+    /// a `Text` child.  Only produced with the `optimize_simple_paragraphs` option.  Has no
+    /// children.
+    SimpleParagraph(String),
+
     /// **Block**. A heading; may be an [ATX heading](https://github.github.com/gfm/#atx-headings)
     /// or a [setext heading](https://github.github.com/gfm/#setext-headings). Contains
     /// **inlines**.
@@ -65,6 +71,114 @@ pub enum NodeValue {
     /// **Block**.  A table cell.  Contains **inlines**.
     TableCell,
 
+    /// **Block**.  A fenced container, opened by a line of three or more colons followed by a
+    /// name, and closed by a line of at least as many colons.  Enabled with `ext_collapsible`
+    /// option for `details` containers, and with `ext_fenced_divs` for any class name or
+    /// attribute block, whose attributes are kept in the node's `attributes`.  Contains other
+    /// **blocks**.
+    ///
+    /// ``` md
+    /// :::details Click to expand
+    /// Hidden content.
+    /// :::
+    /// ```
+    Container(NodeContainer),
+
+    /// **Block**.  A collapsible section, opened by a line of three or more plus signs followed
+    /// by its summary, and closed by a line of plus signs.  Enabled with `ext_details` option.
+    /// The `summary` is the text of the summary as written.  Contains a `DetailsSummary`
+    /// followed by other **blocks**.
+    ///
+    /// ``` md
+    /// +++ Click to *expand*
+    /// Hidden content.
+    /// +++
+    /// ```
+    DetailsBlock {
+        /// The summary text, before inline parsing.
+        summary: String,
+    },
+
+    /// **Block**.  The summary of a details block; always its first child.  Contains **inlines**.
+    DetailsSummary,
+
+    /// **Block**.  A set of side-by-side columns, written as rows of cells separated by `|||`.
+    /// Enabled with `ext_column_blocks` option.  The `usize` is the number of columns.  Contains
+    /// only `Column`s.
+    ///
+    /// ``` md
+    /// ||| # Left     ||| # Right     |||
+    /// ||| Some text. ||| More text.  |||
+    /// ```
+    ColumnBlock(usize),
+
+    /// **Block**.  A single column of a column block.  Its content is parsed as a document of its
+    /// own.  Contains other **blocks**.
+    Column,
+
+    /// **Block**.  The list of cited works, appended to the document when `ext_citations` option
+    /// is enabled and a `bibliography` is given.  Holds the keys of the cited works found in the
+    /// bibliography, in order of first citation.  Has no children.
+    Bibliography(Vec<String>),
+
+    /// **Block**.  A block macro, written as `@@` and the macro's name on a line of its own,
+    /// optionally followed by arguments.  Enabled with `ext_block_macros` option.  Has no
+    /// children.
+    ///
+    /// ``` md
+    /// @@include-snippet path/to/code.rs:10-20
+    /// ```
+    BlockMacro {
+        /// The name of the macro.
+        name: String,
+
+        /// The macro's arguments, with surrounding whitespace removed.
+        args: String,
+    },
+
+    /// **Block**.  A [link reference definition](https://github.github.com/gfm/#link-reference-definitions),
+    /// kept in the tree when the `keep_reference_definitions` option is set.  Has no children.
+    ///
+    /// ``` md
+    /// [foo]: /url "title"
+    /// ```
+    ReferenceDef {
+        /// The label, as written between the brackets.
+        label: String,
+
+        /// The destination URL, with any angle brackets and escapes removed.
+        url: String,
+
+        /// The title, with its delimiters and escapes removed; empty if there is none.
+        title: String,
+    },
+
+    /// **Block**.  An abbreviation definition, kept in the tree when the `ext_abbreviations`
+    /// option is set.  Has no children.
+    ///
+    /// ``` md
+    /// *[HTML]: HyperText Markup Language
+    /// ```
+    AbbreviationDef {
+        /// The abbreviation, as written between the brackets.
+        abbr: String,
+
+        /// The expansion of the abbreviation; may be empty.
+        title: String,
+    },
+
+    /// **Block**.  A footnote definition, enabled with `ext_footnotes` option.  The `String` is
+    /// the label, as written between the brackets after the caret.  After parsing, the
+    /// definitions are the last children of the document, in the order the footnotes are first
+    /// referenced and then those never referenced.  Contains other **blocks**.
+    ///
+    /// ``` md
+    /// [^note]: The footnote's text.
+    ///
+    ///     Further paragraphs are indented.
+    /// ```
+    FootnoteDefinition(String),
+
     /// **Inline**.  [Textual content](https://github.github.com/gfm/#textual-content).  All text
     /// in a document will be contained in a `Text` node.
     Text(String),
@@ -97,6 +211,9 @@ pub enum NodeValue {
     /// **Inline**.  Superscript.  Enabled with `ext_superscript` option.
     Superscript,
 
+    /// **Inline**.  Highlighted text.  Enabled with `ext_highlight` option.
+    Highlight,
+
     /// **Inline**.  A [link](https://github.github.com/gfm/#links) to some URL, with possible
     /// title.
     Link(NodeLink),
@@ -106,10 +223,45 @@ pub enum NodeValue {
 
     /// **Inline**.  Underline
     Underline,
+
+    /// **Inline**.  A citation of a work by its key, written `[@key]`.  Enabled with
+    /// `ext_citations` option.
+    Cite(String),
+
+    /// **Inline**.  An occurrence of an abbreviation defined elsewhere in the document, holding
+    /// its expansion.  Contains the abbreviation as a single `Text` node.  Enabled with
+    /// `ext_abbreviations` option.
+    Abbreviation(String),
+
+    /// **Inline**.  A reference to a footnote by its label, written `[^label]`.  Enabled with
+    /// `ext_footnotes` option.
+    FootnoteReference(String),
+
+    /// **Inline**.  An inline macro, written `{name:args}`.  Enabled with `ext_inline_macros`
+    /// option.
+    InlineMacro {
+        /// The name of the macro.
+        name: String,
+
+        /// The macro's arguments, as written.
+        args: String,
+    },
+
+    /// **Inline**.  The checkbox of a
+    /// [task list item](https://github.github.com/gfm/#task-list-items-extension-).  Enabled with
+    /// `ext_tasklist` option; always the first child of the paragraph which opens a list item.
+    TaskItem {
+        /// Whether the task is checked.
+        checked: bool,
+
+        /// With `ext_tasklist_nesting` option, whether all task items in the item's sub-lists are
+        /// checked, or `None` if there are none.  Always `None` otherwise.
+        children_all_checked: Option<bool>,
+    },
 }
 
 /// Alignment of a single table cell.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum TableAlignment {
     /// Cell content is unaligned.
     None,
@@ -125,7 +277,7 @@ pub enum TableAlignment {
 }
 
 /// The details of a link's destination, or an image's source.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct NodeLink {
     /// The URL for the link destination or image source.
     pub url: String,
@@ -138,7 +290,7 @@ pub struct NodeLink {
 }
 
 /// The metadata of a list; the kind of list, the delimiter used and so on.
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub struct NodeList {
     /// The kind of list (bullet (unordered) or ordered).
     pub list_type: ListType,
@@ -196,7 +348,7 @@ impl Default for ListDelimType {
 }
 
 /// The metadata and data of a code block (fenced or indented).
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct NodeCodeBlock {
     /// Whether the code block is fenced.
     pub fenced: bool,
@@ -211,17 +363,29 @@ pub struct NodeCodeBlock {
     pub fence_offset: usize,
 
     /// For fenced code blocks, the [info string](https://github.github.com/gfm/#info-string) after
-    /// the opening fence, if any.
+    /// the opening fence, if any.  With `ext_code_block_attrs` option, if the info string is a
+    /// set of attributes, this is the language they name, if any.
     pub info: String,
 
+    /// With `ext_code_block_attrs` option, the CSS classes given in the info string other than
+    /// the language.
+    pub classes: Vec<String>,
+
+    /// With `ext_code_block_attrs` option, the ID given in the info string, if any.
+    pub id: Option<String>,
+
     /// The literal contents of the code block.  As the contents are not interpreted as Markdown at
     /// all, they are contained within this structure, rather than inserted into a child inline of
     /// any kind.
     pub literal: String,
+
+    /// The first and last lines of the input document the literal contents were taken from,
+    /// excluding any fences; `None` if the code block is empty.
+    pub content_lines: Option<(u32, u32)>,
 }
 
 /// The metadata of a heading.
-#[derive(Default, Debug, Clone, Copy)]
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
 pub struct NodeHeading {
     /// The level of the header; from 1 to 6 for ATX headings, 1 or 2 for setext headings.
     pub level: u32,
@@ -231,16 +395,75 @@ pub struct NodeHeading {
 }
 
 /// The metadata of an included HTML block.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct NodeHtmlBlock {
-    #[doc(hidden)]
-    pub block_type: u8,
+    /// Which of the kinds of HTML block this is, which determines how it ends.
+    pub block_type: HtmlBlockType,
 
     /// The literal contents of the HTML block.  Per NodeCodeBlock, the content is included here
     /// rather than in any inline.
     pub literal: String,
 }
 
+/// The kinds of [HTML block](https://github.github.com/gfm/#html-blocks), numbered as in the spec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HtmlBlockType {
+    /// Starts with `<script`, `<pre` or `<style`, and ends with the matching end tag.
+    ScriptPreStyle = 1,
+
+    /// Starts with `<!--` and ends with `-->`.
+    Comment = 2,
+
+    /// Starts with `<?` and ends with `?>`.
+    ProcessingInstruction = 3,
+
+    /// Starts with `<!` and a letter, and ends with `>`.
+    Declaration = 4,
+
+    /// Starts with `<![CDATA[` and ends with `]]>`.
+    CdataSection = 5,
+
+    /// Starts with a known block-level tag, and ends with a blank line.
+    BlockLevelTag = 6,
+
+    /// Starts with any other complete open or closing tag alone on its line, and ends with a
+    /// blank line.  Cannot interrupt a paragraph.
+    CompleteTag = 7,
+}
+
+impl TryFrom<u8> for HtmlBlockType {
+    type Error = u8;
+
+    /// Converts an HTML block type's number in the spec to the type, or returns the number back
+    /// if it is not one.
+    fn try_from(n: u8) -> Result<HtmlBlockType, u8> {
+        match n {
+            1 => Ok(HtmlBlockType::ScriptPreStyle),
+            2 => Ok(HtmlBlockType::Comment),
+            3 => Ok(HtmlBlockType::ProcessingInstruction),
+            4 => Ok(HtmlBlockType::Declaration),
+            5 => Ok(HtmlBlockType::CdataSection),
+            6 => Ok(HtmlBlockType::BlockLevelTag),
+            7 => Ok(HtmlBlockType::CompleteTag),
+            _ => Err(n),
+        }
+    }
+}
+
+/// The metadata of a fenced container.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct NodeContainer {
+    /// The number of colons in the opening fence.
+    pub fence_length: usize,
+
+    /// The name following the opening fence, e.g. `details` or a fenced div's class.  Empty for a
+    /// fenced div opened with an attribute block.
+    pub name: String,
+
+    /// Any text following the name on the opening fence line of a `details` container, which is
+    /// its summary.
+    pub info: String,
+}
 
 impl NodeValue {
     /// Indicates whether this node is a block node or inline node.
@@ -253,11 +476,22 @@ impl NodeValue {
             NodeValue::CodeBlock(..) |
             NodeValue::HtmlBlock(..) |
             NodeValue::Paragraph |
+            NodeValue::SimpleParagraph(..) |
             NodeValue::Heading(..) |
             NodeValue::ThematicBreak |
             NodeValue::Table(..) |
             NodeValue::TableRow(..) |
-            NodeValue::TableCell => true,
+            NodeValue::TableCell |
+            NodeValue::Container(..) |
+            NodeValue::DetailsBlock { .. } |
+            NodeValue::DetailsSummary |
+            NodeValue::ColumnBlock(..) |
+            NodeValue::Column |
+            NodeValue::Bibliography(..) |
+            NodeValue::BlockMacro { .. } |
+            NodeValue::ReferenceDef { .. } |
+            NodeValue::AbbreviationDef { .. } |
+            NodeValue::FootnoteDefinition(..) => true,
             _ => false,
         }
     }
@@ -267,7 +501,8 @@ impl NodeValue {
         match *self {
             NodeValue::Paragraph |
             NodeValue::Heading(..) |
-            NodeValue::CodeBlock(..) => true,
+            NodeValue::CodeBlock(..) |
+            NodeValue::ColumnBlock(..) => true,
             _ => false,
         }
     }
@@ -277,7 +512,8 @@ impl NodeValue {
         match *self {
             NodeValue::Paragraph |
             NodeValue::Heading(..) |
-            NodeValue::TableCell => true,
+            NodeValue::TableCell |
+            NodeValue::DetailsSummary => true,
             _ => false,
         }
     }
@@ -324,8 +560,13 @@ pub struct Ast {
     /// The column in the input document the node ends at.
     pub end_column: usize,
 
-    #[doc(hidden)]
+    /// The raw text content of a block which contains inlines, as it was before inline parsing.
+    /// When parsing with `parse_document_raw`, this is the only representation of the text.
     pub content: String,
+
+    /// Additional HTML attributes for the node's element, as name and value pairs.  These are
+    /// given with the `ext_raw_attribute_passthrough` option.
+    pub attributes: Vec<(String, String)>,
     #[doc(hidden)]
     pub open: bool,
     #[doc(hidden)]
@@ -337,6 +578,7 @@ pub fn make_block(value: NodeValue, start_line: u32, start_column: usize) -> Ast
     Ast {
         value: value,
         content: String::new(),
+        attributes: vec![],
         start_line: start_line,
         start_column: start_column,
         end_line: start_line,
@@ -367,7 +609,11 @@ pub fn can_contain_type<'a>(node: &'a AstNode<'a>, child: &NodeValue) -> bool {
     match node.data.borrow().value {
         NodeValue::Document |
         NodeValue::BlockQuote |
-        NodeValue::Item(..) => {
+        NodeValue::Item(..) |
+        NodeValue::Container(..) |
+        NodeValue::DetailsBlock { .. } |
+        NodeValue::Column |
+        NodeValue::FootnoteDefinition(..) => {
             child.block() &&
                 match *child {
                     NodeValue::Item(..) => false,
@@ -384,6 +630,7 @@ pub fn can_contain_type<'a>(node: &'a AstNode<'a>, child: &NodeValue) -> bool {
 
         NodeValue::Paragraph |
         NodeValue::Heading(..) |
+        NodeValue::DetailsSummary |
         NodeValue::Emph |
         NodeValue::Strong |
         NodeValue::Link(..) |
@@ -396,6 +643,13 @@ pub fn can_contain_type<'a>(node: &'a AstNode<'a>, child: &NodeValue) -> bool {
             }
         }
 
+        NodeValue::ColumnBlock(..) => {
+            match *child {
+                NodeValue::Column => true,
+                _ => false,
+            }
+        }
+
         NodeValue::TableRow(..) => {
             match *child {
                 NodeValue::TableCell => true,
@@ -448,3 +702,401 @@ pub fn containing_block<'a>(node: &'a AstNode<'a>) -> Option<&'a AstNode<'a>> {
     }
     None
 }
+
+/// Returns the first node in the tree under `root`, including `root` itself and in document
+/// order, for which `predicate` returns `true`.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, ComrakOptions};
+/// # use comrak::nodes::{find_first, NodeValue};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let root = parse_document(&arena, "Intro.\n\n## Usage\n\n## Licence\n", &ComrakOptions::default());
+///
+/// let usage = find_first(root, |node| match node.data.borrow().value {
+///     NodeValue::Text(ref text) => text == "Usage",
+///     _ => false,
+/// }).and_then(|text| text.parent()).unwrap();
+/// assert_eq!(usage.data.borrow().start_line, 3);
+/// # }
+/// ```
+pub fn find_first<'a, P>(root: &'a AstNode<'a>, mut predicate: P) -> Option<&'a AstNode<'a>>
+where
+    P: FnMut(&'a AstNode<'a>) -> bool,
+{
+    root.descendants().find(|node| predicate(node))
+}
+
+/// Returns all nodes in the tree under `root`, including `root` itself and in document order, for
+/// which `predicate` returns `true`.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, ComrakOptions};
+/// # use comrak::nodes::{find_all, NodeValue};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let root = parse_document(
+///     &arena,
+///     "See [the docs](/docs), [GitHub](https://github.com) and [crates.io](https://crates.io).\n",
+///     &ComrakOptions::default());
+///
+/// let external = find_all(root, |node| match node.data.borrow().value {
+///     NodeValue::Link(ref link) => link.url.starts_with("https://"),
+///     _ => false,
+/// });
+/// assert_eq!(external.len(), 2);
+/// # }
+/// ```
+pub fn find_all<'a, P>(root: &'a AstNode<'a>, mut predicate: P) -> Vec<&'a AstNode<'a>>
+where
+    P: FnMut(&'a AstNode<'a>) -> bool,
+{
+    root.descendants().filter(|node| predicate(node)).collect()
+}
+
+/// Iterates over all `Heading` nodes under `root`, in document order.
+pub fn headings<'a>(root: &'a AstNode<'a>) -> impl Iterator<Item = &'a AstNode<'a>> {
+    root.descendants().filter(|node| match node.data.borrow().value {
+        NodeValue::Heading(..) => true,
+        _ => false,
+    })
+}
+
+/// Returns the section begun by `heading`: the heading itself and its following siblings, up to
+/// but not including the next heading of the same or a higher level.  Returns an empty vector if
+/// `heading` is not a `Heading`.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, ComrakOptions};
+/// # use comrak::nodes::{headings, section};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let root = parse_document(
+///     &arena,
+///     "# One\n\nIntro.\n\n## Detail\n\nMore.\n\n# Two\n",
+///     &ComrakOptions::default());
+///
+/// let one = headings(root).next().unwrap();
+/// assert_eq!(section(one).len(), 4);
+/// # }
+/// ```
+pub fn section<'a>(heading: &'a AstNode<'a>) -> Vec<&'a AstNode<'a>> {
+    let level = match node_heading_level(heading) {
+        Some(level) => level,
+        None => return vec![],
+    };
+
+    let mut nodes = vec![heading];
+    nodes.extend(heading.following_siblings().skip(1).take_while(|node| {
+        node_heading_level(node).map_or(true, |l| l > level)
+    }));
+    nodes
+}
+
+/// Iterates over all `Link` nodes under `root`, in document order.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, ComrakOptions};
+/// # use comrak::nodes::{links, NodeValue};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let root = parse_document(
+///     &arena,
+///     "# [Home](/)\n\n* [Rust](https://www.rust-lang.org)\n* ![logo](/logo.png)\n",
+///     &ComrakOptions::default());
+///
+/// let external = links(root)
+///     .filter_map(|node| match node.data.borrow().value {
+///         NodeValue::Link(ref link) if link.url.starts_with("http") => Some(link.url.clone()),
+///         _ => None,
+///     })
+///     .collect::<Vec<_>>();
+/// assert_eq!(external, vec!["https://www.rust-lang.org".to_string()]);
+/// # }
+/// ```
+pub fn links<'a>(root: &'a AstNode<'a>) -> impl Iterator<Item = &'a AstNode<'a>> {
+    root.descendants().filter(|node| match node.data.borrow().value {
+        NodeValue::Link(..) => true,
+        _ => false,
+    })
+}
+
+/// Iterates over all `Image` nodes under `root`, in document order.
+pub fn images<'a>(root: &'a AstNode<'a>) -> impl Iterator<Item = &'a AstNode<'a>> {
+    root.descendants().filter(|node| match node.data.borrow().value {
+        NodeValue::Image(..) => true,
+        _ => false,
+    })
+}
+
+/// Returns the URL and alt text of the first `Image` under `root`, such as for a preview card.
+/// The alt text is the image description as plain text.  Images written as raw HTML are not
+/// found, and neither is anything within code or HTML blocks, which have no children.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, ComrakOptions};
+/// # use comrak::nodes::first_image;
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let root = parse_document(
+///     &arena,
+///     "    ![code](/no.png)\n\nSee ![a *cat*](/cat.png) and ![dog](/dog.png).\n",
+///     &ComrakOptions::default());
+///
+/// assert_eq!(first_image(root), Some(("/cat.png".to_string(), "a cat".to_string())));
+/// # }
+/// ```
+pub fn first_image<'a>(root: &'a AstNode<'a>) -> Option<(String, String)> {
+    let image = images(root).next()?;
+    let url = match image.data.borrow().value {
+        NodeValue::Image(ref link) => link.url.clone(),
+        _ => unreachable!(),
+    };
+    let mut alt = String::new();
+    collect_text(image, &mut alt);
+    Some((url, alt))
+}
+
+/// Counts of the words and the main kinds of node in a document, as computed by
+/// `document_statistics`.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct DocumentStats {
+    /// The number of whitespace-separated words in the document's text.
+    pub word_count: usize,
+
+    /// The number of paragraphs.
+    pub paragraph_count: usize,
+
+    /// The number of headings.
+    pub heading_count: usize,
+
+    /// The number of code blocks, whether fenced or indented.
+    pub code_block_count: usize,
+
+    /// The number of links.
+    pub link_count: usize,
+
+    /// The number of images.
+    pub image_count: usize,
+
+    /// An estimate of the minutes needed to read the document, at 200 words per minute.
+    pub estimated_reading_time_minutes: f32,
+}
+
+/// Counts the words and the main kinds of node in the document under `root`.  Words are counted
+/// by splitting each text node on whitespace, so code and raw HTML are not included.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{document_statistics, parse_document, ComrakOptions};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let root = parse_document(
+///     &arena,
+///     "# Title\n\nA [short](/) paragraph.\n\n    code\n",
+///     &ComrakOptions::default());
+///
+/// let stats = document_statistics(root);
+/// assert_eq!(stats.word_count, 4);
+/// assert_eq!(stats.paragraph_count, 1);
+/// assert_eq!(stats.heading_count, 1);
+/// assert_eq!(stats.code_block_count, 1);
+/// assert_eq!(stats.link_count, 1);
+/// assert_eq!(stats.estimated_reading_time_minutes, 0.02);
+/// # }
+/// ```
+pub fn document_statistics<'a>(root: &'a AstNode<'a>) -> DocumentStats {
+    let mut stats = DocumentStats::default();
+    for node in root.descendants() {
+        match node.data.borrow().value {
+            NodeValue::Text(ref literal) => stats.word_count += literal.split_whitespace().count(),
+            NodeValue::SimpleParagraph(ref literal) => {
+                stats.paragraph_count += 1;
+                stats.word_count += literal.split_whitespace().count();
+            }
+            NodeValue::Paragraph => stats.paragraph_count += 1,
+            NodeValue::Heading(..) => stats.heading_count += 1,
+            NodeValue::CodeBlock(..) => stats.code_block_count += 1,
+            NodeValue::Link(..) => stats.link_count += 1,
+            NodeValue::Image(..) => stats.image_count += 1,
+            _ => (),
+        }
+    }
+    stats.estimated_reading_time_minutes = stats.word_count as f32 / 200.0;
+    stats
+}
+
+/// Returns the literal contents of an `HtmlBlock` node, or `None` for any other node.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, ComrakOptions};
+/// # use comrak::nodes::node_html_block_literal;
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let root = parse_document(&arena, "<div>\nhi\n</div>\n", &ComrakOptions::default());
+/// let block = root.first_child().unwrap();
+/// assert_eq!(&*node_html_block_literal(block).unwrap(), "<div>\nhi\n</div>\n");
+/// assert!(node_html_block_literal(root).is_none());
+/// # }
+/// ```
+pub fn node_html_block_literal<'a>(node: &'a AstNode<'a>) -> Option<Ref<'a, str>> {
+    Ref::filter_map(node.data.borrow(), |ast| match ast.value {
+        NodeValue::HtmlBlock(ref nhb) => Some(nhb.literal.as_str()),
+        _ => None,
+    }).ok()
+}
+
+/// Returns the literal contents of a `CodeBlock` node, or `None` for any other node.
+pub fn node_code_block_literal<'a>(node: &'a AstNode<'a>) -> Option<Ref<'a, str>> {
+    Ref::filter_map(node.data.borrow(), |ast| match ast.value {
+        NodeValue::CodeBlock(ref ncb) => Some(ncb.literal.as_str()),
+        _ => None,
+    }).ok()
+}
+
+/// Returns the info string of a `CodeBlock` node, or `None` for any other node.
+pub fn node_code_block_info<'a>(node: &'a AstNode<'a>) -> Option<Ref<'a, str>> {
+    Ref::filter_map(node.data.borrow(), |ast| match ast.value {
+        NodeValue::CodeBlock(ref ncb) => Some(ncb.info.as_str()),
+        _ => None,
+    }).ok()
+}
+
+/// Returns the kind of an `HtmlBlock` node, or `None` for any other node.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, ComrakOptions};
+/// # use comrak::nodes::{node_html_block_type, HtmlBlockType};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let root = parse_document(&arena, "<!-- hi -->\n", &ComrakOptions::default());
+/// let block = root.first_child().unwrap();
+/// assert_eq!(node_html_block_type(block), Some(HtmlBlockType::Comment));
+/// assert!(node_html_block_type(root).is_none());
+/// # }
+/// ```
+pub fn node_html_block_type<'a>(node: &'a AstNode<'a>) -> Option<HtmlBlockType> {
+    match node.data.borrow().value {
+        NodeValue::HtmlBlock(ref nhb) => Some(nhb.block_type),
+        _ => None,
+    }
+}
+
+/// Returns the level of a `Heading` node, or `None` for any other node.
+pub fn node_heading_level<'a>(node: &'a AstNode<'a>) -> Option<u32> {
+    match node.data.borrow().value {
+        NodeValue::Heading(ref nh) => Some(nh.level),
+        _ => None,
+    }
+}
+
+/// Returns the URL of a `Link` or `Image` node, or `None` for any other node.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, ComrakOptions};
+/// # use comrak::nodes::{node_link_url, node_link_title};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let root = parse_document(&arena, "[x](/url \"title\")\n", &ComrakOptions::default());
+/// let link = root.first_child().unwrap().first_child().unwrap();
+/// assert_eq!(&*node_link_url(link).unwrap(), "/url");
+/// assert_eq!(&*node_link_title(link).unwrap(), "title");
+/// # }
+/// ```
+pub fn node_link_url<'a>(node: &'a AstNode<'a>) -> Option<Ref<'a, str>> {
+    Ref::filter_map(node.data.borrow(), |ast| match ast.value {
+        NodeValue::Link(ref nl) |
+        NodeValue::Image(ref nl) => Some(nl.url.as_str()),
+        _ => None,
+    }).ok()
+}
+
+/// Returns the title of a `Link` or `Image` node, or `None` for any other node.
+pub fn node_link_title<'a>(node: &'a AstNode<'a>) -> Option<Ref<'a, str>> {
+    Ref::filter_map(node.data.borrow(), |ast| match ast.value {
+        NodeValue::Link(ref nl) |
+        NodeValue::Image(ref nl) => Some(nl.title.as_str()),
+        _ => None,
+    }).ok()
+}
+
+/// Returns the literal contents of a `Text`, `Code` or `HtmlInline` node, or `None` for any other
+/// node.
+pub fn node_literal<'a>(node: &'a AstNode<'a>) -> Option<Ref<'a, str>> {
+    Ref::filter_map(node.data.borrow(), |ast| match ast.value {
+        NodeValue::Text(ref literal) |
+        NodeValue::Code(ref literal) |
+        NodeValue::HtmlInline(ref literal) => Some(literal.as_str()),
+        _ => None,
+    }).ok()
+}
+
+/// Extracts the cells of a `Table` node as rows of plain text, header row first.  Inline
+/// formatting is dropped, keeping only the text; line breaks become spaces.  Returns no rows for
+/// any other node.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, ComrakOptions};
+/// # use comrak::nodes::table_to_rows;
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let mut options = ComrakOptions::default();
+/// options.ext_table = true;
+/// let root = parse_document(&arena, "| a | b |\n|---|---|\n| *c* | `d` |\n", &options);
+///
+/// let rows = table_to_rows(root.first_child().unwrap());
+/// let csv = rows.iter().map(|row| row.join(",")).collect::<Vec<_>>().join("\n");
+/// assert_eq!(csv, "a,b\nc,d");
+/// # }
+/// ```
+pub fn table_to_rows<'a>(table: &'a AstNode<'a>) -> Vec<Vec<String>> {
+    if !matches!(table.data.borrow().value, NodeValue::Table(..)) {
+        return vec![];
+    }
+
+    table
+        .children()
+        .map(|row| {
+            row.children()
+                .map(|cell| {
+                    let mut text = String::new();
+                    collect_text(cell, &mut text);
+                    text
+                })
+                .collect()
+        })
+        .collect()
+}
+
+pub(crate) fn collect_text<'a>(node: &'a AstNode<'a>, output: &mut String) {
+    match node.data.borrow().value {
+        NodeValue::Text(ref literal) |
+        NodeValue::Code(ref literal) => *output += literal,
+        NodeValue::LineBreak |
+        NodeValue::SoftBreak => output.push(' '),
+        _ => {
+            for child in node.children() {
+                collect_text(child, output);
+            }
+        }
+    }
+}
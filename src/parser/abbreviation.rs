@@ -0,0 +1,61 @@
+use nodes::{NodeValue, AstNode};
+use parser::inlines::make_inline;
+use std::collections::HashMap;
+use typed_arena::Arena;
+
+pub fn process_abbreviations<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    node: &'a AstNode<'a>,
+    contents: &mut String,
+    abbreviations: &HashMap<String, String>,
+) {
+    let (start, abbr, title) = match find_abbreviation(contents, abbreviations) {
+        Some(found) => found,
+        None => return,
+    };
+    let end = start + abbr.len();
+
+    let inl = make_inline(arena, NodeValue::Abbreviation(title.to_string()));
+    inl.append(make_inline(arena, NodeValue::Text(abbr.to_string())));
+
+    node.insert_after(inl);
+    if end < contents.len() {
+        let remain = contents[end..].to_string();
+        inl.insert_after(make_inline(arena, NodeValue::Text(remain)));
+    }
+    contents.truncate(start);
+}
+
+// Finds the first whole-word occurrence of any abbreviation, preferring the longest of those
+// starting at the same place.
+fn find_abbreviation<'h>(
+    contents: &str,
+    abbreviations: &'h HashMap<String, String>,
+) -> Option<(usize, &'h str, &'h str)> {
+    let mut found: Option<(usize, &'h str, &'h str)> = None;
+
+    for (abbr, title) in abbreviations {
+        let start = match contents.match_indices(abbr.as_str()).map(|(i, _)| i).find(|&i| {
+            is_whole_word(contents, i, i + abbr.len())
+        }) {
+            Some(start) => start,
+            None => continue,
+        };
+
+        let better = match found {
+            Some((s, a, _)) => start < s || (start == s && abbr.len() > a.len()),
+            None => true,
+        };
+        if better {
+            found = Some((start, abbr, title));
+        }
+    }
+
+    found
+}
+
+fn is_whole_word(contents: &str, start: usize, end: usize) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    !contents[..start].chars().next_back().map_or(false, is_word) &&
+        !contents[end..].chars().next().map_or(false, is_word)
+}
@@ -1,7 +1,8 @@
-use ctype::{isspace, isalpha, isalnum};
+use ctype::{isspace, isalpha, isalnum, isdigit};
 use nodes::{NodeValue, NodeLink, AstNode};
 use parser::inlines::make_inline;
 use regex::{Regex, Captures};
+use std::collections::HashSet;
 use typed_arena::Arena;
 use unicode_categories::UnicodeCategories;
 
@@ -9,6 +10,7 @@ pub fn process_autolinks<'a>(
     arena: &'a Arena<AstNode<'a>>,
     node: &'a AstNode<'a>,
     contents: &mut String,
+    tlds: Option<&HashSet<String>>,
 ) {
     let len = contents.len();
     let mut i = 0;
@@ -19,19 +21,19 @@ pub fn process_autolinks<'a>(
         while i < len {
             match contents.as_bytes()[i] {
                 b':' => {
-                    post_org = url_match(arena, contents, i);
+                    post_org = url_match(arena, contents, i, tlds);
                     if post_org.is_some() {
                         break;
                     }
                 }
                 b'w' => {
-                    post_org = www_match(arena, contents, i);
+                    post_org = www_match(arena, contents, i, tlds);
                     if post_org.is_some() {
                         break;
                     }
                 }
                 b'@' => {
-                    post_org = email_match(arena, contents, i);
+                    post_org = email_match(arena, contents, i, tlds);
                     if post_org.is_some() {
                         break;
                     }
@@ -59,6 +61,7 @@ fn www_match<'a>(
     arena: &'a Arena<AstNode<'a>>,
     contents: &str,
     i: usize,
+    tlds: Option<&HashSet<String>>,
 ) -> Option<(&'a AstNode<'a>, usize, usize)> {
     lazy_static! {
         static ref WWW_DELIMS: [bool; 256] = {
@@ -85,6 +88,10 @@ fn www_match<'a>(
         Some(link_end) => link_end,
     };
 
+    if !tld_allowed(&contents[i..i + link_end], tlds) {
+        return None;
+    }
+
     while i + link_end < contents.len() && !isspace(contents.as_bytes()[i + link_end]) {
         link_end += 1;
     }
@@ -136,6 +143,16 @@ fn check_domain(data: &str) -> Option<usize> {
     }
 }
 
+fn tld_allowed(domain: &str, tlds: Option<&HashSet<String>>) -> bool {
+    let tlds = match tlds {
+        Some(tlds) => tlds,
+        None => return true,
+    };
+
+    let tld = domain.trim_end_matches('.').rsplit('.').next().unwrap_or("");
+    tlds.iter().any(|t| t.eq_ignore_ascii_case(tld))
+}
+
 fn is_valid_hostchar(ch: char) -> bool {
     !ch.is_whitespace() && !ch.is_punctuation()
 }
@@ -205,6 +222,7 @@ fn url_match<'a>(
     arena: &'a Arena<AstNode<'a>>,
     contents: &str,
     i: usize,
+    tlds: Option<&HashSet<String>>,
 ) -> Option<(&'a AstNode<'a>, usize, usize)> {
     lazy_static! {
         static ref SCHEMES: Vec<&'static str> =
@@ -234,6 +252,10 @@ fn url_match<'a>(
         Some(link_end) => link_end,
     };
 
+    if !tld_allowed(&contents[i + 3..i + 3 + link_end], tlds) {
+        return None;
+    }
+
     while link_end < size - i && !isspace(contents.as_bytes()[i + link_end]) {
         link_end += 1;
     }
@@ -256,6 +278,7 @@ fn email_match<'a>(
     arena: &'a Arena<AstNode<'a>>,
     contents: &str,
     i: usize,
+    tlds: Option<&HashSet<String>>,
 ) -> Option<(&'a AstNode<'a>, usize, usize)> {
     lazy_static! {
         static ref EMAIL_OK_SET: [bool; 256] = {
@@ -318,6 +341,10 @@ fn email_match<'a>(
         return None;
     }
 
+    if !tld_allowed(&contents[i + 1..i + link_end], tlds) {
+        return None;
+    }
+
     link_end = autolink_delim(&contents[i..], link_end);
 
     let mut url = "mailto:".to_string();
@@ -340,6 +367,135 @@ fn email_match<'a>(
     Some((inl, rewind, rewind + link_end))
 }
 
+pub fn process_phone_links<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    node: &'a AstNode<'a>,
+    contents: &mut String,
+) {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(concat!(
+            r"(?:\+\d{1,3}[ .-]?)?(?:\([2-9]\d{2}\)[ .-]?|[2-9]\d{2}[ .-])\d{3}[ .-]\d{4}",
+            r"|\+\d{8,15}"
+        )).unwrap();
+    }
+
+    let (start, end) = match RE.find_iter(contents).find(|m| {
+        phone_bounded(contents, m.start(), m.end())
+    }) {
+        Some(m) => (m.start(), m.end()),
+        None => return,
+    };
+
+    let number = contents[start..end].to_string();
+    let mut url = "tel:".to_string();
+    if number.starts_with('+') {
+        url.push('+');
+    }
+    url.extend(number.chars().filter(|c| c.is_ascii_digit()));
+
+    let inl = make_inline(
+        arena,
+        NodeValue::Link(NodeLink {
+            url,
+            title: String::new(),
+        }),
+    );
+    inl.append(make_inline(arena, NodeValue::Text(number)));
+
+    node.insert_after(inl);
+    if end < contents.len() {
+        let remain = contents[end..].to_string();
+        inl.insert_after(make_inline(arena, NodeValue::Text(remain)));
+    }
+    contents.truncate(start);
+}
+
+// A phone number must stand on its own: this keeps runs of digits such as version numbers, IP
+// addresses and longer identifiers from being partially matched.
+fn phone_bounded(contents: &str, start: usize, end: usize) -> bool {
+    let bytes = contents.as_bytes();
+
+    if start > 0 {
+        let c = bytes[start - 1];
+        if isalnum(c) || c == b'.' || c == b'-' || c == b'+' || c == b'/' || c == b'_' {
+            return false;
+        }
+    }
+
+    if end < bytes.len() {
+        let c = bytes[end];
+        if isalnum(c) {
+            return false;
+        }
+        if (c == b'.' || c == b'-') && end + 1 < bytes.len() && isdigit(bytes[end + 1]) {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Links the first `@mention` or `#hashtag` in `contents`, according to `sigil`, to `template`
+/// with `{}` replaced by the name.
+pub fn process_tagged_links<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    node: &'a AstNode<'a>,
+    contents: &mut String,
+    sigil: u8,
+    template: &str,
+) {
+    let (start, end) = match find_tag(contents.as_bytes(), sigil) {
+        Some(found) => found,
+        None => return,
+    };
+
+    let inl = make_inline(
+        arena,
+        NodeValue::Link(NodeLink {
+            url: template.replace("{}", &contents[start + 1..end]),
+            title: String::new(),
+        }),
+    );
+    inl.append(make_inline(arena, NodeValue::Text(contents[start..end].to_string())));
+
+    node.insert_after(inl);
+    if end < contents.len() {
+        let remain = contents[end..].to_string();
+        inl.insert_after(make_inline(arena, NodeValue::Text(remain)));
+    }
+    contents.truncate(start);
+}
+
+// A tag is the sigil followed by letters, digits and underscores, at least one of them a letter.
+// It must not follow a word character, a slash or another sigil, which rules out email addresses,
+// URL fragments and runs like `@@name`; nor may it be followed by an `@`.
+fn find_tag(bytes: &[u8], sigil: u8) -> Option<(usize, usize)> {
+    let is_name = |c: u8| isalnum(c) || c == b'_';
+
+    for start in 0..bytes.len() {
+        if bytes[start] != sigil {
+            continue;
+        }
+
+        if start > 0 {
+            let c = bytes[start - 1];
+            if is_name(c) || c == b'/' || c == b'@' || c == b'#' {
+                continue;
+            }
+        }
+
+        let end = start + 1 + bytes[start + 1..].iter().take_while(|&&c| is_name(c)).count();
+        if bytes.get(end) == Some(&b'@') {
+            continue;
+        }
+        if bytes[start + 1..end].iter().any(|&c| isalpha(c)) {
+            return Some((start, end));
+        }
+    }
+
+    None
+}
+
 // reddit extensions
 
 pub fn process_redditlinks<'a>(
@@ -0,0 +1,29 @@
+/// Splits a column block row, `||| one ||| two |||`, into the text of each of its columns.  A
+/// single space after each separator is dropped, as is any trailing whitespace, so that the
+/// indentation of the column content is otherwise preserved.
+pub fn row(line: &str) -> Option<Vec<&str>> {
+    let line = line.trim_end();
+    if line.len() < 6 || !line.starts_with("|||") || !line.ends_with("|||") {
+        return None;
+    }
+
+    Some(
+        line[3..line.len() - 3]
+            .split("|||")
+            .map(|cell| cell.strip_prefix(' ').unwrap_or(cell).trim_end())
+            .collect(),
+    )
+}
+
+/// Collects the lines of a column block's rows into the Markdown source of each column.
+pub fn split(content: &str, columns: usize) -> Vec<String> {
+    let mut sources = vec![String::new(); columns];
+    for line in content.lines() {
+        let cells = row(line).unwrap_or_default();
+        for (source, cell) in sources.iter_mut().zip(cells) {
+            *source += cell;
+            source.push('\n');
+        }
+    }
+    sources
+}
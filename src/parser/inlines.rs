@@ -1,5 +1,5 @@
 use arena_tree::Node;
-use ctype::{isspace, ispunct};
+use ctype::{isspace, ispunct, isalnum, isalpha};
 use entity;
 use nodes::{NodeValue, Ast, NodeLink, AstNode};
 use parser::{unwrap_into, unwrap_into_copy, ComrakOptions, Reference, AutolinkType};
@@ -86,12 +86,21 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
         {
             s.special_chars[c as usize] = true;
         }
-        if options.ext_strikethrough {
+        if options.ext_strikethrough() {
             s.special_chars[b'~' as usize] = true;
         }
         if options.ext_superscript {
             s.special_chars[b'^' as usize] = true;
         }
+        if options.ext_highlight {
+            s.special_chars[b'=' as usize] = true;
+        }
+        if options.ext_smart_dashes {
+            s.special_chars[b'-' as usize] = true;
+        }
+        if options.ext_inline_macros {
+            s.special_chars[b'{' as usize] = true;
+        }
         s
     }
 
@@ -114,14 +123,29 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
             '&' => new_inl = Some(self.handle_entity()),
             '<' => new_inl = Some(self.handle_pointy_brace()),
             '*' | '_' | '\'' | '"' => new_inl = Some(self.handle_delim(c as u8)),
+            '-' if self.options.ext_smart_dashes => new_inl = Some(self.handle_hyphen()),
+            '{' if self.options.ext_inline_macros => {
+                new_inl = match self.handle_inline_macro() {
+                    Some(inl) => Some(inl),
+                    None => {
+                        self.pos += 1;
+                        Some(make_inline(self.arena, NodeValue::Text("{".to_string())))
+                    }
+                };
+            }
             // TODO: smart characters. Eh.
-            //'-' => new_inl => Some(self.handle_hyphen()),
             //'.' => new_inl => Some(self.handle_period()),
             '[' => {
-                self.pos += 1;
-                let inl = make_inline(self.arena, NodeValue::Text("[".to_string()));
-                new_inl = Some(inl);
-                self.push_bracket(false, inl);
+                let special = self.handle_citation().or_else(|| self.handle_footnote_reference());
+                new_inl = match special {
+                    Some(inl) => Some(inl),
+                    None => {
+                        self.pos += 1;
+                        let inl = make_inline(self.arena, NodeValue::Text("[".to_string()));
+                        self.push_bracket(false, inl);
+                        Some(inl)
+                    }
+                };
             }
             ']' => new_inl = self.handle_close_bracket(),
             '!' => {
@@ -136,10 +160,12 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
                 }
             }
             _ => {
-                if self.options.ext_strikethrough && c == '~' {
+                if self.options.ext_strikethrough() && c == '~' {
                     new_inl = Some(self.handle_delim(b'~'));
                 } else if self.options.ext_superscript && c == '^' {
                     new_inl = Some(self.handle_delim(b'^'));
+                } else if self.options.ext_highlight && c == '=' {
+                    new_inl = Some(self.handle_delim(b'='));
                 } else {
                     let endpos = self.find_special_char();
                     let mut contents = self.input[self.pos..endpos].to_string();
@@ -240,8 +266,9 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
                 let old_closer = closer;
 
                 if closer.unwrap().delim_char == b'*' || closer.unwrap().delim_char == b'_' ||
-                    (self.options.ext_strikethrough && closer.unwrap().delim_char == b'~') ||
-                    (self.options.ext_superscript && closer.unwrap().delim_char == b'^')
+                    (self.options.ext_strikethrough() && closer.unwrap().delim_char == b'~') ||
+                    (self.options.ext_superscript && closer.unwrap().delim_char == b'^') ||
+                    (self.options.ext_highlight && closer.unwrap().delim_char == b'=')
                 {
                     if opener_found {
                         closer = self.insert_emph(opener.unwrap(), closer.unwrap());
@@ -354,6 +381,51 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
         self.input.len()
     }
 
+    pub fn handle_hyphen(&mut self) -> &'a AstNode<'a> {
+        let start = self.pos;
+        while self.peek_char() == Some(&b'-') {
+            self.pos += 1;
+        }
+        let hyphens = self.pos - start;
+
+        if hyphens == 1 || (self.options.ext_autolink() && self.in_autolink_candidate(start)) {
+            let text = self.input[start..self.pos].to_string();
+            return make_inline(self.arena, NodeValue::Text(text));
+        }
+
+        let (em, en) = if hyphens % 3 == 0 {
+            (hyphens / 3, 0)
+        } else if hyphens % 2 == 0 {
+            (0, hyphens / 2)
+        } else if hyphens % 3 == 2 {
+            ((hyphens - 2) / 3, 1)
+        } else {
+            ((hyphens - 4) / 3, 2)
+        };
+
+        let mut text = String::with_capacity(3 * (em + en));
+        for _ in 0..em {
+            text.push('\u{2014}');
+        }
+        for _ in 0..en {
+            text.push('\u{2013}');
+        }
+        make_inline(self.arena, NodeValue::Text(text))
+    }
+
+    // Whether the word around the hyphens before `self.pos` looks like a URL or email address,
+    // which autolinking will want as written.
+    fn in_autolink_candidate(&self, start: usize) -> bool {
+        let bytes = self.input.as_bytes();
+        let word_start = bytes[..start].iter().rposition(|&c| isspace(c)).map_or(0, |i| i + 1);
+        let word_end = bytes[self.pos..]
+            .iter()
+            .position(|&c| isspace(c))
+            .map_or(bytes.len(), |i| self.pos + i);
+        let word = &self.input[word_start..word_end];
+        word.contains("://") || word.contains("www.") || word.contains('@')
+    }
+
     pub fn handle_newline(&mut self) -> &'a AstNode<'a> {
         let nlpos = self.pos;
         if self.input.as_bytes()[self.pos] == b'\r' {
@@ -363,7 +435,8 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
             self.pos += 1;
         }
         self.skip_spaces();
-        if nlpos > 1 && self.input.as_bytes()[nlpos - 1] == b' ' &&
+        if !self.options.ignore_trailing_space_breaks && nlpos > 1 &&
+            self.input.as_bytes()[nlpos - 1] == b' ' &&
             self.input.as_bytes()[nlpos - 2] == b' '
         {
             make_inline(self.arena, NodeValue::LineBreak)
@@ -442,7 +515,8 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
         let contents = self.input[self.pos - numdelims..self.pos].to_string();
         let inl = make_inline(self.arena, NodeValue::Text(contents));
 
-        if (can_open || can_close) && c != b'\'' && c != b'"' {
+        // Only runs of exactly two equals signs delimit highlights.
+        if (can_open || can_close) && c != b'\'' && c != b'"' && (c != b'=' || numdelims == 2) {
             self.push_delimiter(c, can_open, can_close, inl);
         }
 
@@ -477,12 +551,18 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
             self.input[self.pos..].chars().next().unwrap()
         };
 
+        // Whether the run is set off from the text on either side, which lets it open or close
+        // next to punctuation on the other.
+        let cjk = self.options.cjk_friendly_emphasis;
+        let set_off_before = before_char.is_whitespace() || before_char.is_punctuation() ||
+            (cjk && is_cjk(before_char));
+        let set_off_after = after_char.is_whitespace() || after_char.is_punctuation() ||
+            (cjk && is_cjk(after_char));
+
         let left_flanking = numdelims > 0 && !after_char.is_whitespace() &&
-            !(after_char.is_punctuation() && !before_char.is_whitespace() &&
-                  !before_char.is_punctuation());
+            (!after_char.is_punctuation() || set_off_before);
         let right_flanking = numdelims > 0 && !before_char.is_whitespace() &&
-            !(before_char.is_punctuation() && !after_char.is_whitespace() &&
-                  !after_char.is_punctuation());
+            (!before_char.is_punctuation() || set_off_after);
 
         if c == b'_' {
             (
@@ -529,7 +609,7 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
         opener_num_chars -= use_delims;
         closer_num_chars -= use_delims;
 
-        if self.options.ext_strikethrough && opener_char == b'~' {
+        if self.options.ext_strikethrough() && opener_char == b'~' {
             opener_num_chars = 0;
             closer_num_chars = 0;
         }
@@ -559,14 +639,16 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
 
         let emph = make_inline(
             self.arena,
-            if self.options.ext_strikethrough && opener_char == b'~' {
-                if use_delims == 1 {
+            if self.options.ext_strikethrough() && opener_char == b'~' {
+                if use_delims == 1 && self.options.ext_underline {
                     NodeValue::Underline
                 } else {
                     NodeValue::Strikethrough
                 }
             } else if self.options.ext_superscript && opener_char == b'^' {
                 NodeValue::Superscript
+            } else if self.options.ext_highlight && opener_char == b'=' {
+                NodeValue::Highlight
             } else if use_delims == 1 {
                 NodeValue::Emph
             } else {
@@ -686,6 +768,90 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
         });
     }
 
+    pub fn handle_citation(&mut self) -> Option<&'a AstNode<'a>> {
+        if !self.options.ext_citations {
+            return None;
+        }
+
+        let rest = &self.input.as_bytes()[self.pos..];
+        if rest.len() < 4 || rest[1] != b'@' || !(isalnum(rest[2]) || rest[2] == b'_') {
+            return None;
+        }
+
+        let mut end = 3;
+        while end < rest.len() &&
+            (isalnum(rest[end]) || b"_:.#$%&-+?<>~/".contains(&rest[end]))
+        {
+            end += 1;
+        }
+        if end == rest.len() || rest[end] != b']' {
+            return None;
+        }
+        if matches!(rest.get(end + 1), Some(&b'(') | Some(&b'[')) {
+            return None;
+        }
+
+        let key = self.input[self.pos + 2..self.pos + end].to_string();
+        self.pos += end + 1;
+        Some(make_inline(self.arena, NodeValue::Cite(key)))
+    }
+
+    pub fn handle_footnote_reference(&mut self) -> Option<&'a AstNode<'a>> {
+        if !self.options.ext_footnotes {
+            return None;
+        }
+
+        let rest = &self.input.as_bytes()[self.pos..];
+        if rest.len() < 4 || rest[1] != b'^' {
+            return None;
+        }
+
+        let mut end = 2;
+        while end < rest.len() && !isspace(rest[end]) && rest[end] != b'[' && rest[end] != b']' {
+            end += 1;
+        }
+        if end == 2 || end == rest.len() || rest[end] != b']' {
+            return None;
+        }
+        if matches!(rest.get(end + 1), Some(&b'(') | Some(&b'[')) {
+            return None;
+        }
+
+        let label = self.input[self.pos + 2..self.pos + end].to_string();
+        self.pos += end + 1;
+        Some(make_inline(self.arena, NodeValue::FootnoteReference(label)))
+    }
+
+    pub fn handle_inline_macro(&mut self) -> Option<&'a AstNode<'a>> {
+        let rest = &self.input.as_bytes()[self.pos..];
+        if rest.len() < 4 || !isalpha(rest[1]) {
+            return None;
+        }
+
+        let mut colon = 2;
+        while colon < rest.len() &&
+            (isalnum(rest[colon]) || rest[colon] == b'-' || rest[colon] == b'_')
+        {
+            colon += 1;
+        }
+        if colon == rest.len() || rest[colon] != b':' {
+            return None;
+        }
+
+        let mut end = colon + 1;
+        while end < rest.len() && rest[end] != b'}' && !strings::is_line_end_char(rest[end]) {
+            end += 1;
+        }
+        if end == rest.len() || rest[end] != b'}' {
+            return None;
+        }
+
+        let name = self.input[self.pos + 1..self.pos + colon].to_string();
+        let args = self.input[self.pos + colon + 1..self.pos + end].to_string();
+        self.pos += end + 1;
+        Some(make_inline(self.arena, NodeValue::InlineMacro { name, args }))
+    }
+
     pub fn handle_close_bracket(&mut self) -> Option<&'a AstNode<'a>> {
         self.pos += 1;
         let initial_pos = self.pos;
@@ -902,6 +1068,7 @@ pub fn make_inline<'a>(arena: &'a Arena<AstNode<'a>>, value: NodeValue) -> &'a A
     let ast = Ast {
         value: value,
         content: String::new(),
+        attributes: vec![],
         start_line: 0,
         start_column: 0,
         end_line: 0,
@@ -930,3 +1097,22 @@ fn make_autolink<'a>(
     ));
     inl
 }
+
+// Han ideographs, kana, hangul, and CJK symbols and punctuation, including full-width forms.
+fn is_cjk(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1100..=0x11FF |
+            0x2E80..=0x2FDF |
+            0x3001..=0x303F |
+            0x3040..=0x30FF |
+            0x3100..=0x31FF |
+            0x3200..=0x9FFF |
+            0xA960..=0xA97F |
+            0xAC00..=0xD7FF |
+            0xF900..=0xFAFF |
+            0xFE30..=0xFE4F |
+            0xFF00..=0xFFEF |
+            0x20000..=0x3FFFF
+    )
+}
@@ -1,20 +1,29 @@
 mod table;
+mod columns;
 mod autolink;
+mod abbreviation;
 mod inlines;
 
 
 use arena_tree::Node;
-use ctype::{isspace, isdigit};
+use ctype::{isspace, isdigit, isalnum};
 use entity;
 use nodes;
 use nodes::{NodeValue, Ast, NodeCodeBlock, NodeHeading, NodeList, ListType, ListDelimType,
-            NodeHtmlBlock, make_block, AstNode};
+            NodeHtmlBlock, HtmlBlockType, NodeContainer, make_block, AstNode};
 use regex::Regex;
 use scanners;
+use std::borrow::Cow;
 use std::cell::RefCell;
 use std::cmp::min;
-use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::error::Error;
+use std::fmt;
 use std::mem;
+use std::sync::Arc;
+use std::str::FromStr;
 use strings;
 use typed_arena::Arena;
 
@@ -32,6 +41,7 @@ pub fn parse_document<'a>(
     let root: &'a AstNode<'a> = arena.alloc(Node::new(RefCell::new(Ast {
         value: NodeValue::Document,
         content: String::new(),
+        attributes: vec![],
         start_line: 0,
         start_column: 0,
         end_line: 0,
@@ -40,13 +50,181 @@ pub fn parse_document<'a>(
         last_line_blank: false,
     })));
     let mut parser = Parser::new(arena, root, options);
-    parser.feed(buffer, true);
+    parser.feed(&normalize_input(buffer, options), true);
     parser.finish()
 }
 
+/// Parse a Markdown document to an AST of blocks only, without parsing inlines.
+///
+/// Blocks which would ordinarily contain inlines (paragraphs, headings and table cells) have no
+/// children; their raw text is left in `Ast::content` for custom inline handling downstream.
+/// Link reference definitions are still consumed.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document_raw, ComrakOptions};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let root = parse_document_raw(&arena, "Hello, *world*.\n", &ComrakOptions::default());
+/// let paragraph = root.first_child().unwrap();
+/// assert!(paragraph.first_child().is_none());
+/// assert_eq!(paragraph.data.borrow().content, "Hello, *world*.\n");
+/// # }
+/// ```
+pub fn parse_document_raw<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    buffer: &str,
+    options: &ComrakOptions,
+) -> &'a AstNode<'a> {
+    let root: &'a AstNode<'a> = arena.alloc(Node::new(RefCell::new(Ast {
+        value: NodeValue::Document,
+        content: String::new(),
+        attributes: vec![],
+        start_line: 0,
+        start_column: 0,
+        end_line: 0,
+        end_column: 0,
+        open: true,
+        last_line_blank: false,
+    })));
+    let mut parser = Parser::new(arena, root, options);
+    parser.feed(&normalize_input(buffer, options), true);
+    parser.finish_raw()
+}
+
+/// Tidies the text of an AST after it has been edited: adjacent `Text` nodes are merged, empty
+/// ones are removed, and the text post-passes of the enabled extensions, such as autolinking,
+/// are run again.  Any nodes they create are allocated in the given arena.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, normalize, format_html, ComrakOptions};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let mut options = ComrakOptions::default();
+/// options.ext_autolink = true;
+/// let root = parse_document(&arena, "Visit *www*.example.com\n", &options);
+///
+/// // Unwrap the emphasis, leaving three adjacent text nodes.
+/// let emph = root.first_child().unwrap().children().nth(1).unwrap();
+/// emph.insert_before(emph.first_child().unwrap());
+/// emph.detach();
+///
+/// normalize(&arena, root, &options);
+/// assert_eq!(format_html(root, &options),
+///            "<p>Visit <a href=\"http://www.example.com\">www.example.com</a></p>\n");
+/// # }
+/// ```
+pub fn normalize<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    options: &ComrakOptions,
+) {
+    let mut parser = Parser::new(arena, root, options);
+
+    let mut empty = vec![];
+    for node in root.descendants() {
+        match node.data.borrow().value {
+            NodeValue::Text(ref text) if text.is_empty() => empty.push(node),
+            NodeValue::AbbreviationDef { ref abbr, ref title } => {
+                parser.abbreviations.entry(abbr.clone()).or_insert_with(|| title.clone());
+            }
+            _ => (),
+        }
+    }
+    for node in empty {
+        node.detach();
+    }
+
+    parser.postprocess();
+}
+
+#[cfg(feature = "unicode-normalization")]
+fn normalize_input<'b>(buffer: &'b str, options: &ComrakOptions) -> Cow<'b, str> {
+    use unicode_normalization::UnicodeNormalization;
+
+    match options.unicode_normalization {
+        Some(NormalizationForm::Nfc) => Cow::Owned(buffer.nfc().collect()),
+        Some(NormalizationForm::Nfkc) => Cow::Owned(buffer.nfkc().collect()),
+        None => Cow::Borrowed(buffer),
+    }
+}
+
+#[cfg(not(feature = "unicode-normalization"))]
+fn normalize_input<'b>(buffer: &'b str, _options: &ComrakOptions) -> Cow<'b, str> {
+    Cow::Borrowed(buffer)
+}
+
+/// Parses a Markdown document which is given in pieces, such as when it arrives over the network,
+/// without first collecting it into one string.  Pieces may split the document anywhere, even
+/// within a line.  The result is the same as for `parse_document` on the whole document.
+///
+/// Like `parse_document`, the nodes are allocated in the given arena, so the arena must outlive
+/// the parser and the returned AST.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{format_html, ComrakOptions, DocumentParser};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let options = ComrakOptions::default();
+///
+/// let mut parser = DocumentParser::new(&arena, &options);
+/// parser.feed("# Hel");
+/// parser.feed("lo\n\n*wor");
+/// parser.feed("ld*\n");
+/// let root = parser.finish();
+/// assert_eq!(format_html(root, &options), "<h1>Hello</h1>\n<p><em>world</em></p>\n");
+/// # }
+/// ```
+pub struct DocumentParser<'a, 'o> {
+    parser: Parser<'a, 'o>,
+    pending: String,
+}
+
+impl<'a, 'o> DocumentParser<'a, 'o> {
+    /// Creates a parser for a new document, whose nodes will be allocated in `arena`.
+    pub fn new(arena: &'a Arena<AstNode<'a>>, options: &'o ComrakOptions) -> DocumentParser<'a, 'o> {
+        let root: &'a AstNode<'a> =
+            arena.alloc(Node::new(RefCell::new(make_block(NodeValue::Document, 0, 0))));
+        DocumentParser {
+            parser: Parser::new(arena, root, options),
+            pending: String::new(),
+        }
+    }
+
+    /// Parses the next piece of the document.  With the `unicode_normalization` option, the
+    /// text is only normalized and parsed once its line is complete, since a line ending always
+    /// separates a character from any combining marks.
+    pub fn feed(&mut self, chunk: &str) {
+        if self.parser.options.unicode_normalization.is_none() {
+            self.parser.feed(chunk, false);
+            return;
+        }
+
+        self.pending.push_str(chunk);
+        if let Some(end) = self.pending.rfind(['\n', '\r']) {
+            self.parser.feed(&normalize_input(&self.pending[..=end], self.parser.options), false);
+            self.pending.drain(..=end);
+        }
+    }
+
+    /// Finishes parsing the document, and returns its root node.
+    pub fn finish(mut self) -> &'a AstNode<'a> {
+        if !self.pending.is_empty() {
+            self.parser.feed(&normalize_input(&self.pending, self.parser.options), false);
+        }
+        self.parser.finish()
+    }
+}
+
 pub struct Parser<'a, 'o> {
     arena: &'a Arena<AstNode<'a>>,
     refmap: HashMap<String, Reference>,
+    abbreviations: HashMap<String, String>,
     root: &'a AstNode<'a>,
     current: &'a AstNode<'a>,
     line_number: u32,
@@ -58,12 +236,13 @@ pub struct Parser<'a, 'o> {
     blank: bool,
     partially_consumed_tab: bool,
     last_line_length: usize,
+    curline_end_column: Option<usize>,
     linebuf: String,
     last_buffer_ended_with_cr: bool,
     options: &'o ComrakOptions,
 }
 
-#[derive(Default, Debug, Clone, Copy)]
+#[derive(Default, Debug, Clone)]
 /// Options for both parser and formatter functions.
 pub struct ComrakOptions {
     /// [Soft line breaks](http://spec.commonmark.org/0.27/#soft-line-breaks) in the input
@@ -79,122 +258,1268 @@ pub struct ComrakOptions {
     /// assert_eq!(markdown_to_html("Hello.\nWorld.\n", &options),
     ///            "<p>Hello.<br />\nWorld.</p>\n");
     /// ```
-    pub hardbreaks: bool,
+    pub hardbreaks: bool,
+
+    /// When `hardbreaks` is set, only soft line breaks within paragraphs translate into hard line
+    /// breaks; those in other blocks, such as setext headings, are left as they are.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.hardbreaks = true;
+    /// assert_eq!(markdown_to_html("Hello.\nWorld.\n===\n", &options),
+    ///            "<h1>Hello.<br />\nWorld.</h1>\n");
+    ///
+    /// options.hardbreaks_paragraph_only = true;
+    /// assert_eq!(markdown_to_html("Hello.\nWorld.\n===\n", &options),
+    ///            "<h1>Hello.\nWorld.</h1>\n");
+    /// ```
+    pub hardbreaks_paragraph_only: bool,
+
+    /// Two or more spaces at the end of a line do not make a hard line break, which must instead
+    /// be written with a backslash.  Trailing spaces are often left in pasted text by accident.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// assert_eq!(markdown_to_html("Hello.  \nWorld.\n", &options),
+    ///            "<p>Hello.<br />\nWorld.</p>\n");
+    ///
+    /// options.ignore_trailing_space_breaks = true;
+    /// assert_eq!(markdown_to_html("Hello.  \nWorld.\\\nAgain.\n", &options),
+    ///            "<p>Hello.\nWorld.<br />\nAgain.</p>\n");
+    /// ```
+    pub ignore_trailing_space_breaks: bool,
+
+    /// Stops lists from interrupting paragraphs, so a line starting with a list marker directly
+    /// after paragraph text continues the paragraph.  A blank line is needed to start the list.
+    /// Paragraphs directly inside list items are exempt, so tight nested lists such as
+    /// `- a\n  - b` still nest.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// assert_eq!(markdown_to_html("Shopping:\n- eggs\n", &options),
+    ///            "<p>Shopping:</p>\n<ul>\n<li>eggs</li>\n</ul>\n");
+    ///
+    /// options.lists_never_interrupt_paragraphs = true;
+    /// assert_eq!(markdown_to_html("Shopping:\n- eggs\n", &options),
+    ///            "<p>Shopping:\n- eggs</p>\n");
+    /// ```
+    pub lists_never_interrupt_paragraphs: bool,
+
+    /// Normalizes the input to this Unicode normalization form before parsing, so that text
+    /// which looks the same renders the same whatever code points it was written with.  Without
+    /// the `unicode-normalization` feature the input is left as it is.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions, NormalizationForm};
+    /// let mut options = ComrakOptions::default();
+    /// options.unicode_normalization = Some(NormalizationForm::Nfc);
+    /// # #[cfg(feature = "unicode-normalization")]
+    /// assert_eq!(markdown_to_html("Cafe\u{301}\n", &options),
+    ///            "<p>Caf\u{e9}</p>\n");
+    /// ```
+    pub unicode_normalization: Option<NormalizationForm>,
+
+    /// GitHub-style `<pre lang="xyz">` is used for fenced code blocks with info tags.  Only the
+    /// part of the first word before any comma is used, so `rust,no_run` gives `lang="rust"`.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// assert_eq!(markdown_to_html("``` rust\nfn hello();\n```\n", &options),
+    ///            "<pre><code class=\"language-rust\">fn hello();\n</code></pre>\n");
+    ///
+    /// options.github_pre_lang = true;
+    /// assert_eq!(markdown_to_html("``` rust\nfn hello();\n```\n", &options),
+    ///            "<pre lang=\"rust\"><code>fn hello();\n</code></pre>\n");
+    /// ```
+    pub github_pre_lang: bool,
+
+    /// Matches GitHub's rendering rather than the CommonMark spec where the two differ.  This
+    /// turns on, regardless of their own settings:
+    ///
+    /// * the `strikethrough`, `tagfilter`, `table`, `autolink` and `tasklist` extensions, and
+    /// * `github_pre_lang`.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// assert_eq!(markdown_to_html("Hello ~~world~~.\n", &options),
+    ///            "<p>Hello ~~world~~.</p>\n");
+    ///
+    /// options.gfm_quirks = true;
+    /// assert_eq!(markdown_to_html("Hello ~~world~~.\n", &options),
+    ///            "<p>Hello <del>world</del>.</p>\n");
+    /// ```
+    pub gfm_quirks: bool,
+
+    /// Lets emphasis, strong emphasis and strikethrough delimiters next to Chinese, Japanese or
+    /// Korean text open and close as if the text were punctuation or whitespace, so that runs
+    /// like `**中文。**测试`, whose inner side is full-width punctuation and outer side an
+    /// ideograph, are emphasized.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// assert_eq!(markdown_to_html("これは**「強調」**です。\n", &options),
+    ///            "<p>これは**「強調」**です。</p>\n");
+    ///
+    /// options.cjk_friendly_emphasis = true;
+    /// assert_eq!(markdown_to_html("これは**「強調」**です。\n", &options),
+    ///            "<p>これは<strong>「強調」</strong>です。</p>\n");
+    /// ```
+    pub cjk_friendly_emphasis: bool,
+
+    /// Each line of a code block is wrapped in a `<span>` carrying its line number.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.code_line_numbers = true;
+    /// assert_eq!(markdown_to_html("    a\n    b\n", &options),
+    ///            "<pre><code><span class=\"line\" data-line=\"1\">a</span>\n\
+    ///             <span class=\"line\" data-line=\"2\">b</span>\n</code></pre>\n");
+    /// ```
+    pub code_line_numbers: bool,
+
+    /// Lets a `linenos` token in a fenced code block's info string turn on `code_line_numbers`
+    /// for that block alone.  Otherwise `linenos` is an ordinary language name.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// assert_eq!(markdown_to_html("``` rust linenos\nfn hello();\n```\n", &options),
+    ///            "<pre><code class=\"language-rust\">fn hello();\n</code></pre>\n");
+    ///
+    /// options.code_linenos_token = true;
+    /// assert_eq!(markdown_to_html("``` rust linenos\nfn hello();\n```\n", &options),
+    ///            "<pre><code class=\"language-rust\">\
+    ///             <span class=\"line\" data-line=\"1\">fn hello();</span>\n</code></pre>\n");
+    /// ```
+    pub code_linenos_token: bool,
+
+    /// If set, the language of a fenced code block (the first word of its info string) is only
+    /// output when it appears in this list, compared case-insensitively.  Otherwise the code block
+    /// is rendered as if it had no language.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// assert_eq!(markdown_to_html("``` console output\n$ ls\n```\n", &options),
+    ///            "<pre><code class=\"language-console\">$ ls\n</code></pre>\n");
+    ///
+    /// options.known_languages = Some(vec!["rust".to_string()]);
+    /// assert_eq!(markdown_to_html("``` console output\n$ ls\n```\n", &options),
+    ///            "<pre><code>$ ls\n</code></pre>\n");
+    /// ```
+    pub known_languages: Option<Vec<String>>,
+
+    /// Maps alternative names for a code block's language to the name to output in its place.
+    /// Languages are compared case-insensitively with both the aliases and the names they map to,
+    /// so differently-cased spellings of a name are also normalized to it.  Aliases are resolved
+    /// before `known_languages` is consulted.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.code_language_aliases.insert("js".to_string(), "javascript".to_string());
+    /// assert_eq!(markdown_to_html("``` JS\nf()\n```\n", &options),
+    ///            "<pre><code class=\"language-javascript\">f()\n</code></pre>\n");
+    /// assert_eq!(markdown_to_html("``` JavaScript\nf()\n```\n", &options),
+    ///            "<pre><code class=\"language-javascript\">f()\n</code></pre>\n");
+    /// ```
+    pub code_language_aliases: HashMap<String, String>,
+
+    /// If set, the HTML output is wrapped in an element with this name and these attributes.
+    /// Names may only contain ASCII letters, digits, `_`, `:` and `-`; an element with any other
+    /// name is left out, as is any attribute with one.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.wrap_element = Some((
+    ///     "article".to_string(),
+    ///     vec![("class".to_string(), "markdown-body".to_string())],
+    /// ));
+    /// assert_eq!(markdown_to_html("Hello.\n", &options),
+    ///            "<article class=\"markdown-body\">\n<p>Hello.</p>\n</article>\n");
+    /// ```
+    pub wrap_element: Option<(String, Vec<(String, String)>)>,
+
+    /// Writes void elements (`hr`, `br`, `img` and task list `input`s) HTML5-style, as `<hr>`,
+    /// rather than XHTML-style, as `<hr />`.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// assert_eq!(markdown_to_html("a\\\nb\n\n---\n", &options),
+    ///            "<p>a<br />\nb</p>\n<hr />\n");
+    ///
+    /// options.html5_void_elements = true;
+    /// assert_eq!(markdown_to_html("a\\\nb\n\n---\n", &options),
+    ///            "<p>a<br>\nb</p>\n<hr>\n");
+    /// ```
+    pub html5_void_elements: bool,
+
+    /// Rewrites bare `<br>` tags in inline raw HTML, in any case and with or without a closing
+    /// slash, in the same form as line breaks: `<br />`, or `<br>` with `html5_void_elements`.
+    /// By default raw HTML is output as written.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.normalize_html_breaks = true;
+    /// assert_eq!(markdown_to_html("a<br>b<BR/>c\n", &options),
+    ///            "<p>a<br />b<br />c</p>\n");
+    /// ```
+    pub normalize_html_breaks: bool,
+
+    /// The class given to thematic breaks in HTML output, if any.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.thematic_break_class = Some("divider".to_string());
+    /// assert_eq!(markdown_to_html("---\n", &options),
+    ///            "<hr class=\"divider\" />\n");
+    /// ```
+    pub thematic_break_class: Option<String>,
+
+    /// Outputs the alignment of table cells as a class of this prefix followed by `left`,
+    /// `center` or `right`, rather than as an `align` attribute.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_table = true;
+    /// options.table_alignment_class_prefix = Some("text-".to_string());
+    /// assert_eq!(markdown_to_html("| a |\n|--:|\n| b |\n", &options),
+    ///            "<table>\n<thead>\n<tr>\n<th class=\"text-right\">a</th>\n</tr>\n</thead>\n\
+    ///             <tbody>\n<tr>\n<td class=\"text-right\">b</td>\n</tr></tbody></table>\n");
+    /// ```
+    pub table_alignment_class_prefix: Option<String>,
+
+    /// Leaves off the newline which otherwise ends the output of both the HTML and CommonMark
+    /// formatters, for embedding the output inline.  Only that one newline is removed.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.strip_trailing_newline = true;
+    /// assert_eq!(markdown_to_html("Hello, *world*.\n", &options),
+    ///            "<p>Hello, <em>world</em>.</p>");
+    /// ```
+    pub strip_trailing_newline: bool,
+
+    /// If the whole document is a single paragraph, its contents are written to HTML without the
+    /// enclosing `<p>` element, for rendering short snippets inline.  Documents with any other
+    /// content are unaffected.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.unwrap_single_paragraph = true;
+    /// assert_eq!(markdown_to_html("Just **bold**.\n", &options),
+    ///            "Just <strong>bold</strong>.");
+    /// assert_eq!(markdown_to_html("One.\n\nTwo.\n", &options),
+    ///            "<p>One.</p>\n<p>Two.</p>\n");
+    /// ```
+    pub unwrap_single_paragraph: bool,
+
+    /// A block quote containing nothing but a single paragraph is written to HTML without the
+    /// `<p>` element around the paragraph, for compact pull quotes.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// assert_eq!(markdown_to_html("> Quoted.\n", &options),
+    ///            "<blockquote>\n<p>Quoted.</p>\n</blockquote>\n");
+    ///
+    /// options.unwrap_blockquote_paragraph = true;
+    /// assert_eq!(markdown_to_html("> Quoted.\n", &options),
+    ///            "<blockquote>\nQuoted.\n</blockquote>\n");
+    /// ```
+    pub unwrap_blockquote_paragraph: bool,
+
+    /// A paragraph consisting of nothing but an image with a title is rendered as a `<figure>`,
+    /// with the title as its caption.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.image_figures = true;
+    /// assert_eq!(markdown_to_html("![A cat](cat.png \"My cat\")\n", &options),
+    ///            "<figure>\n<img src=\"cat.png\" alt=\"A cat\" />\n\
+    ///             <figcaption>My cat</figcaption>\n</figure>\n");
+    /// ```
+    pub image_figures: bool,
+
+    /// Tabs in code blocks are kept as they appear in the source.  By default, a tab which is only
+    /// partly consumed by the indentation of a code block or its container is expanded to spaces.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// assert_eq!(markdown_to_html("-\t\tcode\n", &options),
+    ///            "<ul>\n<li>\n<pre><code>  code\n</code></pre>\n</li>\n</ul>\n");
+    ///
+    /// options.preserve_tabs = true;
+    /// assert_eq!(markdown_to_html("-\t\tcode\n", &options),
+    ///            "<ul>\n<li>\n<pre><code>\tcode\n</code></pre>\n</li>\n</ul>\n");
+    /// ```
+    pub preserve_tabs: bool,
+
+    /// The wrap column when outputting CommonMark.  Columns are counted in bytes, unless the
+    /// `unicode-width` feature is enabled, in which case each character counts for its display
+    /// width: two for East Asian wide characters and none for combining marks.
+    ///
+    /// ```
+    /// # extern crate typed_arena;
+    /// # extern crate comrak;
+    /// # use comrak::{parse_document, ComrakOptions, format_commonmark};
+    /// # fn main() {
+    /// # let arena = typed_arena::Arena::new();
+    /// let mut options = ComrakOptions::default();
+    /// let node = parse_document(&arena, "hello hello hello hello hello hello", &options);
+    /// assert_eq!(format_commonmark(node, &options),
+    ///            "hello hello hello hello hello hello\n");
+    ///
+    /// options.width = 20;
+    /// assert_eq!(format_commonmark(node, &options),
+    ///            "hello hello hello\nhello hello hello\n");
+    /// # }
+    /// ```
+    pub width: usize,
+
+    /// Keeps the author's style when outputting CommonMark, where the document records it: bullet
+    /// characters and list marker spacing, fence characters and lengths, indented code blocks,
+    /// setext headings, and runs of more than one blank line between blocks.
+    ///
+    /// ```
+    /// # extern crate typed_arena;
+    /// # extern crate comrak;
+    /// # use comrak::{parse_document, ComrakOptions, format_commonmark};
+    /// # fn main() {
+    /// # let arena = typed_arena::Arena::new();
+    /// let mut options = ComrakOptions::default();
+    /// let node = parse_document(&arena, "Title\n=====\n\n* one\n* two\n", &options);
+    /// assert_eq!(format_commonmark(node, &options),
+    ///            "# Title\n\n  - one\n  - two\n");
+    ///
+    /// options.preserve_source_style = true;
+    /// assert_eq!(format_commonmark(node, &options),
+    ///            "Title\n=====\n\n* one\n* two\n");
+    /// # }
+    /// ```
+    pub preserve_source_style: bool,
+
+    /// The shortest fence to use for fenced code blocks when outputting CommonMark.  Fences are
+    /// still made longer than any run of the fence character in the code.  Values below 3, the
+    /// minimum the spec allows, including the default, mean 3.
+    ///
+    /// ```
+    /// # extern crate typed_arena;
+    /// # extern crate comrak;
+    /// # use comrak::{parse_document, ComrakOptions, format_commonmark};
+    /// # fn main() {
+    /// # let arena = typed_arena::Arena::new();
+    /// let mut options = ComrakOptions::default();
+    /// options.min_fence_length = 4;
+    /// let node = parse_document(&arena, "```rust\ncode\n```\n", &options);
+    /// assert_eq!(format_commonmark(node, &options),
+    ///            "```` rust\ncode\n````\n");
+    /// # }
+    /// ```
+    pub min_fence_length: usize,
+
+    /// Enables the
+    /// [strikethrough extension](https://github.github.com/gfm/#strikethrough-extension-)
+    /// from the GFM spec.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_strikethrough = true;
+    /// assert_eq!(markdown_to_html("Hello ~world~ there.\n", &options),
+    ///            "<p>Hello <del>world</del> there.</p>\n");
+    /// ```
+    pub ext_strikethrough: bool,
+
+    /// The element used for strikethrough text in HTML output.  `None`, the default, means `del`.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_strikethrough = true;
+    /// options.strikethrough_element = Some("span".to_string());
+    /// options.strikethrough_class = Some("deleted".to_string());
+    /// assert_eq!(markdown_to_html("Hello ~~world~~.\n", &options),
+    ///            "<p>Hello <span class=\"deleted\">world</span>.</p>\n");
+    /// ```
+    pub strikethrough_element: Option<String>,
+
+    /// The class given to strikethrough elements in HTML output, if any.
+    pub strikethrough_class: Option<String>,
+
+    /// Makes text between single tildes underlined rather than struck through, as reddit does.
+    /// Only takes effect with `ext_strikethrough`; double tildes still strike through.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_strikethrough = true;
+    /// options.ext_underline = true;
+    /// assert_eq!(markdown_to_html("Hello ~world~ ~~there~~.\n", &options),
+    ///            "<p>Hello <u>world</u> <del>there</del>.</p>\n");
+    /// ```
+    pub ext_underline: bool,
+
+    /// The element used for underlined text in HTML output.  `None`, the default, means `u`.
+    pub underline_element: Option<String>,
+
+    /// The class given to underline elements in HTML output, if any.
+    pub underline_class: Option<String>,
+
+    /// Enables the
+    /// [tagfilter extension](https://github.github.com/gfm/#disallowed-raw-html-extension-)
+    /// from the GFM spec.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_tagfilter = true;
+    /// assert_eq!(markdown_to_html("Hello <xmp>.\n\n<xmp>", &options),
+    ///            "<p>Hello &lt;xmp>.</p>\n&lt;xmp>\n");
+    /// ```
+    pub ext_tagfilter: bool,
+
+    /// Enables the [table extension](https://github.github.com/gfm/#tables-extension-)
+    /// from the GFM spec.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_table = true;
+    /// assert_eq!(markdown_to_html("| a | b |\n|---|---|\n| c | d |\n", &options),
+    ///            "<table>\n<thead>\n<tr>\n<th>a</th>\n<th>b</th>\n</tr>\n</thead>\n\
+    ///             <tbody>\n<tr>\n<td>c</td>\n<td>d</td>\n</tr></tbody></table>\n");
+    /// ```
+    pub ext_table: bool,
+
+    /// Enables the [autolink extension](https://github.github.com/gfm/#autolinks-extension-)
+    /// from the GFM spec.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_autolink = true;
+    /// assert_eq!(markdown_to_html("Hello www.github.com.\n", &options),
+    ///            "<p>Hello <a href=\"http://www.github.com\">www.github.com</a>.</p>\n");
+    /// ```
+    pub ext_autolink: bool,
+
+    /// If set, `ext_autolink` only applies to text whose containing block is one of these kinds.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, AutolinkContext, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_autolink = true;
+    /// options.autolink_contexts = Some(vec![AutolinkContext::Paragraph]);
+    /// assert_eq!(markdown_to_html("# www.github.com\n\nHello www.github.com.\n", &options),
+    ///            "<h1>www.github.com</h1>\n\
+    ///             <p>Hello <a href=\"http://www.github.com\">www.github.com</a>.</p>\n");
+    /// ```
+    pub autolink_contexts: Option<Vec<AutolinkContext>>,
+
+    /// If set, `ext_autolink` only links domains whose top-level domain is in this set, compared
+    /// case-insensitively.  If unset, any top-level domain is accepted.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_autolink = true;
+    /// options.autolink_known_tlds = Some(["com".to_string()].iter().cloned().collect());
+    /// assert_eq!(markdown_to_html("www.github.com and www.example.localdomain\n", &options),
+    ///            "<p><a href=\"http://www.github.com\">www.github.com</a> and \
+    ///             www.example.localdomain</p>\n");
+    /// ```
+    pub autolink_known_tlds: Option<HashSet<String>>,
+
+    /// Enables the
+    /// [task list items extension](https://github.github.com/gfm/#task-list-items-extension-)
+    /// from the GFM spec.
+    ///
+    /// Note that the spec does not define the precise output, so only the bare essentials are
+    /// rendered.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_tasklist = true;
+    /// assert_eq!(markdown_to_html("* [x] Done\n* [ ] Not done\n", &options),
+    ///            "<ul>\n<li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> Done</li>\n\
+    ///            <li><input type=\"checkbox\" disabled=\"\" /> Not done</li>\n</ul>\n");
+    /// ```
+    pub ext_tasklist: bool,
+
+    /// Enables the superscript Comrak extension.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_superscript = true;
+    /// assert_eq!(markdown_to_html("e = mc^2^.\n", &options),
+    ///            "<p>e = mc<sup>2</sup>.</p>\n");
+    /// ```
+    pub ext_superscript: bool,
+
+    /// The element used for superscript text in HTML output.  `None`, the default, means `sup`.
+    pub superscript_element: Option<String>,
+
+    /// The class given to superscript elements in HTML output, if any.
+    pub superscript_class: Option<String>,
+
+    /// Enables highlighted text, written between runs of exactly two equals signs.  Other runs of
+    /// equals signs are left as text.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_highlight = true;
+    /// assert_eq!(markdown_to_html("A ==key== point, a === b.\n", &options),
+    ///            "<p>A <mark>key</mark> point, a === b.</p>\n");
+    /// ```
+    pub ext_highlight: bool,
+
+    /// The element used for highlighted text in HTML output.  `None`, the default, means `mark`.
+    pub highlight_element: Option<String>,
+
+    /// The class given to highlight elements in HTML output, if any.
+    pub highlight_class: Option<String>,
+
+    /// Enables collapsible sections, written as a `details` container fenced with three or more
+    /// colons.  The text following `details` on the opening line becomes the summary.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_collapsible = true;
+    /// assert_eq!(markdown_to_html(":::details More\nHidden.\n:::\n", &options),
+    ///            "<details>\n<summary>More</summary>\n<p>Hidden.</p>\n</details>\n");
+    /// ```
+    pub ext_collapsible: bool,
+
+    /// Enables collapsible sections written as a block fenced with three or more plus signs.  The
+    /// text following the opening fence becomes the summary, and is parsed as inline Markdown.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_details = true;
+    /// assert_eq!(markdown_to_html("+++ *More*\nHidden.\n+++\n", &options),
+    ///            "<details>\n<summary><em>More</em></summary>\n<p>Hidden.</p>\n</details>\n");
+    /// ```
+    pub ext_details: bool,
+
+    /// Enables Pandoc-style fenced divs.  A line of three or more colons followed by a class name
+    /// or an attribute block such as `{.note #intro}` opens a `<div>` with those attributes,
+    /// closed by a line of at least as many colons.  Any other text after the colons leaves the
+    /// line as ordinary text.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_fenced_divs = true;
+    /// assert_eq!(markdown_to_html("::: warning\nCareful.\n:::\n", &options),
+    ///            "<div class=\"warning\">\n<p>Careful.</p>\n</div>\n");
+    /// assert_eq!(markdown_to_html("::: {.note #intro}\nHello.\n:::\n", &options),
+    ///            "<div id=\"intro\" class=\"note\">\n<p>Hello.</p>\n</div>\n");
+    /// ```
+    pub ext_fenced_divs: bool,
+
+    /// Enables side-by-side columns, written as consecutive rows of cells separated by `|||`.
+    /// Each column's cells, read top to bottom, are parsed as Markdown of their own.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_column_blocks = true;
+    /// assert_eq!(markdown_to_html("||| *One* ||| Two |||\n", &options),
+    ///            "<div class=\"columns\">\n<div class=\"column\">\n<p><em>One</em></p>\n</div>\n\
+    ///             <div class=\"column\">\n<p>Two</p>\n</div>\n</div>\n");
+    /// ```
+    pub ext_column_blocks: bool,
+
+    /// The maximum number of link reference definitions stored for a document; further
+    /// definitions are still consumed, but ignored.  `0` means no limit.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.max_references = 1;
+    /// assert_eq!(markdown_to_html("[a]: /a\n[b]: /b\n\n[a] [b]\n", &options),
+    ///            "<p><a href=\"/a\">a</a> [b]</p>\n");
+    /// ```
+    pub max_references: usize,
+
+    /// Keeps link reference definitions in the tree as `ReferenceDef` nodes, where they were
+    /// written, rather than only consuming them.  They are still used to resolve links, and
+    /// render as nothing in HTML.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.keep_reference_definitions = true;
+    /// assert_eq!(markdown_to_html("[a]: /a\n\n[a]\n", &options),
+    ///            "<p><a href=\"/a\">a</a></p>\n");
+    /// ```
+    pub keep_reference_definitions: bool,
+
+    /// Enables autolinking of telephone numbers, in international (`+1-555-123-4567`,
+    /// `+15551234567`) or North American (`(555) 123-4567`, `555.123.4567`) formats.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_autolink_phone = true;
+    /// assert_eq!(markdown_to_html("Call +1-555-123-4567.\n", &options),
+    ///            "<p>Call <a href=\"tel:+15551234567\">+1-555-123-4567</a>.</p>\n");
+    /// ```
+    pub ext_autolink_phone: bool,
+
+    /// Enables linking of `@mention`s to `mention_url_template`.  A mention must not follow a
+    /// letter or digit, so the `@` in an email address does not start one.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_mentions = true;
+    /// assert_eq!(markdown_to_html("Thanks @alice, not alice@example.com.\n", &options),
+    ///            "<p>Thanks <a href=\"/users/alice\">@alice</a>, not alice@example.com.</p>\n");
+    /// ```
+    pub ext_mentions: bool,
+
+    /// The URL `@mention`s link to, with `{}` replaced by the name.  Defaults to `/users/{}`.
+    pub mention_url_template: Option<String>,
+
+    /// Enables linking of `#hashtag`s to `hashtag_url_template`.  A hashtag must contain a letter,
+    /// so `#1` is left alone.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_hashtags = true;
+    /// options.hashtag_url_template = Some("https://example.com/t/{}".to_string());
+    /// assert_eq!(markdown_to_html("Posted in #rust.\n", &options),
+    ///            "<p>Posted in <a href=\"https://example.com/t/rust\">#rust</a>.</p>\n");
+    /// ```
+    pub ext_hashtags: bool,
+
+    /// The URL `#hashtag`s link to, with `{}` replaced by the tag.  Defaults to `/tags/{}`.
+    pub hashtag_url_template: Option<String>,
+
+    /// Enables abbreviations, defined on a line of their own as `*[abbr]: expansion`.  Whole-word
+    /// occurrences of a defined abbreviation in text outside code and links are wrapped in an
+    /// `<abbr>` element titled with its expansion.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_abbreviations = true;
+    /// assert_eq!(markdown_to_html("HTML, not HTML5.\n\n*[HTML]: HyperText Markup Language\n",
+    ///                             &options),
+    ///            "<p><abbr title=\"HyperText Markup Language\">HTML</abbr>, not HTML5.</p>\n");
+    /// ```
+    pub ext_abbreviations: bool,
+
+    /// Enables footnotes, referenced as `[^label]` and defined by a block starting `[^label]:`,
+    /// whose following lines are indented by four spaces.  References are numbered in order, and
+    /// the definitions are moved to the end of the document, where the HTML output lists them in
+    /// a `<section class="footnotes">` with links back to the text.  Where a label is defined more
+    /// than once the first definition is used, and references to undefined labels are left as
+    /// text.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_footnotes = true;
+    /// assert_eq!(markdown_to_html("Hi[^1].\n\n[^1]: A note.\n", &options),
+    ///            "<p>Hi<sup class=\"footnote-ref\">\
+    ///             <a href=\"#fn1\" id=\"fnref1\" role=\"doc-noteref\">1</a></sup>.</p>\n\
+    ///             <section class=\"footnotes\" role=\"doc-endnotes\">\n<ol>\n<li id=\"fn1\">\n\
+    ///             <p>A note. <a href=\"#fnref1\" class=\"footnote-backref\" \
+    ///             role=\"doc-backlink\" aria-label=\"Back to reference 1\">↩</a></p>\n\
+    ///             </li>\n</ol>\n</section>\n");
+    /// ```
+    pub ext_footnotes: bool,
+
+    /// The text of the link back from each footnote to its first reference in HTML output.
+    /// `None`, the default, means `↩`; it may also be empty.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_footnotes = true;
+    /// options.footnote_backref_symbol = Some("[↑]".to_string());
+    /// assert!(markdown_to_html("Hi[^1].\n\n[^1]: A note.\n", &options)
+    ///     .contains("aria-label=\"Back to reference 1\">[↑]</a>"));
+    /// ```
+    pub footnote_backref_symbol: Option<String>,
+
+    /// Hides the links back from footnotes to their references in HTML output.  They are still
+    /// written, for navigation by script, but with `aria-hidden` and `display:none`.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_footnotes = true;
+    /// options.footnote_backref_hidden = true;
+    /// assert!(markdown_to_html("Hi[^1].\n\n[^1]: A note.\n", &options)
+    ///     .contains("aria-hidden=\"true\" style=\"display:none\">↩</a>"));
+    /// ```
+    pub footnote_backref_hidden: bool,
+
+    /// How footnotes are numbered.  With `PerSection`, the numbers start again at 1 at each
+    /// top-level heading of the given level or a higher one, each section's footnotes follow it,
+    /// and their HTML ids name the section, as in `fn-section-1-note-1`.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions, FootnoteNumbering};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_footnotes = true;
+    /// options.footnote_numbering = FootnoteNumbering::PerSection { level: 2 };
+    /// let html = markdown_to_html("## A\n\nOne[^a].\n\n## B\n\nTwo[^b].\n\n\
+    ///                              [^a]: First.\n\n[^b]: Second.\n", &options);
+    /// assert!(html.contains("<a href=\"#fn-section-2-note-1\" \
+    ///                        id=\"fnref-section-2-note-1\" role=\"doc-noteref\">1</a>"));
+    /// ```
+    pub footnote_numbering: FootnoteNumbering,
+
+    /// Wraps each top-level heading, and the content up to the next heading of the same or a
+    /// higher level, in a `<section>` element in HTML output.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_html_sections = true;
+    /// assert_eq!(markdown_to_html("# A\n\n## B\n\nText.\n\n# C\n", &options),
+    ///            "<section>\n<h1>A</h1>\n<section>\n<h2>B</h2>\n<p>Text.</p>\n</section>\n\
+    ///             </section>\n<section>\n<h1>C</h1>\n</section>\n");
+    /// ```
+    pub ext_html_sections: bool,
+
+    /// Tracks the completion of nested task lists when used with `ext_tasklist`: a task item
+    /// whose sub-lists contain task items is checked exactly when all of them are, and is marked
+    /// with `data-partial="true"` in HTML output when some are not.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_tasklist = true;
+    /// options.ext_tasklist_nesting = true;
+    /// assert_eq!(markdown_to_html("* [x] Parent\n  * [x] Done\n  * [ ] Not done\n", &options),
+    ///            "<ul>\n<li><input type=\"checkbox\" disabled=\"\" data-partial=\"true\" /> Parent\n\
+    ///             <ul>\n<li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> Done</li>\n\
+    ///             <li><input type=\"checkbox\" disabled=\"\" /> Not done</li>\n</ul>\n</li>\n</ul>\n");
+    /// ```
+    pub ext_tasklist_nesting: bool,
+
+    /// Parses Pandoc-style attributes in the info string of fenced code blocks, such as
+    /// `{.rust .highlighted #example-1}`.  The first class names the language, and the rest are
+    /// added to the `<code>` element along with the ID.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_code_block_attrs = true;
+    /// assert_eq!(markdown_to_html("``` {.rust .highlighted #example-1}\nfn hello();\n```\n",
+    ///                             &options),
+    ///            "<pre><code class=\"language-rust highlighted\" id=\"example-1\">\
+    ///             fn hello();\n</code></pre>\n");
+    /// ```
+    pub ext_code_block_attrs: bool,
+
+    /// Parses attribute blocks such as `{#id .class key=value}` at the end of a heading, or on
+    /// the last line of a paragraph, and adds them to the HTML element.  Values containing spaces
+    /// may be quoted.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_raw_attribute_passthrough = true;
+    /// assert_eq!(markdown_to_html("# Intro {#intro .lead}\n\nText.\n{data-note=\"a b\"}\n",
+    ///                             &options),
+    ///            "<h1 id=\"intro\" class=\"lead\">Intro</h1>\n\
+    ///             <p data-note=\"a b\">Text.</p>\n");
+    /// ```
+    pub ext_raw_attribute_passthrough: bool,
+
+    /// Enables citations, written `[@key]`.  Without a `bibliography`, each is rendered as a
+    /// `<cite>` element carrying its key.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_citations = true;
+    /// assert_eq!(markdown_to_html("As shown [@Smith2021].\n", &options),
+    ///            "<p>As shown <cite data-ref=\"Smith2021\">[@Smith2021]</cite>.</p>\n");
+    /// ```
+    pub ext_citations: bool,
+
+    /// Converts `---` to an em dash and `--` to an en dash in text, as smart punctuation would,
+    /// but leaves quotes and ellipses as they are.  Longer runs of hyphens are split into em and
+    /// en dashes the same way as in cmark's smart punctuation.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_smart_dashes = true;
+    /// assert_eq!(markdown_to_html("1--2 --- \"quoted\"...\n", &options),
+    ///            "<p>1\u{2013}2 \u{2014} &quot;quoted&quot;...</p>\n");
+    /// ```
+    pub ext_smart_dashes: bool,
 
-    /// GitHub-style `<pre lang="xyz">` is used for fenced code blocks with info tags.
+    /// Enables block macros: a line consisting of `@@` and a macro name, optionally followed by
+    /// arguments.  Each is rendered by the handler registered for its name in
+    /// `block_macro_handlers`, whose output is included as raw HTML; macros without a handler are
+    /// rendered as an HTML comment.
     ///
     /// ```
-    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// # use comrak::{markdown_to_html, ComrakOptions, MacroHandler};
     /// let mut options = ComrakOptions::default();
-    /// assert_eq!(markdown_to_html("``` rust\nfn hello();\n```\n", &options),
-    ///            "<pre><code class=\"language-rust\">fn hello();\n</code></pre>\n");
+    /// options.ext_block_macros = true;
+    /// options.block_macro_handlers.insert("version-badge".to_string(), MacroHandler::new(|args| {
+    ///     format!("<span class=\"badge\">v{}</span>\n", args)
+    /// }));
+    /// assert_eq!(markdown_to_html("@@version-badge 1.0.0\n\n@@toc\n", &options),
+    ///            "<span class=\"badge\">v1.0.0</span>\n<!-- unknown macro: toc -->\n");
+    /// ```
+    pub ext_block_macros: bool,
+
+    /// The handlers for block macros, by macro name.  See `ext_block_macros`.
+    pub block_macro_handlers: HashMap<String, MacroHandler>,
+
+    /// Enables inline macros, written `{name:args}`.  Each is rendered by the handler registered
+    /// for its name in `inline_macro_handlers`, whose output is included as raw HTML; macros
+    /// without a handler are rendered as an HTML comment.
     ///
-    /// options.github_pre_lang = true;
-    /// assert_eq!(markdown_to_html("``` rust\nfn hello();\n```\n", &options),
-    ///            "<pre lang=\"rust\"><code>fn hello();\n</code></pre>\n");
     /// ```
-    pub github_pre_lang: bool,
+    /// # use comrak::{markdown_to_html, ComrakOptions, MacroHandler};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_inline_macros = true;
+    /// options.inline_macro_handlers.insert("color".to_string(), MacroHandler::new(|args| {
+    ///     format!("<span style=\"color:{}\">", args)
+    /// }));
+    /// assert_eq!(markdown_to_html("{color:red}Red</span>\n", &options),
+    ///            "<p><span style=\"color:red\">Red</span></p>\n");
+    /// ```
+    pub ext_inline_macros: bool,
+
+    /// The handlers for inline macros, by macro name.  See `ext_inline_macros`.
+    pub inline_macro_handlers: HashMap<String, MacroHandler>,
 
-    /// The wrap column when outputting CommonMark.
+    /// After parsing, replaces each paragraph consisting of a single `Text` inline with a
+    /// `SimpleParagraph`, which the HTML formatter renders without visiting any children.
     ///
     /// ```
-    /// # extern crate typed_arena;
     /// # extern crate comrak;
-    /// # use comrak::{parse_document, ComrakOptions, format_commonmark};
+    /// # extern crate typed_arena;
+    /// # use comrak::{parse_document, format_html, ComrakOptions};
+    /// # use comrak::nodes::NodeValue;
     /// # fn main() {
-    /// # let arena = typed_arena::Arena::new();
+    /// let arena = typed_arena::Arena::new();
     /// let mut options = ComrakOptions::default();
-    /// let node = parse_document(&arena, "hello hello hello hello hello hello", &options);
-    /// assert_eq!(format_commonmark(node, &options),
-    ///            "hello hello hello hello hello hello\n");
+    /// options.optimize_simple_paragraphs = true;
+    /// let root = parse_document(&arena, "Plain text.\n\nNot *plain*.\n", &options);
     ///
-    /// options.width = 20;
-    /// assert_eq!(format_commonmark(node, &options),
-    ///            "hello hello hello\nhello hello hello\n");
+    /// let first = root.first_child().unwrap();
+    /// assert_eq!(first.data.borrow().value,
+    ///            NodeValue::SimpleParagraph("Plain text.".to_string()));
+    /// assert_eq!(root.last_child().unwrap().data.borrow().value, NodeValue::Paragraph);
+    /// assert_eq!(format_html(root, &options), "<p>Plain text.</p>\n<p>Not <em>plain</em>.</p>\n");
     /// # }
     /// ```
-    pub width: usize,
+    pub optimize_simple_paragraphs: bool,
 
-    /// Enables the
-    /// [strikethrough extension](https://github.github.com/gfm/#strikethrough-extension-)
-    /// from the GFM spec.
+    /// The works which citations may refer to, by key.  When given, citations of works in the
+    /// bibliography are numbered in order of first citation and link to a list of the cited works
+    /// appended to the document.
     ///
     /// ```
-    /// # use comrak::{markdown_to_html, ComrakOptions};
-    /// let mut options = ComrakOptions::default();
-    /// options.ext_strikethrough = true;
-    /// assert_eq!(markdown_to_html("Hello ~world~ there.\n", &options),
-    ///            "<p>Hello <del>world</del> there.</p>\n");
-    /// ```
-    pub ext_strikethrough: bool,
-
-    /// Enables the
-    /// [tagfilter extension](https://github.github.com/gfm/#disallowed-raw-html-extension-)
-    /// from the GFM spec.
+    /// # use comrak::{markdown_to_html, BibEntry, ComrakOptions};
+    /// # use std::collections::HashMap;
+    /// let mut bibliography = HashMap::new();
+    /// bibliography.insert("Smith2021".to_string(), BibEntry {
+    ///     text: "Smith, J. (2021). On citations.".to_string(),
+    /// });
     ///
-    /// ```
-    /// # use comrak::{markdown_to_html, ComrakOptions};
     /// let mut options = ComrakOptions::default();
-    /// options.ext_tagfilter = true;
-    /// assert_eq!(markdown_to_html("Hello <xmp>.\n\n<xmp>", &options),
-    ///            "<p>Hello &lt;xmp>.</p>\n&lt;xmp>\n");
+    /// options.ext_citations = true;
+    /// options.bibliography = Some(bibliography);
+    /// assert_eq!(markdown_to_html("As shown [@Smith2021].\n", &options),
+    ///            "<p>As shown <cite><a href=\"#ref-Smith2021\">[1]</a></cite>.</p>\n\
+    ///             <section class=\"bibliography\">\n<ol>\n\
+    ///             <li id=\"ref-Smith2021\">Smith, J. (2021). On citations.</li>\n\
+    ///             </ol>\n</section>\n");
     /// ```
-    pub ext_tagfilter: bool,
+    pub bibliography: Option<HashMap<String, BibEntry>>,
 
-    /// Enables the [table extension](https://github.github.com/gfm/#tables-extension-)
-    /// from the GFM spec.
+    /// A hook called by the HTML formatter with the destination, title and plain text of every
+    /// link and image.  Where it returns a URL, that URL is rendered in place of the original.
     ///
     /// ```
-    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// # use comrak::{markdown_to_html, ComrakOptions, LinkResolver};
     /// let mut options = ComrakOptions::default();
-    /// options.ext_table = true;
-    /// assert_eq!(markdown_to_html("| a | b |\n|---|---|\n| c | d |\n", &options),
-    ///            "<table>\n<thead>\n<tr>\n<th>a</th>\n<th>b</th>\n</tr>\n</thead>\n\
-    ///             <tbody>\n<tr>\n<td>c</td>\n<td>d</td>\n</tr></tbody></table>\n");
+    /// options.link_resolver = Some(LinkResolver::new(|url, _title, _text| {
+    ///     if url.starts_with('/') {
+    ///         Some(format!("https://example.com{}", url))
+    ///     } else {
+    ///         None
+    ///     }
+    /// }));
+    /// assert_eq!(markdown_to_html("[home](/) and [elsewhere](http://e.org)\n", &options),
+    ///            "<p><a href=\"https://example.com/\">home</a> and \
+    ///             <a href=\"http://e.org\">elsewhere</a></p>\n");
     /// ```
-    pub ext_table: bool,
+    pub link_resolver: Option<LinkResolver>,
+}
 
-    /// Enables the [autolink extension](https://github.github.com/gfm/#autolinks-extension-)
-    /// from the GFM spec.
+impl ComrakOptions {
+    /// Creates default options with the named extensions enabled, or returns an error for the
+    /// first name which is not an extension.
     ///
     /// ```
-    /// # use comrak::{markdown_to_html, ComrakOptions};
-    /// let mut options = ComrakOptions::default();
-    /// options.ext_autolink = true;
-    /// assert_eq!(markdown_to_html("Hello www.github.com.\n", &options),
-    ///            "<p>Hello <a href=\"http://www.github.com\">www.github.com</a>.</p>\n");
+    /// # use comrak::{ComrakOptions, UnknownExtension};
+    /// let options = ComrakOptions::with_extensions(&["table", "autolink"]).unwrap();
+    /// assert!(options.ext_table && options.ext_autolink);
+    ///
+    /// assert_eq!(ComrakOptions::with_extensions(&["table", "tables"]).unwrap_err(),
+    ///            UnknownExtension("tables".to_string()));
     /// ```
-    pub ext_autolink: bool,
+    pub fn with_extensions<S: AsRef<str>>(names: &[S]) -> Result<ComrakOptions, UnknownExtension> {
+        let mut options = ComrakOptions::default();
+        for name in names {
+            options.enable_extension(name.as_ref().parse()?);
+        }
+        Ok(options)
+    }
 
-    /// Enables the
-    /// [task list items extension](https://github.github.com/gfm/#task-list-items-extension-)
-    /// from the GFM spec.
+    /// Creates options from `COMRAK_*` variables, named after the `comrak` binary's flags, or
+    /// returns an error for the first name in `COMRAK_EXTENSIONS` which is not an extension.
+    /// Variables are looked up with `var`; the binary passes one reading the environment.
     ///
-    /// Note that the spec does not define the precise output, so only the bare essentials are
-    /// rendered.
+    /// * `COMRAK_EXTENSIONS` is a comma-separated list of extensions to enable.
+    /// * `COMRAK_HARDBREAKS`, `COMRAK_HARDBREAKS_PARAGRAPH_ONLY`, `COMRAK_GITHUB_PRE_LANG`,
+    ///   `COMRAK_LINE_NUMBERS` and `COMRAK_PRESERVE_TABS` turn their option on unless empty, `0`
+    ///   or `false`.
+    /// * `COMRAK_WIDTH` and `COMRAK_MAX_REFERENCES` are numbers.
+    ///
+    /// Options whose variable is unset, or is not a valid number, keep their defaults.
     ///
     /// ```
-    /// # use comrak::{markdown_to_html, ComrakOptions};
-    /// let mut options = ComrakOptions::default();
-    /// options.ext_tasklist = true;
-    /// assert_eq!(markdown_to_html("* [x] Done\n* [ ] Not done\n", &options),
-    ///            "<ul>\n<li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> Done</li>\n\
-    ///            <li><input type=\"checkbox\" disabled=\"\" /> Not done</li>\n</ul>\n");
+    /// # use comrak::ComrakOptions;
+    /// let options = ComrakOptions::from_vars(|name| match name {
+    ///     "COMRAK_EXTENSIONS" => Some("table, autolink".to_string()),
+    ///     _ => None,
+    /// }).unwrap();
+    /// assert!(options.ext_table && options.ext_autolink);
+    ///
+    /// let options = ComrakOptions::from_vars(|name| std::env::var(name).ok());
     /// ```
-    pub ext_tasklist: bool,
+    pub fn from_vars<F>(var: F) -> Result<ComrakOptions, UnknownExtension>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let flag = |name: &str| match var(name) {
+            Some(value) => !value.is_empty() && value != "0" && value != "false",
+            None => false,
+        };
+        let number = |name: &str| var(name).and_then(|v| v.trim().parse().ok());
+
+        let extensions = var("COMRAK_EXTENSIONS").unwrap_or_default();
+        let names = extensions
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<_>>();
+
+        let mut options = ComrakOptions::with_extensions(&names)?;
+        options.hardbreaks = flag("COMRAK_HARDBREAKS");
+        options.hardbreaks_paragraph_only = flag("COMRAK_HARDBREAKS_PARAGRAPH_ONLY");
+        options.github_pre_lang = flag("COMRAK_GITHUB_PRE_LANG");
+        options.code_line_numbers = flag("COMRAK_LINE_NUMBERS");
+        options.preserve_tabs = flag("COMRAK_PRESERVE_TABS");
+        options.width = number("COMRAK_WIDTH").unwrap_or(options.width);
+        options.max_references = number("COMRAK_MAX_REFERENCES").unwrap_or(options.max_references);
+        Ok(options)
+    }
 
-    /// Enables the superscript Comrak extension.
+    /// Enables the given extension.
     ///
     /// ```
-    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// # use comrak::{markdown_to_html, ComrakOptions, Extension};
     /// let mut options = ComrakOptions::default();
-    /// options.ext_superscript = true;
+    /// options.enable_extension("superscript".parse::<Extension>().unwrap());
     /// assert_eq!(markdown_to_html("e = mc^2^.\n", &options),
     ///            "<p>e = mc<sup>2</sup>.</p>\n");
     /// ```
-    pub ext_superscript: bool,
+    pub fn enable_extension(&mut self, extension: Extension) {
+        match extension {
+            Extension::Strikethrough => self.ext_strikethrough = true,
+            Extension::Tagfilter => self.ext_tagfilter = true,
+            Extension::Table => self.ext_table = true,
+            Extension::Autolink => self.ext_autolink = true,
+            Extension::Tasklist => self.ext_tasklist = true,
+            Extension::Superscript => self.ext_superscript = true,
+            Extension::Collapsible => self.ext_collapsible = true,
+            Extension::FencedDivs => self.ext_fenced_divs = true,
+            Extension::ColumnBlocks => self.ext_column_blocks = true,
+            Extension::AutolinkPhone => self.ext_autolink_phone = true,
+            Extension::HtmlSections => self.ext_html_sections = true,
+            Extension::TasklistNesting => self.ext_tasklist_nesting = true,
+            Extension::CodeBlockAttrs => self.ext_code_block_attrs = true,
+            Extension::Citations => self.ext_citations = true,
+            Extension::SmartDashes => self.ext_smart_dashes = true,
+            Extension::BlockMacros => self.ext_block_macros = true,
+            Extension::InlineMacros => self.ext_inline_macros = true,
+            Extension::RawAttributePassthrough => self.ext_raw_attribute_passthrough = true,
+            Extension::Details => self.ext_details = true,
+            Extension::Mentions => self.ext_mentions = true,
+            Extension::Hashtags => self.ext_hashtags = true,
+            Extension::Abbreviations => self.ext_abbreviations = true,
+            Extension::Highlight => self.ext_highlight = true,
+            Extension::Footnotes => self.ext_footnotes = true,
+            Extension::Underline => self.ext_underline = true,
+        }
+    }
+
+    // The settings `gfm_quirks` turns on, whatever their own values.
+
+    pub(crate) fn ext_strikethrough(&self) -> bool {
+        self.ext_strikethrough || self.gfm_quirks
+    }
+
+    pub(crate) fn ext_tagfilter(&self) -> bool {
+        self.ext_tagfilter || self.gfm_quirks
+    }
+
+    pub(crate) fn ext_table(&self) -> bool {
+        self.ext_table || self.gfm_quirks
+    }
+
+    pub(crate) fn ext_autolink(&self) -> bool {
+        self.ext_autolink || self.gfm_quirks
+    }
+
+    pub(crate) fn ext_tasklist(&self) -> bool {
+        self.ext_tasklist || self.gfm_quirks
+    }
+
+    pub(crate) fn github_pre_lang(&self) -> bool {
+        self.github_pre_lang || self.gfm_quirks
+    }
+}
+
+/// An extension which may be enabled in `ComrakOptions`, by the name used on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Extension {
+    /// Enables `ext_strikethrough`.
+    Strikethrough,
+
+    /// Enables `ext_tagfilter`.
+    Tagfilter,
+
+    /// Enables `ext_table`.
+    Table,
+
+    /// Enables `ext_autolink`.
+    Autolink,
+
+    /// Enables `ext_tasklist`.
+    Tasklist,
+
+    /// Enables `ext_superscript`.
+    Superscript,
+
+    /// Enables `ext_collapsible`.
+    Collapsible,
+
+    /// Enables `ext_fenced_divs`.
+    FencedDivs,
+
+    /// Enables `ext_column_blocks`.
+    ColumnBlocks,
+
+    /// Enables `ext_autolink_phone`.
+    AutolinkPhone,
+
+    /// Enables `ext_html_sections`.
+    HtmlSections,
+
+    /// Enables `ext_tasklist_nesting`.
+    TasklistNesting,
+
+    /// Enables `ext_code_block_attrs`.
+    CodeBlockAttrs,
+
+    /// Enables `ext_citations`.
+    Citations,
+
+    /// Enables `ext_smart_dashes`.
+    SmartDashes,
+
+    /// Enables `ext_block_macros`.
+    BlockMacros,
+
+    /// Enables `ext_inline_macros`.
+    InlineMacros,
+
+    /// Enables `ext_raw_attribute_passthrough`.
+    RawAttributePassthrough,
+
+    /// Enables `ext_details`.
+    Details,
+
+    /// Enables `ext_mentions`.
+    Mentions,
+
+    /// Enables `ext_hashtags`.
+    Hashtags,
+
+    /// Enables `ext_abbreviations`.
+    Abbreviations,
+
+    /// Enables `ext_highlight`.
+    Highlight,
+
+    /// Enables `ext_footnotes`.
+    Footnotes,
+
+    /// Enables `ext_underline`.
+    Underline,
 }
 
+static EXTENSIONS: [Extension; 25] = [
+    Extension::Strikethrough,
+    Extension::Tagfilter,
+    Extension::Table,
+    Extension::Autolink,
+    Extension::Tasklist,
+    Extension::Superscript,
+    Extension::Collapsible,
+    Extension::FencedDivs,
+    Extension::ColumnBlocks,
+    Extension::AutolinkPhone,
+    Extension::HtmlSections,
+    Extension::TasklistNesting,
+    Extension::CodeBlockAttrs,
+    Extension::Citations,
+    Extension::SmartDashes,
+    Extension::BlockMacros,
+    Extension::InlineMacros,
+    Extension::RawAttributePassthrough,
+    Extension::Details,
+    Extension::Mentions,
+    Extension::Hashtags,
+    Extension::Abbreviations,
+    Extension::Highlight,
+    Extension::Footnotes,
+    Extension::Underline,
+];
+
+impl Extension {
+    /// All the extensions, in the order they are listed on the command line.
+    pub fn all() -> &'static [Extension] {
+        &EXTENSIONS
+    }
+
+    /// The name of the extension, as accepted by `FromStr`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Extension::Strikethrough => "strikethrough",
+            Extension::Tagfilter => "tagfilter",
+            Extension::Table => "table",
+            Extension::Autolink => "autolink",
+            Extension::Tasklist => "tasklist",
+            Extension::Superscript => "superscript",
+            Extension::Collapsible => "collapsible",
+            Extension::FencedDivs => "fenced-divs",
+            Extension::ColumnBlocks => "column-blocks",
+            Extension::AutolinkPhone => "autolink-phone",
+            Extension::HtmlSections => "html-sections",
+            Extension::TasklistNesting => "tasklist-nesting",
+            Extension::CodeBlockAttrs => "code-block-attrs",
+            Extension::Citations => "citations",
+            Extension::SmartDashes => "smart-dashes",
+            Extension::BlockMacros => "block-macros",
+            Extension::InlineMacros => "inline-macros",
+            Extension::RawAttributePassthrough => "raw-attribute-passthrough",
+            Extension::Details => "details",
+            Extension::Mentions => "mentions",
+            Extension::Hashtags => "hashtags",
+            Extension::Abbreviations => "abbreviations",
+            Extension::Highlight => "highlight",
+            Extension::Footnotes => "footnotes",
+            Extension::Underline => "underline",
+        }
+    }
+}
+
+impl fmt::Display for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Extension {
+    type Err = UnknownExtension;
+
+    fn from_str(s: &str) -> Result<Extension, UnknownExtension> {
+        match s {
+            "strikethrough" => Ok(Extension::Strikethrough),
+            "tagfilter" => Ok(Extension::Tagfilter),
+            "table" => Ok(Extension::Table),
+            "autolink" => Ok(Extension::Autolink),
+            "tasklist" => Ok(Extension::Tasklist),
+            "superscript" => Ok(Extension::Superscript),
+            "collapsible" => Ok(Extension::Collapsible),
+            "fenced-divs" => Ok(Extension::FencedDivs),
+            "column-blocks" => Ok(Extension::ColumnBlocks),
+            "autolink-phone" => Ok(Extension::AutolinkPhone),
+            "html-sections" => Ok(Extension::HtmlSections),
+            "tasklist-nesting" => Ok(Extension::TasklistNesting),
+            "code-block-attrs" => Ok(Extension::CodeBlockAttrs),
+            "citations" => Ok(Extension::Citations),
+            "smart-dashes" => Ok(Extension::SmartDashes),
+            "block-macros" => Ok(Extension::BlockMacros),
+            "inline-macros" => Ok(Extension::InlineMacros),
+            "raw-attribute-passthrough" => Ok(Extension::RawAttributePassthrough),
+            "details" => Ok(Extension::Details),
+            "mentions" => Ok(Extension::Mentions),
+            "hashtags" => Ok(Extension::Hashtags),
+            "abbreviations" => Ok(Extension::Abbreviations),
+            "highlight" => Ok(Extension::Highlight),
+            "footnotes" => Ok(Extension::Footnotes),
+            "underline" => Ok(Extension::Underline),
+            _ => Err(UnknownExtension(s.to_string())),
+        }
+    }
+}
+
+/// The error returned when parsing an unknown extension name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownExtension(pub String);
+
+impl fmt::Display for UnknownExtension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown extension: {}", self.0)
+    }
+}
+
+impl Error for UnknownExtension {}
+
 
 #[derive(Clone)]
 pub struct Reference {
@@ -211,6 +1536,7 @@ impl<'a, 'o> Parser<'a, 'o> {
         Parser {
             arena: arena,
             refmap: HashMap::new(),
+            abbreviations: HashMap::new(),
             root: root,
             current: root,
             line_number: 0,
@@ -222,6 +1548,7 @@ impl<'a, 'o> Parser<'a, 'o> {
             blank: false,
             partially_consumed_tab: false,
             last_line_length: 0,
+            curline_end_column: None,
             linebuf: String::with_capacity(80),
             last_buffer_ended_with_cr: false,
             options: options,
@@ -233,7 +1560,7 @@ impl<'a, 'o> Parser<'a, 'o> {
         let buffer = s.as_bytes();
         let sz = buffer.len();
 
-        if self.last_buffer_ended_with_cr && buffer[i] == b'\n' {
+        if self.last_buffer_ended_with_cr && i < sz && buffer[i] == b'\n' {
             i += 1;
         }
         self.last_buffer_ended_with_cr = false;
@@ -269,12 +1596,15 @@ impl<'a, 'o> Parser<'a, 'o> {
             } else if eol < sz && buffer[eol] == b'\0' {
                 self.linebuf += &s[i..eol];
                 self.linebuf.push('\u{fffd}');
-                eol += 1;
             } else {
                 self.linebuf += &s[i..eol];
             }
 
             i = eol;
+            if i < sz && buffer[i] == b'\0' {
+                i += 1;
+                continue;
+            }
             if i < sz && buffer[i] == b'\r' {
                 i += 1;
                 if i == sz {
@@ -334,6 +1664,7 @@ impl<'a, 'o> Parser<'a, 'o> {
         self.column = 0;
         self.blank = false;
         self.partially_consumed_tab = false;
+        self.curline_end_column = Some(columns_without_eol(line));
 
         if self.line_number == 0 && line.len() >= 3 && line.chars().next().unwrap() == '\u{feff}' {
             self.offset += 3;
@@ -352,13 +1683,8 @@ impl<'a, 'o> Parser<'a, 'o> {
             }
         }
 
-        self.last_line_length = line.len();
-        if self.last_line_length > 0 && line.as_bytes()[self.last_line_length - 1] == b'\n' {
-            self.last_line_length -= 1;
-        }
-        if self.last_line_length > 0 && line.as_bytes()[self.last_line_length - 1] == b'\r' {
-            self.last_line_length -= 1;
-        }
+        self.last_line_length = columns_without_eol(line);
+        self.curline_end_column = None;
     }
 
     fn check_open_blocks(&mut self, line: &str, all_matched: &mut bool) -> Option<&'a AstNode<'a>> {
@@ -411,11 +1737,36 @@ impl<'a, 'o> Parser<'a, 'o> {
                         return (false, container, should_continue);
                     }
                 }
+                NodeValue::Container(..) => {
+                    if !self.parse_container_prefix(line, container, ast, &mut should_continue) {
+                        return (false, container, should_continue);
+                    }
+                }
+                NodeValue::DetailsBlock { .. } => {
+                    if !self.parse_details_prefix(line, container, ast, &mut should_continue) {
+                        return (false, container, should_continue);
+                    }
+                }
                 NodeValue::Paragraph => {
                     if self.blank {
                         return (false, container, should_continue);
                     }
                 }
+                NodeValue::FootnoteDefinition(..) => {
+                    if self.indent >= CODE_INDENT {
+                        self.advance_offset(line, CODE_INDENT, true);
+                    } else if !self.blank {
+                        return (false, container, should_continue);
+                    }
+                }
+                NodeValue::ColumnBlock(columns) => {
+                    if self.indent >= CODE_INDENT ||
+                        columns::row(&line[self.first_nonspace..]).map(|r| r.len()) !=
+                            Some(columns)
+                    {
+                        return (false, container, should_continue);
+                    }
+                }
                 NodeValue::Table(..) => {
                     if !table::matches(&line[self.first_nonspace..]) {
                         return (false, container, should_continue);
@@ -438,6 +1789,9 @@ impl<'a, 'o> Parser<'a, 'o> {
         let mut matched: usize = 0;
         let mut nl: NodeList = NodeList::default();
         let mut sc: scanners::SetextChar = scanners::SetextChar::Equals;
+        let mut macro_call: (&str, &str) = ("", "");
+        let mut footnote_label = "";
+        let mut container_opener = (NodeContainer::default(), vec![]);
         let mut maybe_lazy = match self.current.data.borrow().value {
             NodeValue::Paragraph => true,
             _ => false,
@@ -445,7 +1799,8 @@ impl<'a, 'o> Parser<'a, 'o> {
 
         while match container.data.borrow().value {
             NodeValue::CodeBlock(..) |
-            NodeValue::HtmlBlock(..) => false,
+            NodeValue::HtmlBlock(..) |
+            NodeValue::ColumnBlock(..) => false,
             _ => true,
         }
         {
@@ -453,14 +1808,14 @@ impl<'a, 'o> Parser<'a, 'o> {
             let indented = self.indent >= CODE_INDENT;
 
             if !indented && line.as_bytes()[self.first_nonspace] == b'>' {
-                let blockquote_startpos = self.first_nonspace;
+                let start_column = self.first_nonspace_column + 1;
                 let offset = self.first_nonspace + 1 - self.offset;
                 self.advance_offset(line, offset, false);
                 if strings::is_space_or_tab(line.as_bytes()[self.offset]) {
                     self.advance_offset(line, 1, true);
                 }
                 *container =
-                    self.add_child(*container, NodeValue::BlockQuote, blockquote_startpos + 1);
+                    self.add_child(*container, NodeValue::BlockQuote, start_column);
             } else if !indented &&
                        unwrap_into(
                     scanners::atx_heading_start(&line[self.first_nonspace..]),
@@ -473,7 +1828,7 @@ impl<'a, 'o> Parser<'a, 'o> {
                 *container = self.add_child(
                     *container,
                     NodeValue::Heading(NodeHeading::default()),
-                    heading_startpos + 1,
+                    self.first_nonspace_column + 1,
                 );
 
                 let mut hashpos = line[self.first_nonspace..]
@@ -504,7 +1859,7 @@ impl<'a, 'o> Parser<'a, 'o> {
                 *container = self.add_child(
                     *container,
                     NodeValue::Heading(NodeHeading::default()),
-                    heading_startpos + 1,
+                    self.first_nonspace_column + 1,
                 );
 
                 let mut hashpos = line[self.first_nonspace..]
@@ -529,6 +1884,7 @@ impl<'a, 'o> Parser<'a, 'o> {
                 )
             {
                 let first_nonspace = self.first_nonspace;
+                let start_column = self.first_nonspace_column + 1;
                 let offset = self.offset;
                 let ncb = NodeCodeBlock {
                     fenced: true,
@@ -537,10 +1893,84 @@ impl<'a, 'o> Parser<'a, 'o> {
                     fence_offset: first_nonspace - offset,
                     info: String::with_capacity(10),
                     literal: String::with_capacity(80),
+                    classes: vec![],
+                    id: None,
+                    content_lines: None,
                 };
-                *container =
-                    self.add_child(*container, NodeValue::CodeBlock(ncb), first_nonspace + 1);
+                *container = self.add_child(*container, NodeValue::CodeBlock(ncb), start_column);
                 self.advance_offset(line, first_nonspace + matched - offset, false);
+            } else if !indented &&
+                       unwrap_into(
+                    scanners::open_container_fence(&line[self.first_nonspace..]),
+                    &mut matched,
+                ) &&
+                       unwrap_into(
+                    self.container_opener(&line[self.first_nonspace + matched..], matched),
+                    &mut container_opener,
+                )
+            {
+                let start_column = self.first_nonspace_column + 1;
+                let (nc, attributes) = mem::take(&mut container_opener);
+                *container = self.add_child(*container, NodeValue::Container(nc), start_column);
+                container.data.borrow_mut().attributes = attributes;
+                let adv = line.len() - 1 - self.offset;
+                self.advance_offset(line, adv, false);
+            } else if !indented && self.options.ext_details &&
+                       unwrap_into(
+                    scanners::open_details_fence(&line[self.first_nonspace..]),
+                    &mut matched,
+                )
+            {
+                let first_nonspace = self.first_nonspace;
+                let start_column = self.first_nonspace_column + 1;
+                let mut summary = line[first_nonspace + matched..].to_string();
+                strings::trim(&mut summary);
+
+                let mut ast = make_block(NodeValue::DetailsSummary, self.line_number, start_column);
+                ast.content = summary.clone();
+                ast.open = false;
+                ast.end_column = columns_without_eol(line);
+
+                *container =
+                    self.add_child(*container, NodeValue::DetailsBlock { summary }, start_column);
+                container.append(self.arena.alloc(Node::new(RefCell::new(ast))));
+                let adv = line.len() - 1 - self.offset;
+                self.advance_offset(line, adv, false);
+            } else if !indented && self.options.ext_column_blocks &&
+                       columns::row(&line[self.first_nonspace..]).is_some()
+            {
+                let columns = columns::row(&line[self.first_nonspace..]).unwrap().len();
+                let start_column = self.first_nonspace_column + 1;
+                *container =
+                    self.add_child(*container, NodeValue::ColumnBlock(columns), start_column);
+            } else if !indented && self.options.ext_block_macros &&
+                       unwrap_into(block_macro(&line[self.first_nonspace..]), &mut macro_call)
+            {
+                let (name, args) = macro_call;
+                let offset = self.first_nonspace_column + 1;
+                *container = self.add_child(
+                    *container,
+                    NodeValue::BlockMacro {
+                        name: name.to_string(),
+                        args: args.to_string(),
+                    },
+                    offset,
+                );
+                let adv = line.len() - 1 - self.offset;
+                self.advance_offset(line, adv, false);
+            } else if !indented && self.options.ext_footnotes &&
+                       unwrap_into_2(
+                    footnote_definition(&line[self.first_nonspace..]),
+                    &mut footnote_label,
+                    &mut matched,
+                )
+            {
+                let label = footnote_label.to_string();
+                let start_column = self.first_nonspace_column + 1;
+                let offset = self.first_nonspace + matched - self.offset;
+                *container =
+                    self.add_child(*container, NodeValue::FootnoteDefinition(label), start_column);
+                self.advance_offset(line, offset, false);
             } else if !indented &&
                        (unwrap_into(
                     scanners::html_block_start(&line[self.first_nonspace..]),
@@ -556,9 +1986,9 @@ impl<'a, 'o> Parser<'a, 'o> {
                                 }
                             })
             {
-                let offset = self.first_nonspace + 1;
+                let offset = self.first_nonspace_column + 1;
                 let nhb = NodeHtmlBlock {
-                    block_type: matched as u8,
+                    block_type: HtmlBlockType::try_from(matched as u8).unwrap(),
                     literal: String::with_capacity(10),
                 };
 
@@ -594,7 +2024,7 @@ impl<'a, 'o> Parser<'a, 'o> {
                            }
                        }
             {
-                let offset = self.first_nonspace + 1;
+                let offset = self.first_nonspace_column + 1;
                 *container = self.add_child(*container, NodeValue::ThematicBreak, offset);
                 let adv = line.len() - 1 - self.offset;
                 self.advance_offset(line, adv, false);
@@ -603,6 +2033,11 @@ impl<'a, 'o> Parser<'a, 'o> {
                                NodeValue::List(..) => true,
                                _ => false,
                            }) &&
+                       !(self.options.lists_never_interrupt_paragraphs &&
+                             match container.data.borrow().value {
+                                 NodeValue::Paragraph => !in_list_item(container),
+                                 _ => false,
+                             }) &&
                        unwrap_into_2(
                     parse_list_marker(
                         line,
@@ -642,7 +2077,7 @@ impl<'a, 'o> Parser<'a, 'o> {
 
                 nl.marker_offset = self.indent;
 
-                let offset = self.first_nonspace + 1;
+                let offset = self.first_nonspace_column + 1;
                 if match container.data.borrow().value {
                     NodeValue::List(ref mnl) => !lists_match(&nl, mnl),
                     _ => true,
@@ -651,7 +2086,7 @@ impl<'a, 'o> Parser<'a, 'o> {
                     *container = self.add_child(*container, NodeValue::List(nl), offset);
                 }
 
-                let offset = self.first_nonspace + 1;
+                let offset = self.first_nonspace_column + 1;
                 *container = self.add_child(*container, NodeValue::Item(nl), offset);
             } else if indented && !maybe_lazy && !self.blank {
                 self.advance_offset(line, CODE_INDENT, true);
@@ -662,11 +2097,14 @@ impl<'a, 'o> Parser<'a, 'o> {
                     fence_offset: 0,
                     info: String::new(),
                     literal: String::with_capacity(80),
+                    classes: vec![],
+                    id: None,
+                    content_lines: None,
                 };
-                let offset = self.offset + 1;
+                let offset = self.column + 1;
                 *container = self.add_child(*container, NodeValue::CodeBlock(ncb), offset);
             } else {
-                let new_container = if !indented && self.options.ext_table {
+                let new_container = if !indented && self.options.ext_table() {
                     table::try_opening_block(self, *container, line)
                 } else {
                     None
@@ -805,17 +2243,124 @@ impl<'a, 'o> Parser<'a, 'o> {
             self.advance_offset(line, 1, true);
             i -= 1;
         }
-        true
+        true
+    }
+
+    fn parse_container_prefix(
+        &mut self,
+        line: &str,
+        container: &'a AstNode<'a>,
+        ast: &mut Ast,
+        should_continue: &mut bool,
+    ) -> bool {
+        let fence_length = match ast.value {
+            NodeValue::Container(ref nc) => nc.fence_length,
+            _ => unreachable!(),
+        };
+
+        let matched = if self.indent <= 3 {
+            scanners::close_container_fence(&line[self.first_nonspace..]).unwrap_or(0)
+        } else {
+            0
+        };
+
+        if matched < fence_length || closing_fence_belongs_to_child(container, matched) {
+            return true;
+        }
+
+        self.close_fenced_block(line, container, ast, matched, should_continue)
+    }
+
+    fn parse_details_prefix(
+        &mut self,
+        line: &str,
+        container: &'a AstNode<'a>,
+        ast: &mut Ast,
+        should_continue: &mut bool,
+    ) -> bool {
+        let matched = if self.indent <= 3 {
+            scanners::close_details_fence(&line[self.first_nonspace..]).unwrap_or(0)
+        } else {
+            0
+        };
+
+        if matched == 0 || details_fence_belongs_to_child(container) {
+            return true;
+        }
+
+        self.close_fenced_block(line, container, ast, matched, should_continue)
+    }
+
+    /// Closes a container at its closing fence of length `matched`, along with any blocks still
+    /// open within it.
+    fn close_fenced_block(
+        &mut self,
+        line: &str,
+        container: &'a AstNode<'a>,
+        ast: &mut Ast,
+        matched: usize,
+        should_continue: &mut bool,
+    ) -> bool {
+        let mut open_children = vec![];
+        let mut ch = container.last_child();
+        while let Some(child) = ch {
+            if !child.data.borrow().open {
+                break;
+            }
+            open_children.push(child);
+            ch = child.last_child();
+        }
+        for child in open_children.into_iter().rev() {
+            self.finalize(child);
+        }
+
+        *should_continue = false;
+        let offset = self.first_nonspace + matched - self.offset;
+        self.advance_offset(line, offset, false);
+        self.current = self.finalize_borrowed(container, ast).unwrap();
+        false
+    }
+
+    /// Parses the text after a container's opening fence into the container and its attributes,
+    /// or returns `None` if it opens no enabled container.  A `details` container with
+    /// `ext_collapsible` takes any text after its name as the summary.  A fenced div takes either
+    /// a single class name or an attribute block such as `{.note #intro}`, and nothing else.
+    fn container_opener(
+        &self,
+        rest: &str,
+        fence_length: usize,
+    ) -> Option<(NodeContainer, Vec<(String, String)>)> {
+        let rest = strings::trim_slice(rest);
+        let name = container_name(rest);
+        let mut nc = NodeContainer {
+            fence_length,
+            ..NodeContainer::default()
+        };
+        if self.options.ext_collapsible && name == "details" {
+            nc.name = name.to_string();
+            nc.info = strings::trim_slice(&rest[name.len()..]).to_string();
+            return Some((nc, vec![]));
+        }
+        if !self.options.ext_fenced_divs {
+            return None;
+        }
+
+        if rest.starts_with('{') && rest.ends_with('}') {
+            let attributes = attribute_block(&rest[1..rest.len() - 1])?;
+            Some((nc, attributes))
+        } else if name.len() == rest.len() {
+            nc.name = name.to_string();
+            Some((nc, vec![]))
+        } else {
+            None
+        }
     }
 
-    fn parse_html_block_prefix(&mut self, t: u8) -> bool {
+    fn parse_html_block_prefix(&mut self, t: HtmlBlockType) -> bool {
         match t {
-            1 | 2 | 3 | 4 | 5 => true,
-            6 | 7 => !self.blank,
-            _ => {
-                assert!(false);
-                false
-            }
+            HtmlBlockType::BlockLevelTag |
+            HtmlBlockType::CompleteTag => !self.blank,
+            _ => true,
         }
     }
 
@@ -853,7 +2398,10 @@ impl<'a, 'o> Parser<'a, 'o> {
             match container.data.borrow().value {
                 NodeValue::BlockQuote |
                 NodeValue::Heading(..) |
-                NodeValue::ThematicBreak => false,
+                NodeValue::ThematicBreak |
+                NodeValue::Container(..) |
+                NodeValue::DetailsBlock { .. } |
+                NodeValue::FootnoteDefinition(..) => false,
                 NodeValue::CodeBlock(ref ncb) => !ncb.fenced,
                 NodeValue::Item(..) => {
                     container.first_child().is_some() ||
@@ -894,13 +2442,15 @@ impl<'a, 'o> Parser<'a, 'o> {
                 AddTextResult::HtmlBlock(block_type) => {
                     self.add_line(container, line);
 
+                    let rest = &line[self.first_nonspace..];
                     let matches_end_condition = match block_type {
-                        1 => scanners::html_block_end_1(&line[self.first_nonspace..]),
-                        2 => scanners::html_block_end_2(&line[self.first_nonspace..]),
-                        3 => scanners::html_block_end_3(&line[self.first_nonspace..]),
-                        4 => scanners::html_block_end_4(&line[self.first_nonspace..]),
-                        5 => scanners::html_block_end_5(&line[self.first_nonspace..]),
-                        _ => false,
+                        HtmlBlockType::ScriptPreStyle => scanners::html_block_end_1(rest),
+                        HtmlBlockType::Comment => scanners::html_block_end_2(rest),
+                        HtmlBlockType::ProcessingInstruction => scanners::html_block_end_3(rest),
+                        HtmlBlockType::Declaration => scanners::html_block_end_4(rest),
+                        HtmlBlockType::CdataSection => scanners::html_block_end_5(rest),
+                        HtmlBlockType::BlockLevelTag |
+                        HtmlBlockType::CompleteTag => false,
                     };
 
                     if matches_end_condition {
@@ -921,7 +2471,7 @@ impl<'a, 'o> Parser<'a, 'o> {
                         self.advance_offset(&line, count, false);
                         self.add_line(container, &line);
                     } else {
-                        let start_column = self.first_nonspace + 1;
+                        let start_column = self.first_nonspace_column + 1;
                         container = self.add_child(container, NodeValue::Paragraph, start_column);
                         let count = self.first_nonspace - self.offset;
                         self.advance_offset(line, count, false);
@@ -939,9 +2489,18 @@ impl<'a, 'o> Parser<'a, 'o> {
         assert!(ast.open);
         if self.partially_consumed_tab {
             self.offset += 1;
-            let chars_to_tab = TAB_STOP - (self.column % TAB_STOP);
-            for _ in 0..chars_to_tab {
-                ast.content.push(' ');
+            let preserve = self.options.preserve_tabs &&
+                match ast.value {
+                    NodeValue::CodeBlock(..) => true,
+                    _ => false,
+                };
+            if preserve {
+                ast.content.push('\t');
+            } else {
+                let chars_to_tab = TAB_STOP - (self.column % TAB_STOP);
+                for _ in 0..chars_to_tab {
+                    ast.content.push(' ');
+                }
             }
         }
         if self.offset < line.len() {
@@ -950,14 +2509,128 @@ impl<'a, 'o> Parser<'a, 'o> {
     }
 
     pub fn finish(&mut self) -> &'a AstNode<'a> {
+        self.finish_blocks();
+        self.process_inlines();
+        if self.options.ext_footnotes {
+            self.process_footnotes();
+        }
+        self.postprocess();
+        if self.options.ext_citations {
+            self.append_bibliography();
+        }
+        if self.options.optimize_simple_paragraphs {
+            simplify_paragraphs(self.root);
+        }
+        self.root
+    }
+
+    fn postprocess(&mut self) {
+        self.postprocess_text_nodes(self.root);
+        if self.options.ext_tasklist() && self.options.ext_tasklist_nesting {
+            postprocess_task_items(self.root);
+        }
+    }
+
+    fn append_bibliography(&mut self) {
+        let bibliography = match self.options.bibliography {
+            Some(ref bibliography) => bibliography,
+            None => return,
+        };
+
+        let mut keys: Vec<String> = vec![];
+        for node in self.root.descendants() {
+            if let NodeValue::Cite(ref key) = node.data.borrow().value {
+                if bibliography.contains_key(key) && !keys.contains(key) {
+                    keys.push(key.clone());
+                }
+            }
+        }
+
+        if !keys.is_empty() {
+            let node = inlines::make_inline(self.arena, NodeValue::Bibliography(keys));
+            self.root.append(node);
+        }
+    }
+
+    // Keeps the first definition of each footnote, turns references to undefined footnotes back
+    // into text, and moves the definitions to the end of the document: first those referenced, in
+    // order of first reference, then the rest in document order.  With per-section numbering,
+    // the referenced definitions go to the end of the section first referring to them instead.
+    fn process_footnotes(&mut self) {
+        let mut definitions: HashMap<String, &'a AstNode<'a>> = HashMap::new();
+        let mut unreferenced = vec![];
+        for node in self.root.descendants().collect::<Vec<_>>() {
+            let label = match node.data.borrow().value {
+                NodeValue::FootnoteDefinition(ref label) => {
+                    strings::normalize_reference_label(label)
+                }
+                _ => continue,
+            };
+            match definitions.entry(label) {
+                Entry::Occupied(..) => node.detach(),
+                Entry::Vacant(entry) => {
+                    entry.insert(node);
+                    unreferenced.push(node);
+                }
+            }
+        }
+
+        // The definitions first referenced in each section, and the heading which ends it.
+        let mut referenced: Vec<&'a AstNode<'a>> = vec![];
+        let mut sections: Vec<(Vec<&'a AstNode<'a>>, Option<&'a AstNode<'a>>)> =
+            vec![(vec![], None)];
+        for block in self.root.children() {
+            if starts_footnote_section(block, self.options.footnote_numbering) {
+                sections.last_mut().unwrap().1 = Some(block);
+                sections.push((vec![], None));
+            }
+            for node in block.descendants() {
+                let mut ast = node.data.borrow_mut();
+                let label = match ast.value {
+                    NodeValue::FootnoteReference(ref label) => label.clone(),
+                    _ => continue,
+                };
+                match definitions.get(&strings::normalize_reference_label(&label)) {
+                    Some(&definition) => {
+                        if !referenced.iter().any(|r| r.same_node(definition)) {
+                            referenced.push(definition);
+                            sections.last_mut().unwrap().0.push(definition);
+                        }
+                    }
+                    None => ast.value = NodeValue::Text(format!("[^{}]", label)),
+                }
+            }
+        }
+
+        unreferenced.retain(|u| !referenced.iter().any(|r| r.same_node(u)));
+        for (section, end) in sections {
+            for definition in section {
+                definition.detach();
+                match end {
+                    Some(heading) => heading.insert_before(definition),
+                    None => self.root.append(definition),
+                }
+            }
+        }
+        for definition in unreferenced {
+            definition.detach();
+            self.root.append(definition);
+        }
+    }
+
+    fn finish_raw(&mut self) -> &'a AstNode<'a> {
+        self.finish_blocks();
+        self.root
+    }
+
+    // Processes any unterminated last line and closes every open block.
+    fn finish_blocks(&mut self) {
         if !self.linebuf.is_empty() {
-            let linebuf = mem::replace(&mut self.linebuf, String::new());
+            let linebuf = mem::take(&mut self.linebuf);
             self.process_line(&linebuf);
         }
 
         self.finalize_document();
-        self.postprocess_text_nodes(self.root);
-        self.root
     }
 
     fn finalize_document(&mut self) {
@@ -966,7 +2639,6 @@ impl<'a, 'o> Parser<'a, 'o> {
         }
 
         self.finalize(self.root);
-        self.process_inlines();
     }
 
     fn finalize(&mut self, node: &'a AstNode<'a>) -> Option<&'a AstNode<'a>> {
@@ -981,29 +2653,37 @@ impl<'a, 'o> Parser<'a, 'o> {
         assert!(ast.open);
         ast.open = false;
 
-        if !self.linebuf.is_empty() {
-            ast.end_line = self.line_number;
-            ast.end_column = self.last_line_length;
-        } else if match ast.value {
-                   NodeValue::Document => true,
+        let curline_end_column = match self.curline_end_column {
+            Some(curline_end_column) => curline_end_column,
+            None => {
+                // End of input; the line number has not been incremented.
+                ast.end_line = self.line_number;
+                ast.end_column = self.last_line_length;
+                return self.finalize_value(node, ast);
+            }
+        };
+
+        if match ast.value {
+                   NodeValue::Document |
+                   NodeValue::Container(..) |
+                   NodeValue::DetailsBlock { .. } |
+                   NodeValue::Column => true,
                    NodeValue::CodeBlock(ref ncb) => ncb.fenced,
                    NodeValue::Heading(ref nh) => nh.setext,
                    _ => false,
                }
         {
             ast.end_line = self.line_number;
-            ast.end_column = self.linebuf.len();
-            if ast.end_column > 0 && self.linebuf.as_bytes()[ast.end_column - 1] == b'\n' {
-                ast.end_column -= 1;
-            }
-            if ast.end_column > 0 && self.linebuf.as_bytes()[ast.end_column - 1] == b'\r' {
-                ast.end_column -= 1;
-            }
+            ast.end_column = curline_end_column;
         } else {
             ast.end_line = self.line_number - 1;
             ast.end_column = self.last_line_length;
         }
 
+        self.finalize_value(node, ast)
+    }
+
+    fn finalize_value(&mut self, node: &'a AstNode<'a>, ast: &mut Ast) -> Option<&'a AstNode<'a>> {
         let content = &mut ast.content;
         let mut pos = 0;
 
@@ -1011,17 +2691,52 @@ impl<'a, 'o> Parser<'a, 'o> {
 
         match ast.value {
             NodeValue::Paragraph => {
-                while !content.is_empty() && content.as_bytes()[0] == b'[' &&
-                    unwrap_into(self.parse_reference_inline(content), &mut pos)
-                {
+                let mut line = ast.start_line;
+                let mut def = None;
+                loop {
+                    let keep = if content.starts_with('[') &&
+                        unwrap_into_2(self.parse_reference_inline(content), &mut pos, &mut def)
+                    {
+                        self.options.keep_reference_definitions
+                    } else if self.options.ext_abbreviations && content.starts_with("*[") &&
+                               unwrap_into_2(
+                                   self.parse_abbreviation_def(content),
+                                   &mut pos,
+                                   &mut def,
+                               )
+                    {
+                        true
+                    } else {
+                        break;
+                    };
+
+                    let start_line = line;
+                    line += content[..pos].matches('\n').count() as u32;
+                    if keep {
+                        let value = def.take().unwrap();
+                        let mut def_ast = make_block(value, start_line, ast.start_column);
+                        def_ast.end_line = line - 1;
+                        def_ast.open = false;
+                        node.insert_before(self.arena.alloc(Node::new(RefCell::new(def_ast))));
+                    }
                     while pos > 0 {
                         pos -= content.remove(0).len_utf8();
                     }
                 }
+                if self.options.ext_raw_attribute_passthrough {
+                    if let Some(attributes) = trailing_attributes(content, true) {
+                        ast.attributes = attributes;
+                    }
+                }
                 if strings::is_blank(content) {
                     node.detach();
                 }
             }
+            NodeValue::Heading(..) if self.options.ext_raw_attribute_passthrough => {
+                if let Some(attributes) = trailing_attributes(content, false) {
+                    ast.attributes = attributes;
+                }
+            }
             NodeValue::CodeBlock(ref mut ncb) => {
                 if !ncb.fenced {
                     strings::remove_trailing_blank_lines(content);
@@ -1039,7 +2754,14 @@ impl<'a, 'o> Parser<'a, 'o> {
                     let mut tmp = entity::unescape_html(&content[..pos]);
                     strings::trim(&mut tmp);
                     strings::unescape(&mut tmp);
-                    ncb.info = tmp;
+                    match code_block_attrs(&tmp) {
+                        Some((lang, classes, id)) if self.options.ext_code_block_attrs => {
+                            ncb.info = lang;
+                            ncb.classes = classes;
+                            ncb.id = id;
+                        }
+                        _ => ncb.info = tmp,
+                    }
 
                     if content.as_bytes()[pos] == b'\r' {
                         pos += 1;
@@ -1052,6 +2774,18 @@ impl<'a, 'o> Parser<'a, 'o> {
                         pos -= content.remove(0).len_utf8();
                     }
                 }
+
+                let bytes = content.as_bytes();
+                let lines = (0..bytes.len())
+                    .filter(|&i| {
+                        bytes[i] == b'\n' || (bytes[i] == b'\r' && bytes.get(i + 1) != Some(&b'\n'))
+                    })
+                    .count() as u32;
+                if lines > 0 {
+                    let first = ast.start_line + if ncb.fenced { 1 } else { 0 };
+                    ncb.content_lines = Some((first, first + lines - 1));
+                }
+
                 mem::swap(&mut ncb.literal, content);
                 content.clear();
             }
@@ -1059,6 +2793,10 @@ impl<'a, 'o> Parser<'a, 'o> {
                 mem::swap(&mut nhb.literal, content);
                 content.clear();
             }
+            NodeValue::ColumnBlock(columns) => {
+                self.parse_columns(node, columns, content, ast.start_line, ast.start_column);
+                content.clear();
+            }
             NodeValue::List(ref mut nl) => {
                 nl.tight = true;
                 let mut ch = node.first_child();
@@ -1093,6 +2831,37 @@ impl<'a, 'o> Parser<'a, 'o> {
         parent
     }
 
+    fn parse_columns(
+        &mut self,
+        node: &'a AstNode<'a>,
+        columns: usize,
+        content: &str,
+        start_line: u32,
+        start_column: usize,
+    ) {
+        for source in columns::split(content, columns) {
+            let column = self.arena.alloc(Node::new(RefCell::new(
+                make_block(NodeValue::Column, start_line, start_column),
+            )));
+
+            // The column is only attached once parsed, as the column block is borrowed while it
+            // is being finalized.
+            let mut parser = Parser::new(self.arena, column, self.options);
+            parser.line_number = start_line - 1;
+            parser.feed(&source, true);
+            parser.finish_raw();
+            node.append(column);
+
+            for (label, reference) in parser.refmap {
+                if self.options.max_references == 0 ||
+                    self.refmap.len() < self.options.max_references
+                {
+                    self.refmap.entry(label).or_insert(reference);
+                }
+            }
+        }
+    }
+
     fn process_inlines(&mut self) {
         self.process_inlines_node(self.root);
     }
@@ -1154,7 +2923,8 @@ impl<'a, 'o> Parser<'a, 'o> {
                         }
                     }
                     NodeValue::Link(..) |
-                    NodeValue::Image(..) => {
+                    NodeValue::Image(..) |
+                    NodeValue::Abbreviation(..) => {
                         this_bracket = true;
                         break;
                     }
@@ -1171,15 +2941,56 @@ impl<'a, 'o> Parser<'a, 'o> {
     }
 
     fn postprocess_text_node(&mut self, node: &'a AstNode<'a>, text: &mut String) {
-        if self.options.ext_tasklist {
+        if self.options.ext_tasklist() {
             self.process_tasklist(node, text);
         }
 
-        if self.options.ext_autolink {
-            autolink::process_autolinks(self.arena, node, text);
+        if self.options.ext_autolink() && self.autolink_allowed(node) {
+            autolink::process_autolinks(
+                self.arena,
+                node,
+                text,
+                self.options.autolink_known_tlds.as_ref(),
+            );
             autolink::process_redditlinks(self.arena, node, text);
         }
 
+        if self.options.ext_autolink_phone {
+            autolink::process_phone_links(self.arena, node, text);
+        }
+
+        if self.options.ext_mentions {
+            let template = self.options.mention_url_template.as_ref().map_or("/users/{}", |t| t);
+            autolink::process_tagged_links(self.arena, node, text, b'@', template);
+        }
+
+        if self.options.ext_hashtags {
+            let template = self.options.hashtag_url_template.as_ref().map_or("/tags/{}", |t| t);
+            autolink::process_tagged_links(self.arena, node, text, b'#', template);
+        }
+
+        if self.options.ext_abbreviations && !self.abbreviations.is_empty() {
+            abbreviation::process_abbreviations(self.arena, node, text, &self.abbreviations);
+        }
+    }
+
+    fn autolink_allowed(&self, node: &'a AstNode<'a>) -> bool {
+        let contexts = match self.options.autolink_contexts {
+            Some(ref contexts) => contexts,
+            None => return true,
+        };
+
+        let block = match node.parent().and_then(nodes::containing_block) {
+            Some(block) => block,
+            None => return false,
+        };
+        let context = match block.data.borrow().value {
+            NodeValue::Paragraph => AutolinkContext::Paragraph,
+            NodeValue::Heading(..) => AutolinkContext::Heading,
+            NodeValue::TableCell => AutolinkContext::TableCell,
+            _ => return false,
+        };
+        contexts.contains(&context)
     }
 
     fn process_tasklist(&mut self, node: &'a AstNode<'a>, text: &mut String) {
@@ -1210,18 +3021,15 @@ impl<'a, 'o> Parser<'a, 'o> {
         *text = text[end..].to_string();
         let checkbox = inlines::make_inline(
             self.arena,
-            NodeValue::HtmlInline(
-                (if active {
-                     "<input type=\"checkbox\" disabled=\"\" checked=\"\" />"
-                 } else {
-                     "<input type=\"checkbox\" disabled=\"\" />"
-                 }).to_string(),
-            ),
+            NodeValue::TaskItem {
+                checked: active,
+                children_all_checked: None,
+            },
         );
         node.insert_before(checkbox);
     }
 
-    fn parse_reference_inline(&mut self, content: &str) -> Option<usize> {
+    fn parse_reference_inline(&mut self, content: &str) -> Option<(usize, Option<NodeValue>)> {
         let delimiter_arena = Arena::new();
         let mut subj = inlines::Subject::new(
             self.arena,
@@ -1276,20 +3084,48 @@ impl<'a, 'o> Parser<'a, 'o> {
             }
         }
 
+        let url = strings::clean_url(&url);
+        let title = strings::clean_title(&title);
+        let def = NodeValue::ReferenceDef {
+            label: lab.clone(),
+            url: url.clone(),
+            title: title.clone(),
+        };
+
         lab = strings::normalize_reference_label(&lab);
-        if !lab.is_empty() {
-            subj.refmap.entry(lab).or_insert(Reference {
-                url: strings::clean_url(&url),
-                title: strings::clean_title(&title),
-            });
+        if !lab.is_empty() &&
+            (self.options.max_references == 0 || subj.refmap.len() < self.options.max_references)
+        {
+            subj.refmap.entry(lab).or_insert(Reference { url, title });
+        }
+        Some((subj.pos, Some(def)))
+    }
+
+    fn parse_abbreviation_def(&mut self, content: &str) -> Option<(usize, Option<NodeValue>)> {
+        lazy_static! {
+            static ref ABBREVIATION_DEF: Regex =
+                Regex::new(r"\A\*\[([^\]\r\n]+)\]:[ \t]*([^\r\n]*)(?:\r\n|\r|\n|\z)").unwrap();
         }
-        Some(subj.pos)
+
+        let c = ABBREVIATION_DEF.captures(content)?;
+        let abbr = c.get(1).unwrap().as_str().trim();
+        if abbr.is_empty() {
+            return None;
+        }
+        let title = c.get(2).unwrap().as_str().trim_end();
+
+        self.abbreviations.entry(abbr.to_string()).or_insert_with(|| title.to_string());
+        let def = NodeValue::AbbreviationDef {
+            abbr: abbr.to_string(),
+            title: title.to_string(),
+        };
+        Some((c.get(0).unwrap().end(), Some(def)))
     }
 }
 
 enum AddTextResult {
     CodeBlock,
-    HtmlBlock(u8),
+    HtmlBlock(HtmlBlockType),
     Otherwise,
 }
 
@@ -1330,6 +3166,7 @@ fn parse_list_marker(
             },
         ));
     } else if isdigit(c) {
+        // At most nine digits are allowed, so `start` fits in a 32-bit `usize`.
         let mut start: usize = 0;
         let mut digits = 0;
 
@@ -1420,13 +3257,430 @@ fn unwrap_into_2<T, U>(tu: Option<(T, U)>, out_t: &mut T, out_u: &mut U) -> bool
     }
 }
 
+/// The number of columns `line` spans, excluding its line ending, with tabs advancing to the
+/// next tab stop as `find_first_nonspace` counts them.
+fn columns_without_eol(line: &str) -> usize {
+    let mut len = line.len();
+    if len > 0 && line.as_bytes()[len - 1] == b'\n' {
+        len -= 1;
+    }
+    if len > 0 && line.as_bytes()[len - 1] == b'\r' {
+        len -= 1;
+    }
+    line.as_bytes()[..len].iter().fold(0, |column, &c| if c == b'\t' {
+        column + TAB_STOP - column % TAB_STOP
+    } else {
+        column + 1
+    })
+}
+
+fn container_name(rest: &str) -> &str {
+    let end = rest.find(|c: char| c.is_whitespace()).unwrap_or(rest.len());
+    &rest[..end]
+}
+
+/// Removes an attribute block from the end of `content`, returning the attributes it gives.  The
+/// block must follow some other text, and be on a line of its own if `own_line` is set.
+fn trailing_attributes(content: &mut String, own_line: bool) -> Option<Vec<(String, String)>> {
+    let (start, end, attributes) = {
+        let trimmed = content.trim_end();
+        if !trimmed.ends_with('}') {
+            return None;
+        }
+        let start = trimmed.rfind('{')?;
+        let before = &trimmed[..start];
+        let separated = if own_line {
+            before.ends_with('\n')
+        } else {
+            before.ends_with([' ', '\t'])
+        };
+        if !separated || before.trim().is_empty() {
+            return None;
+        }
+        let attributes = attribute_block(&trimmed[start + 1..trimmed.len() - 1])?;
+        (before.trim_end().len(), trimmed.len(), attributes)
+    };
+
+    if own_line {
+        content.truncate(start);
+        content.push('\n');
+    } else {
+        content.drain(start..end);
+    }
+    Some(attributes)
+}
+
+/// Parses the contents of an attribute block such as `{#id .class key=value key2="quoted value"}`
+/// into name and value pairs, with the ID first and the classes combined into one attribute.
+fn attribute_block(block: &str) -> Option<Vec<(String, String)>> {
+    let mut id = None;
+    let mut classes = vec![];
+    let mut attributes = vec![];
+
+    let mut rest = block.trim_start();
+    while !rest.is_empty() {
+        let end;
+        if rest.starts_with('#') || rest.starts_with('.') {
+            end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            if end == 1 {
+                return None;
+            }
+            if rest.starts_with('#') {
+                id = Some(rest[1..end].to_string());
+            } else {
+                classes.push(rest[1..end].to_string());
+            }
+        } else {
+            let eq = rest.find('=')?;
+            let key = &rest[..eq];
+            if key.is_empty() ||
+                !key.bytes().all(|c| isalnum(c) || c == b'-' || c == b'_' || c == b':')
+            {
+                return None;
+            }
+
+            let value;
+            if rest[eq + 1..].starts_with('"') {
+                let close = eq + 2 + rest[eq + 2..].find('"')?;
+                value = &rest[eq + 2..close];
+                end = close + 1;
+                if !rest[end..].is_empty() && !rest[end..].starts_with(char::is_whitespace) {
+                    return None;
+                }
+            } else {
+                end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                value = &rest[eq + 1..end];
+            }
+
+            match key {
+                "id" => id = Some(value.to_string()),
+                "class" => classes.extend(value.split_whitespace().map(|c| c.to_string())),
+                _ => attributes.push((key.to_string(), value.to_string())),
+            }
+        }
+        rest = rest[end..].trim_start();
+    }
+
+    if !classes.is_empty() {
+        attributes.insert(0, ("class".to_string(), classes.join(" ")));
+    }
+    if let Some(id) = id {
+        attributes.insert(0, ("id".to_string(), id));
+    }
+    if attributes.is_empty() {
+        None
+    } else {
+        Some(attributes)
+    }
+}
+
+/// Splits a block macro line such as `@@include path/to/file` into its name and arguments.
+fn block_macro(line: &str) -> Option<(&str, &str)> {
+    if !line.starts_with("@@") {
+        return None;
+    }
+
+    let rest = &line[2..];
+    let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+        .unwrap_or(rest.len());
+    if end == 0 || !rest.as_bytes()[0].is_ascii_alphabetic() {
+        return None;
+    }
+    if !rest[end..].is_empty() && !rest[end..].starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some((&rest[..end], rest[end..].trim()))
+}
+
+// Matches the start of a footnote definition, `[^label]:` and any spaces or tabs following,
+// returning the label and the length matched.
+fn footnote_definition(line: &str) -> Option<(&str, usize)> {
+    lazy_static! {
+        static ref FOOTNOTE_DEFINITION: Regex = Regex::new(r"\A\[\^([^\]\[\s]+)\]:[ \t]*").unwrap();
+    }
+
+    let c = FOOTNOTE_DEFINITION.captures(line)?;
+    Some((c.get(1).unwrap().as_str(), c.get(0).unwrap().end()))
+}
+
+fn code_block_attrs(info: &str) -> Option<(String, Vec<String>, Option<String>)> {
+    if !info.starts_with('{') || !info.ends_with('}') {
+        return None;
+    }
+
+    let mut lang = None;
+    let mut classes = vec![];
+    let mut id = None;
+    for attr in info[1..info.len() - 1].split_whitespace() {
+        if attr.len() < 2 {
+            return None;
+        }
+        match attr.as_bytes()[0] {
+            b'.' if lang.is_none() => lang = Some(attr[1..].to_string()),
+            b'.' => classes.push(attr[1..].to_string()),
+            b'#' => id = Some(attr[1..].to_string()),
+            _ => return None,
+        }
+    }
+
+    Some((lang.unwrap_or_default(), classes, id))
+}
+
+fn closing_fence_belongs_to_child<'a>(container: &'a AstNode<'a>, fence_length: usize) -> bool {
+    let mut ch = container.last_child();
+    while let Some(child) = ch {
+        if !child.data.borrow().open {
+            break;
+        }
+        match child.data.borrow().value {
+            NodeValue::Container(ref nc) if nc.fence_length <= fence_length => return true,
+            NodeValue::CodeBlock(ref ncb) if ncb.fenced => return true,
+            NodeValue::HtmlBlock(..) => return true,
+            _ => (),
+        }
+        ch = child.last_child();
+    }
+    false
+}
+
+fn details_fence_belongs_to_child<'a>(container: &'a AstNode<'a>) -> bool {
+    let mut ch = container.last_child();
+    while let Some(child) = ch {
+        if !child.data.borrow().open {
+            break;
+        }
+        match child.data.borrow().value {
+            NodeValue::DetailsBlock { .. } |
+            NodeValue::HtmlBlock(..) => return true,
+            NodeValue::CodeBlock(ref ncb) if ncb.fenced => return true,
+            _ => (),
+        }
+        ch = child.last_child();
+    }
+    false
+}
+
+fn in_list_item<'a>(node: &'a AstNode<'a>) -> bool {
+    match node.parent() {
+        Some(parent) => match parent.data.borrow().value {
+            NodeValue::Item(..) => true,
+            _ => false,
+        },
+        None => false,
+    }
+}
+
 fn lists_match(list_data: &NodeList, item_data: &NodeList) -> bool {
     list_data.list_type == item_data.list_type && list_data.delimiter == item_data.delimiter &&
         list_data.bullet_char == item_data.bullet_char
 }
 
+/// How footnotes are numbered, for use with the `footnote_numbering` option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FootnoteNumbering {
+    /// One sequence of numbers for the whole document.
+    Global,
+
+    /// A sequence of numbers for each section, starting at each top-level heading of `level` or
+    /// a higher level.
+    PerSection {
+        /// The heading level which starts a section.
+        level: u8,
+    },
+}
+
+impl Default for FootnoteNumbering {
+    fn default() -> Self {
+        FootnoteNumbering::Global
+    }
+}
+
+/// Whether `block`, a top-level block, starts a new section for footnote numbering.
+pub(crate) fn starts_footnote_section<'a>(
+    block: &'a AstNode<'a>,
+    numbering: FootnoteNumbering,
+) -> bool {
+    let level = match numbering {
+        FootnoteNumbering::Global => return false,
+        FootnoteNumbering::PerSection { level } => level,
+    };
+    match block.data.borrow().value {
+        NodeValue::Heading(ref nh) => nh.level <= u32::from(level),
+        _ => false,
+    }
+}
+
+/// A Unicode normalization form, for use with the `unicode_normalization` option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalizationForm {
+    /// Canonical composition, which only joins characters with their combining marks.
+    Nfc,
+
+    /// Compatibility composition, which also folds compatibility characters such as ligatures
+    /// and full-width forms into their plain equivalents.
+    Nfkc,
+}
+
+/// A kind of block in which `ext_autolink` may apply, for use with the `autolink_contexts`
+/// option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutolinkContext {
+    /// Paragraphs.
+    Paragraph,
+
+    /// ATX and setext headings.
+    Heading,
+
+    /// Table cells.
+    TableCell,
+}
+
+/// A work which may be cited, for use with the `bibliography` option.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct BibEntry {
+    /// The full reference to the work, as it appears in the list of cited works.
+    pub text: String,
+}
+
+/// A function transforming link and image destinations, for use with the `link_resolver` option.
+/// It is called with the destination, title and plain text of each link, and returns the
+/// destination to render instead, if any.
+#[derive(Clone)]
+pub struct LinkResolver(Arc<ResolveFn>);
+
+type ResolveFn = dyn Fn(&str, &str, &str) -> Option<String> + Send + Sync;
+
+impl LinkResolver {
+    /// Wraps `f` for use as a link resolver.
+    pub fn new<F>(f: F) -> LinkResolver
+    where
+        F: Fn(&str, &str, &str) -> Option<String> + Send + Sync + 'static,
+    {
+        LinkResolver(Arc::new(f))
+    }
+
+    /// Returns the destination to render for a link to `url`, if it should be replaced.
+    pub fn resolve(&self, url: &str, title: &str, text: &str) -> Option<String> {
+        (self.0)(url, title, text)
+    }
+}
+
+impl fmt::Debug for LinkResolver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("LinkResolver")
+    }
+}
+
+/// A function rendering a macro from its arguments, for use with the `block_macro_handlers` and
+/// `inline_macro_handlers` options.  Its output is included in the HTML as is.
+#[derive(Clone)]
+pub struct MacroHandler(Arc<MacroFn>);
+
+type MacroFn = dyn Fn(&str) -> String + Send + Sync;
+
+impl MacroHandler {
+    /// Wraps `f` for use as a macro handler.
+    pub fn new<F>(f: F) -> MacroHandler
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        MacroHandler(Arc::new(f))
+    }
+
+    /// Renders a use of the macro with the given arguments.
+    pub fn render(&self, args: &str) -> String {
+        (self.0)(args)
+    }
+}
+
+impl fmt::Debug for MacroHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("MacroHandler")
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AutolinkType {
     URI,
     Email,
 }
+
+fn simplify_paragraphs<'a>(node: &'a AstNode<'a>) {
+    let mut ast = node.data.borrow_mut();
+    match ast.value {
+        NodeValue::Paragraph if ast.attributes.is_empty() => (),
+        NodeValue::Document |
+        NodeValue::BlockQuote |
+        NodeValue::List(..) |
+        NodeValue::Item(..) |
+        NodeValue::Container(..) |
+        NodeValue::DetailsBlock { .. } |
+        NodeValue::ColumnBlock(..) |
+        NodeValue::Column |
+        NodeValue::FootnoteDefinition(..) => {
+            drop(ast);
+            for child in node.children() {
+                simplify_paragraphs(child);
+            }
+            return;
+        }
+        _ => return,
+    }
+
+    let text = match node.first_child() {
+        Some(text) if text.next_sibling().is_none() => text,
+        _ => return,
+    };
+    let literal = match text.data.borrow_mut().value {
+        NodeValue::Text(ref mut literal) => mem::take(literal),
+        _ => return,
+    };
+    text.detach();
+    ast.value = NodeValue::SimpleParagraph(literal);
+}
+
+fn task_item<'a>(item: &'a AstNode<'a>) -> Option<&'a AstNode<'a>> {
+    let checkbox = item.first_child()?.first_child()?;
+    match checkbox.data.borrow().value {
+        NodeValue::TaskItem { .. } => Some(checkbox),
+        _ => None,
+    }
+}
+
+fn postprocess_task_items<'a>(node: &'a AstNode<'a>) {
+    for child in node.children() {
+        postprocess_task_items(child);
+    }
+
+    match node.data.borrow().value {
+        NodeValue::Item(..) => (),
+        _ => return,
+    }
+
+    let checkbox = match task_item(node) {
+        Some(checkbox) => checkbox,
+        None => return,
+    };
+
+    let mut all_checked = None;
+    for list in node.children() {
+        if let NodeValue::List(..) = list.data.borrow().value {
+            for sub_checkbox in list.children().filter_map(task_item) {
+                if let NodeValue::TaskItem { checked, .. } = sub_checkbox.data.borrow().value {
+                    all_checked = Some(all_checked.unwrap_or(true) && checked);
+                }
+            }
+        }
+    }
+
+    if let NodeValue::TaskItem {
+        ref mut checked,
+        ref mut children_all_checked,
+    } = checkbox.data.borrow_mut().value
+    {
+        *children_all_checked = all_checked;
+        if let Some(all_checked) = all_checked {
+            *checked = all_checked;
+        }
+    }
+}
@@ -60,6 +60,36 @@ pub fn close_code_fence(line: &str) -> Option<usize> {
     captures(&RE, line, 1)
 }
 
+pub fn open_container_fence(line: &str) -> Option<usize> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            r"\A(?:(:{3,})[ \t]*[^: \t\r\n][^\r\n\x00]*[\r\n])").unwrap();
+    }
+    captures(&RE, line, 1)
+}
+
+pub fn close_container_fence(line: &str) -> Option<usize> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"\A(?:(:{3,})[ \t]*[\r\n])").unwrap();
+    }
+    captures(&RE, line, 1)
+}
+
+pub fn open_details_fence(line: &str) -> Option<usize> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            r"\A(?:(\+{3,})[ \t]+[^ \t\r\n][^\r\n\x00]*[\r\n])").unwrap();
+    }
+    captures(&RE, line, 1)
+}
+
+pub fn close_details_fence(line: &str) -> Option<usize> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"\A(?:(\+{3,})[ \t]*[\r\n])").unwrap();
+    }
+    captures(&RE, line, 1)
+}
+
 lazy_static! {
     static ref BLOCK_TAG_NAMES: Vec<&'static str> = vec![
       "address", "article", "aside", "base", "basefont", "blockquote", "body", "caption", "center",
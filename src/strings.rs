@@ -116,20 +116,29 @@ pub fn chop_trailing_hashtags(line: &mut String) {
     }
 }
 
+// Trimming also removes vertical tab and form feed, which `isspace` leaves alone as cmark does.
+fn is_trimmable(c: u8) -> bool {
+    isspace(c) || c == b'\x0b' || c == b'\x0c'
+}
+
+pub fn trim_ascii_start(line: &str) -> &str {
+    let start = line.bytes().take_while(|&c| is_trimmable(c)).count();
+    &line[start..]
+}
+
+pub fn trim_ascii_end(line: &str) -> &str {
+    let end = line.len() - line.bytes().rev().take_while(|&c| is_trimmable(c)).count();
+    &line[..end]
+}
+
 pub fn rtrim(line: &mut String) {
-    let mut len = line.len();
-    while len > 0 && isspace(line.as_bytes()[len - 1]) {
-        line.pop();
-        len -= 1;
-    }
+    let len = trim_ascii_end(line).len();
+    line.truncate(len);
 }
 
 pub fn ltrim(line: &mut String) {
-    let mut len = line.len();
-    while len > 0 && isspace(line.as_bytes()[0]) {
-        line.remove(0);
-        len -= 1;
-    }
+    let start = line.len() - trim_ascii_start(line).len();
+    line.drain(..start);
 }
 
 pub fn trim(line: &mut String) {
@@ -137,17 +146,8 @@ pub fn trim(line: &mut String) {
     rtrim(line);
 }
 
-pub fn trim_slice(mut i: &str) -> &str {
-    let mut len = i.len();
-    while len > 0 && isspace(i.as_bytes()[0]) {
-        i = &i[1..];
-        len -= 1;
-    }
-    while len > 0 && isspace(i.as_bytes()[len - 1]) {
-        i = &i[..len - 1];
-        len -= 1;
-    }
-    i
+pub fn trim_slice(i: &str) -> &str {
+    trim_ascii_end(trim_ascii_start(i))
 }
 
 pub fn clean_url(url: &str) -> String {
@@ -206,7 +206,7 @@ pub fn normalize_reference_label(i: &str) -> String {
     let mut last_was_whitespace = false;
     for c in i.chars() {
         for e in c.to_lowercase() {
-            if e.is_whitespace() {
+            if (e as u32) < 0x80 && isspace(e as u8) {
                 if !last_was_whitespace {
                     last_was_whitespace = true;
                     v.push(' ');
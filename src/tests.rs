@@ -1,6 +1,13 @@
-use {Arena, parse_document, ComrakOptions};
+use {Arena, parse_document, parse_document_raw, diff_ast, AstDiff, AutolinkContext, BibEntry,
+     ComrakOptions, DocumentParser, Extension, FootnoteNumbering, LinkResolver, MacroHandler,
+     UnknownExtension, format_json, normalize, render_many};
+use nodes::NodeValue;
 use cm;
-use html;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
 
 fn compare_strs(output: &str, expected: &str, kind: &str) {
     if output != expected {
@@ -32,12 +39,12 @@ where
     opts(&mut options);
 
     let root = parse_document(&arena, &input.chars().collect::<String>(), &options);
-    let output = html::format_document(root, &options);
+    let output = ::html::format_document(root, &options);
     compare_strs(&output, expected, "regular");
 
     let md = cm::format_document(root, &options);
     let root = parse_document(&arena, &md.chars().collect::<String>(), &options);
-    let output_from_rt = html::format_document(root, &options);
+    let output_from_rt = ::html::format_document(root, &options);
     compare_strs(&output_from_rt, expected, "roundtrip");
 }
 
@@ -78,6 +85,77 @@ fn codefence() {
     );
 }
 
+#[test]
+fn codefence_closing_length_and_char() {
+    // A closing fence needs at least as many of the same character as the opening one.
+    html(
+        concat!("````\n", "a\n", "```\n", "b\n", "````\n", "c\n"),
+        concat!("<pre><code>a\n", "```\n", "b\n", "</code></pre>\n", "<p>c</p>\n"),
+    );
+    html(
+        concat!("````\n", "a\n", "`````\n", "c\n"),
+        concat!("<pre><code>a\n", "</code></pre>\n", "<p>c</p>\n"),
+    );
+    html(
+        concat!("```\n", "a\n", "~~~\n", "b\n", "```\n"),
+        concat!("<pre><code>a\n", "~~~\n", "b\n", "</code></pre>\n"),
+    );
+    html(
+        concat!("~~~~\n", "a\n", "```\n", "~~~\n", "b\n", "~~~~\n"),
+        concat!("<pre><code>a\n", "```\n", "~~~\n", "b\n", "</code></pre>\n"),
+    );
+
+    // An unclosed fence runs to the end of the document.
+    html(
+        concat!("````\n", "open\n", "```\n"),
+        concat!("<pre><code>open\n", "```\n", "</code></pre>\n"),
+    );
+}
+
+#[test]
+fn codefence_consecutive() {
+    // A closing fence ends its block, so the next line may open another fence at once.
+    html(
+        concat!("```\n", "code1\n", "```\n", "```\n", "code2\n", "```\n"),
+        concat!("<pre><code>code1\n", "</code></pre>\n", "<pre><code>code2\n", "</code></pre>\n"),
+    );
+    html(
+        concat!("```rust\n", "a\n", "```\n", "~~~\n", "b\n", "~~~\n", "```\n", "c\n", "```\n"),
+        concat!(
+            "<pre><code class=\"language-rust\">a\n",
+            "</code></pre>\n",
+            "<pre><code>b\n",
+            "</code></pre>\n",
+            "<pre><code>c\n",
+            "</code></pre>\n"
+        ),
+    );
+    html(
+        concat!("> ```\n", "> a\n", "> ```\n", "> ```\n", "> b\n", "> ```\n"),
+        concat!(
+            "<blockquote>\n",
+            "<pre><code>a\n",
+            "</code></pre>\n",
+            "<pre><code>b\n",
+            "</code></pre>\n",
+            "</blockquote>\n"
+        ),
+    );
+    html(
+        concat!("- ```\n", "  a\n", "  ```\n", "  ```\n", "  b\n", "  ```\n"),
+        concat!(
+            "<ul>\n",
+            "<li>\n",
+            "<pre><code>a\n",
+            "</code></pre>\n",
+            "<pre><code>b\n",
+            "</code></pre>\n",
+            "</li>\n",
+            "</ul>\n"
+        ),
+    );
+}
+
 #[test]
 fn lists() {
     html(
@@ -96,6 +174,254 @@ fn lists() {
     );
 }
 
+#[test]
+fn list_start_digits() {
+    html(
+        "123456789. nine\n",
+        concat!("<ol start=\"123456789\">\n", "<li>nine</li>\n", "</ol>\n"),
+    );
+    html(
+        "999999999) nine\n",
+        concat!("<ol start=\"999999999\">\n", "<li>nine</li>\n", "</ol>\n"),
+    );
+    html(
+        concat!("1234567890. ten\n", "\n", "9999999999) ten\n"),
+        concat!("<p>1234567890. ten</p>\n", "<p>9999999999) ten</p>\n"),
+    );
+}
+
+#[test]
+fn lists_never_interrupt_paragraphs() {
+    let input = concat!(
+        "Things:\n",
+        "- one\n",
+        "1. two\n",
+        "\n",
+        "- three\n",
+        "- four\n"
+    );
+
+    html(
+        concat!("Things:\n", "1. one\n", "2. two\n"),
+        concat!("<p>Things:</p>\n", "<ol>\n", "<li>one</li>\n", "<li>two</li>\n", "</ol>\n"),
+    );
+    html_opts(
+        concat!("Things:\n", "1. one\n", "2. two\n"),
+        "<p>Things:\n1. one\n2. two</p>\n",
+        |opts| opts.lists_never_interrupt_paragraphs = true,
+    );
+    html_opts(
+        input,
+        concat!(
+            "<p>Things:\n",
+            "- one\n",
+            "1. two</p>\n",
+            "<ul>\n",
+            "<li>three</li>\n",
+            "<li>four</li>\n",
+            "</ul>\n"
+        ),
+        |opts| opts.lists_never_interrupt_paragraphs = true,
+    );
+
+    // Paragraphs in list items may still start nested lists.
+    html_opts(
+        concat!("- a\n", "  - b\n", "1. c\n", "   1. d\n"),
+        concat!(
+            "<ul>\n",
+            "<li>a\n",
+            "<ul>\n",
+            "<li>b</li>\n",
+            "</ul>\n",
+            "</li>\n",
+            "</ul>\n",
+            "<ol>\n",
+            "<li>c\n",
+            "<ol>\n",
+            "<li>d</li>\n",
+            "</ol>\n",
+            "</li>\n",
+            "</ol>\n"
+        ),
+        |opts| opts.lists_never_interrupt_paragraphs = true,
+    );
+}
+
+#[test]
+fn nested_list_tightness() {
+    // Inner loose list in an outer tight one.
+    html(
+        concat!("- a\n", "  - b\n", "\n", "    c\n", "- d\n"),
+        concat!(
+            "<ul>\n",
+            "<li>a\n",
+            "<ul>\n",
+            "<li>\n",
+            "<p>b</p>\n",
+            "<p>c</p>\n",
+            "</li>\n",
+            "</ul>\n",
+            "</li>\n",
+            "<li>d</li>\n",
+            "</ul>\n"
+        ),
+    );
+
+    // Inner tight lists in an outer loose one.
+    html(
+        concat!("- a\n", "  - b\n", "  - c\n", "\n", "- d\n", "  - e\n", "  - f\n"),
+        concat!(
+            "<ul>\n",
+            "<li>\n",
+            "<p>a</p>\n",
+            "<ul>\n",
+            "<li>b</li>\n",
+            "<li>c</li>\n",
+            "</ul>\n",
+            "</li>\n",
+            "<li>\n",
+            "<p>d</p>\n",
+            "<ul>\n",
+            "<li>e</li>\n",
+            "<li>f</li>\n",
+            "</ul>\n",
+            "</li>\n",
+            "</ul>\n"
+        ),
+    );
+
+    // A blank line between inner items loosens only the inner list.
+    html(
+        concat!("- a\n", "  - b\n", "\n", "  - c\n"),
+        concat!(
+            "<ul>\n",
+            "<li>a\n",
+            "<ul>\n",
+            "<li>\n",
+            "<p>b</p>\n",
+            "</li>\n",
+            "<li>\n",
+            "<p>c</p>\n",
+            "</li>\n",
+            "</ul>\n",
+            "</li>\n",
+            "</ul>\n"
+        ),
+    );
+
+    // A blank line inside a nested blockquote doesn't loosen the list.
+    html(
+        concat!("* a\n", "  > b\n", "  >\n", "* c\n"),
+        concat!(
+            "<ul>\n",
+            "<li>a\n",
+            "<blockquote>\n",
+            "<p>b</p>\n",
+            "</blockquote>\n",
+            "</li>\n",
+            "<li>c</li>\n",
+            "</ul>\n"
+        ),
+    );
+}
+
+#[test]
+fn list_outdent() {
+    // A line indented less than the item's content closes the list; one indented to the
+    // content stays in the item.
+    html(
+        concat!("- a\n", "\n", " b\n"),
+        concat!("<ul>\n", "<li>a</li>\n", "</ul>\n", "<p>b</p>\n"),
+    );
+    html(
+        concat!("- a\n", "\n", "  b\n"),
+        concat!("<ul>\n", "<li>\n", "<p>a</p>\n", "<p>b</p>\n", "</li>\n", "</ul>\n"),
+    );
+    html(
+        concat!(" - a\n", "\n", "  b\n"),
+        concat!("<ul>\n", "<li>a</li>\n", "</ul>\n", "<p>b</p>\n"),
+    );
+    html(
+        concat!("1.  a\n", "\n", "   b\n"),
+        concat!("<ol>\n", "<li>a</li>\n", "</ol>\n", "<p>b</p>\n"),
+    );
+    html(
+        concat!("1.  a\n", "\n", "    b\n"),
+        concat!("<ol>\n", "<li>\n", "<p>a</p>\n", "<p>b</p>\n", "</li>\n", "</ol>\n"),
+    );
+
+    // Between the content columns of nested items, a line belongs to the outer item.
+    html(
+        concat!("- a\n", "  - b\n", "\n", "   c\n"),
+        concat!(
+            "<ul>\n",
+            "<li>\n",
+            "<p>a</p>\n",
+            "<ul>\n",
+            "<li>b</li>\n",
+            "</ul>\n",
+            "<p>c</p>\n",
+            "</li>\n",
+            "</ul>\n"
+        ),
+    );
+    html(
+        concat!("- a\n", "  - b\n", "\n", " c\n"),
+        concat!(
+            "<ul>\n",
+            "<li>a\n",
+            "<ul>\n",
+            "<li>b</li>\n",
+            "</ul>\n",
+            "</li>\n",
+            "</ul>\n",
+            "<p>c</p>\n"
+        ),
+    );
+
+    // A paragraph continuation line is lazy, whatever its indentation.
+    html(
+        concat!("- a\n", "b\n"),
+        concat!("<ul>\n", "<li>a\n", "b</li>\n", "</ul>\n"),
+    );
+}
+
+#[test]
+fn list_marker_tabs() {
+    // A tab after the marker runs to the next tab stop, so content starts at column 4.
+    html(
+        concat!("-\tfoo\n", "\n", "    bar\n"),
+        concat!("<ul>\n", "<li>\n", "<p>foo</p>\n", "<p>bar</p>\n", "</li>\n", "</ul>\n"),
+    );
+    html(
+        concat!("-\tfoo\n", "\n", "  bar\n"),
+        concat!("<ul>\n", "<li>foo</li>\n", "</ul>\n", "<p>bar</p>\n"),
+    );
+    html(
+        concat!("1.\tfoo\n", "\n", "    bar\n"),
+        concat!("<ol>\n", "<li>\n", "<p>foo</p>\n", "<p>bar</p>\n", "</li>\n", "</ol>\n"),
+    );
+    html(
+        concat!("1.\tfoo\n", "\n", "   bar\n"),
+        concat!("<ol>\n", "<li>foo</li>\n", "</ol>\n", "<p>bar</p>\n"),
+    );
+    html(
+        concat!("10.\tfoo\n", "\n", "\tbar\n"),
+        concat!(
+            "<ol start=\"10\">\n",
+            "<li>\n",
+            "<p>foo</p>\n",
+            "<p>bar</p>\n",
+            "</li>\n",
+            "</ol>\n"
+        ),
+    );
+    html(
+        "-\t\tfoo\n",
+        concat!("<ul>\n", "<li>\n", "<pre><code>  foo\n", "</code></pre>\n", "</li>\n", "</ul>\n"),
+    );
+}
+
 #[test]
 fn thematic_breaks() {
     html(
@@ -104,6 +430,63 @@ fn thematic_breaks() {
     );
 }
 
+#[test]
+fn thematic_break_markup() {
+    html("---\n", "<hr />\n");
+    html_opts("---\n", "<hr>\n", |opts| opts.html5_void_elements = true);
+    html_opts(
+        "---\n",
+        "<hr class=\"a&amp;b\" />\n",
+        |opts| opts.thematic_break_class = Some("a&b".to_string()),
+    );
+    html_opts(
+        "---\n",
+        "<hr class=\"divider\">\n",
+        |opts| {
+            opts.html5_void_elements = true;
+            opts.thematic_break_class = Some("divider".to_string());
+        },
+    );
+}
+
+#[test]
+fn html5_void_elements() {
+    html_opts(
+        concat!("a  \n", "b\\\n", "c ![x](/y.png \"t\")\n", "\n", "- [x] done\n"),
+        concat!(
+            "<p>a<br>\n",
+            "b<br>\n",
+            "c <img src=\"/y.png\" alt=\"x\" title=\"t\"></p>\n",
+            "<ul>\n",
+            "<li><input type=\"checkbox\" disabled=\"\" checked=\"\"> done</li>\n",
+            "</ul>\n"
+        ),
+        |opts| {
+            opts.html5_void_elements = true;
+            opts.ext_tasklist = true;
+        },
+    );
+}
+
+#[test]
+fn normalize_html_breaks() {
+    let input = "a<br>b<br/>c<br />d<BR  >e<br class=\"x\">f\n";
+    html(input, "<p>a<br>b<br/>c<br />d<BR  >e<br class=\"x\">f</p>\n");
+    html_opts(
+        input,
+        "<p>a<br />b<br />c<br />d<br />e<br class=\"x\">f</p>\n",
+        |opts| opts.normalize_html_breaks = true,
+    );
+    html_opts(
+        input,
+        "<p>a<br>b<br>c<br>d<br>e<br class=\"x\">f</p>\n",
+        |opts| {
+            opts.normalize_html_breaks = true;
+            opts.html5_void_elements = true;
+        },
+    );
+}
+
 #[test]
 fn setext_heading() {
     html(
@@ -285,6 +668,33 @@ fn entities() {
     );
 }
 
+#[test]
+fn ampersands() {
+    // Entities are decoded when parsing, so every `&` left in text is escaped exactly once, as in
+    // cmark; code spans and code blocks keep entities as written.
+    html(
+        concat!(
+            "Bare & and &, &amp; and &amp;amp;, &nope; and &#38; &#x26; &#0;.\n",
+            "\n",
+            "`& &amp; &nope;`\n",
+            "\n",
+            "[link](/a?b=1&c=2&amp;d \"&amp;&\")\n",
+            "\n",
+            "``` &amp;\n",
+            "& &amp;\n",
+            "```\n"
+        ),
+        concat!(
+            "<p>Bare &amp; and &amp;, &amp; and &amp;amp;, ",
+            "&amp;nope; and &amp; &amp; \u{fffd}.</p>\n",
+            "<p><code>&amp; &amp;amp; &amp;nope;</code></p>\n",
+            "<p><a href=\"/a?b=1&amp;c=2&amp;d\" title=\"&amp;&amp;\">link</a></p>\n",
+            "<pre><code class=\"language-&amp;\">&amp; &amp;amp;\n",
+            "</code></pre>\n"
+        ),
+    );
+}
+
 #[test]
 fn pointy_brace() {
     html(
@@ -374,6 +784,51 @@ fn strikethrough() {
     );
 }
 
+#[test]
+fn cjk_friendly_emphasis() {
+    let input = concat!(
+        "**中文**。\n",
+        "\n",
+        "**中文。**测试\n",
+        "\n",
+        "これは**「強調」**です。\n",
+        "\n",
+        "*あ。*い ~~削除。~~テキスト\n",
+        "\n",
+        "**강조.**다\n",
+        "\n",
+        "a**\"b\"**c\n"
+    );
+
+    html_opts(
+        input,
+        concat!(
+            "<p><strong>中文</strong>。</p>\n",
+            "<p>**中文。**测试</p>\n",
+            "<p>これは**「強調」**です。</p>\n",
+            "<p>*あ。*い ~~削除。~~テキスト</p>\n",
+            "<p>**강조.**다</p>\n",
+            "<p>a**&quot;b&quot;**c</p>\n"
+        ),
+        |opts| opts.ext_strikethrough = true,
+    );
+    html_opts(
+        input,
+        concat!(
+            "<p><strong>中文</strong>。</p>\n",
+            "<p><strong>中文。</strong>测试</p>\n",
+            "<p>これは<strong>「強調」</strong>です。</p>\n",
+            "<p><em>あ。</em>い <del>削除。</del>テキスト</p>\n",
+            "<p><strong>강조.</strong>다</p>\n",
+            "<p>a**&quot;b&quot;**c</p>\n"
+        ),
+        |opts| {
+            opts.ext_strikethrough = true;
+            opts.cjk_friendly_emphasis = true;
+        },
+    );
+}
+
 #[test]
 fn table() {
     html_opts(
@@ -403,6 +858,20 @@ fn autolink_www() {
               |opts| opts.ext_autolink = true);
 }
 
+#[test]
+fn autolink_www_text_verbatim() {
+    // Only the href gains a scheme; the visible text is the matched text as written.
+    html_opts(
+        "See www.Example.com/Path/To_File?q=a&b=%20c~d, and (www.example.com/a(b)).\n",
+        concat!(
+            "<p>See <a href=\"http://www.Example.com/Path/To_File?q=a&amp;b=%20c%7Ed\">",
+            "www.Example.com/Path/To_File?q=a&amp;b=%20c~d</a>, and ",
+            "(<a href=\"http://www.example.com/a(b)\">www.example.com/a(b)</a>).</p>\n"
+        ),
+        |opts| opts.ext_autolink = true,
+    );
+}
+
 #[test]
 fn autolink_email() {
     html_opts(concat!("john@smith.com\n"),
@@ -436,17 +905,122 @@ fn autolink_scheme_multiline() {
 }
 
 #[test]
-fn tagfilter() {
-    html_opts(concat!("hi <xmp> ok\n", "\n", "<xmp>\n"),
-              concat!("<p>hi &lt;xmp> ok</p>\n", "&lt;xmp>\n"),
-              |opts| opts.ext_tagfilter = true);
+fn autolink_consecutive() {
+    html_opts(
+        "www.a.com, www.b.com.\n",
+        concat!(
+            "<p><a href=\"http://www.a.com\">www.a.com</a>, ",
+            "<a href=\"http://www.b.com\">www.b.com</a>.</p>\n"
+        ),
+        |opts| opts.ext_autolink = true,
+    );
+    html_opts(
+        "(www.a.com) http://b.org/x?, me@c.io; www.d.com\n",
+        concat!(
+            "<p>(<a href=\"http://www.a.com\">www.a.com</a>) ",
+            "<a href=\"http://b.org/x\">http://b.org/x</a>?, ",
+            "<a href=\"mailto:me@c.io\">me@c.io</a>; ",
+            "<a href=\"http://www.d.com\">www.d.com</a></p>\n"
+        ),
+        |opts| opts.ext_autolink = true,
+    );
+
+    let arena = Arena::new();
+    let mut options = ComrakOptions::default();
+    options.ext_autolink = true;
+    let root = parse_document(&arena, "x www.a.com, www.b.com.\n", &options);
+    let children = root.first_child()
+        .unwrap()
+        .children()
+        .map(|n| match n.data.borrow().value {
+            NodeValue::Text(ref text) => format!("text {:?}", text),
+            NodeValue::Link(ref nl) => format!("link {:?}", nl.url),
+            ref other => panic!("unexpected {:?}", other),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        children,
+        vec![
+            "text \"x \"",
+            "link \"http://www.a.com\"",
+            "text \", \"",
+            "link \"http://www.b.com\"",
+            "text \".\"",
+        ]
+    );
 }
 
 #[test]
-fn tasklist() {
+fn autolink_parentheses() {
     html_opts(
-        concat!(
-            "* [ ] Red\n",
+        "(see www.example.com)\n",
+        "<p>(see <a href=\"http://www.example.com\">www.example.com</a>)</p>\n",
+        |opts| opts.ext_autolink = true,
+    );
+    html_opts(
+        concat!(
+            "www.google.com/search?q=Markup+(business)\n\n",
+            "www.google.com/search?q=Markup+(business)))\n\n",
+            "(www.google.com/search?q=Markup+(business))\n\n",
+            "(www.google.com/search?q=Markup+(business)\n"
+        ),
+        concat!(
+            "<p><a href=\"http://www.google.com/search?q=Markup+(business)\">",
+            "www.google.com/search?q=Markup+(business)</a></p>\n",
+            "<p><a href=\"http://www.google.com/search?q=Markup+(business)\">",
+            "www.google.com/search?q=Markup+(business)</a>))</p>\n",
+            "<p>(<a href=\"http://www.google.com/search?q=Markup+(business)\">",
+            "www.google.com/search?q=Markup+(business)</a>)</p>\n",
+            "<p>(<a href=\"http://www.google.com/search?q=Markup+(business)\">",
+            "www.google.com/search?q=Markup+(business)</a></p>\n"
+        ),
+        |opts| opts.ext_autolink = true,
+    );
+    html_opts(
+        "(https://example.com/a_(b))\n",
+        "<p>(<a href=\"https://example.com/a_(b)\">https://example.com/a_(b)</a>)</p>\n",
+        |opts| opts.ext_autolink = true,
+    );
+}
+
+#[test]
+fn tagfilter() {
+    html_opts(concat!("hi <xmp> ok\n", "\n", "<xmp>\n"),
+              concat!("<p>hi &lt;xmp> ok</p>\n", "&lt;xmp>\n"),
+              |opts| opts.ext_tagfilter = true);
+}
+
+#[test]
+fn tagfilter_html_blocks() {
+    html_opts(
+        concat!(
+            "<script>\n",
+            "alert(1);\n",
+            "</script>\n",
+            "\n",
+            "<div><SCRIPT src=x></script></div>\n",
+            "\n",
+            "<style>p{}</style> and <iframe/>\n",
+            "\n",
+            "a <script>x</script> <scripts> <b>ok</b>\n"
+        ),
+        concat!(
+            "&lt;script>\n",
+            "alert(1);\n",
+            "&lt;/script>\n",
+            "<div>&lt;SCRIPT src=x>&lt;/script></div>\n",
+            "&lt;style>p{}&lt;/style> and &lt;iframe/>\n",
+            "<p>a &lt;script>x&lt;/script> <scripts> <b>ok</b></p>\n"
+        ),
+        |opts| opts.ext_tagfilter = true,
+    );
+}
+
+#[test]
+fn tasklist() {
+    html_opts(
+        concat!(
+            "* [ ] Red\n",
             "* [x] Green\n",
             "* [ ] Blue\n",
             "<!-- end list -->\n",
@@ -488,8 +1062,3282 @@ fn tasklist() {
 }
 
 #[test]
-fn superscript() {
-    html_opts(concat!("e = mc^2^.\n"),
-              concat!("<p>e = mc<sup>2</sup>.</p>\n"),
-              |opts| opts.ext_superscript = true);
+fn tasklist_marker_indentation() {
+    html_opts(
+        concat!(
+            "- [ ] zero\n",
+            "-  [x] one\n",
+            "-   [ ] two\n",
+            "-    [x] three\n",
+            "<!-- end list -->\n",
+            "  * [ ] indented item\n",
+            "   *  [x] indented further\n"
+        ),
+        concat!(
+            "<ul>\n",
+            "<li><input type=\"checkbox\" disabled=\"\" /> zero</li>\n",
+            "<li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> one</li>\n",
+            "<li><input type=\"checkbox\" disabled=\"\" /> two</li>\n",
+            "<li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> three</li>\n",
+            "</ul>\n",
+            "<!-- end list -->\n",
+            "<ul>\n",
+            "<li><input type=\"checkbox\" disabled=\"\" /> indented item</li>\n",
+            "<li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> indented further</li>\n",
+            "</ul>\n"
+        ),
+        |opts| opts.ext_tasklist = true,
+    );
+}
+
+#[test]
+fn tasklist_nesting() {
+    html_opts(
+        concat!(
+            "* [ ] All done\n",
+            "  * [x] One\n",
+            "  * [x] Two\n",
+            "* [x] Partly done\n",
+            "  * [ ] One\n",
+            "    * [x] One.one\n",
+            "  * [x] Two\n",
+            "  * Not a task\n",
+            "* [x] No tasks\n",
+            "  * Plain\n"
+        ),
+        concat!(
+            "<ul>\n",
+            "<li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> All done\n",
+            "<ul>\n",
+            "<li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> One</li>\n",
+            "<li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> Two</li>\n",
+            "</ul>\n",
+            "</li>\n",
+            "<li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> Partly done\n",
+            "<ul>\n",
+            "<li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> One\n",
+            "<ul>\n",
+            "<li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> One.one</li>\n",
+            "</ul>\n",
+            "</li>\n",
+            "<li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> Two</li>\n",
+            "<li>Not a task</li>\n",
+            "</ul>\n",
+            "</li>\n",
+            "<li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> No tasks\n",
+            "<ul>\n",
+            "<li>Plain</li>\n",
+            "</ul>\n",
+            "</li>\n",
+            "</ul>\n"
+        ),
+        |opts| {
+            opts.ext_tasklist = true;
+            opts.ext_tasklist_nesting = true;
+        },
+    );
+
+    let arena = Arena::new();
+    let mut options = ComrakOptions::default();
+    options.ext_tasklist = true;
+    options.ext_tasklist_nesting = true;
+    let root = parse_document(
+        &arena,
+        "* [x] Parent\n  * [ ] One\n  * [x] Two\n* [ ] Leaf\n",
+        &options,
+    );
+    let states = root.descendants()
+        .filter_map(|n| match n.data.borrow().value {
+            NodeValue::TaskItem {
+                checked,
+                children_all_checked,
+            } => Some((checked, children_all_checked)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        states,
+        vec![
+            (false, Some(false)),
+            (false, None),
+            (true, None),
+            (false, None),
+        ]
+    );
+}
+
+#[test]
+fn preserve_tabs() {
+    html_opts(
+        concat!(
+            "- ```make\n",
+            "  all:\n",
+            "\techo hi\n",
+            "  ```\n",
+            "\n",
+            "-\t\tcode\n"
+        ),
+        concat!(
+            "<ul>\n",
+            "<li>\n",
+            "<pre><code class=\"language-make\">all:\n",
+            "\techo hi\n",
+            "</code></pre>\n",
+            "</li>\n",
+            "<li>\n",
+            "<pre><code>\tcode\n",
+            "</code></pre>\n",
+            "</li>\n",
+            "</ul>\n"
+        ),
+        |opts| opts.preserve_tabs = true,
+    );
+}
+
+#[test]
+fn details() {
+    html_opts(
+        concat!(
+            "+++ Click to *expand*\n",
+            "Hidden **content**.\n",
+            "\n",
+            "++++ Nested\n",
+            "```\n",
+            "+++\n",
+            "```\n",
+            "+++\n",
+            "\n",
+            "- item\n",
+            "+++\n",
+            "After.\n"
+        ),
+        concat!(
+            "<details>\n",
+            "<summary>Click to <em>expand</em></summary>\n",
+            "<p>Hidden <strong>content</strong>.</p>\n",
+            "<details>\n",
+            "<summary>Nested</summary>\n",
+            "<pre><code>+++\n",
+            "</code></pre>\n",
+            "</details>\n",
+            "<ul>\n",
+            "<li>item</li>\n",
+            "</ul>\n",
+            "</details>\n",
+            "<p>After.</p>\n"
+        ),
+        |opts| opts.ext_details = true,
+    );
+
+    html_opts(
+        concat!("> +++ Quoted\n", "> Inside.\n", "\n", "+++ Unclosed\n", "Rest.\n"),
+        concat!(
+            "<blockquote>\n",
+            "<details>\n",
+            "<summary>Quoted</summary>\n",
+            "<p>Inside.</p>\n",
+            "</details>\n",
+            "</blockquote>\n",
+            "<details>\n",
+            "<summary>Unclosed</summary>\n",
+            "<p>Rest.</p>\n",
+            "</details>\n"
+        ),
+        |opts| opts.ext_details = true,
+    );
+
+    html_opts(
+        concat!("+++\n", "\n", "++++plus\n", "\n", "    +++ Indented\n"),
+        concat!(
+            "<p>+++</p>\n",
+            "<p>++++plus</p>\n",
+            "<pre><code>+++ Indented\n",
+            "</code></pre>\n"
+        ),
+        |opts| opts.ext_details = true,
+    );
+
+    html(
+        "+++ Summary\nText.\n+++\n",
+        "<p>+++ Summary\nText.\n+++</p>\n",
+    );
+}
+
+#[test]
+fn code_block_attrs() {
+    let cases = [
+        ("{.rust}", "<pre><code class=\"language-rust\">"),
+        ("{.rust .highlighted}", "<pre><code class=\"language-rust highlighted\">"),
+        ("{.rust #example-1}", "<pre><code class=\"language-rust\" id=\"example-1\">"),
+        (
+            "{.rust .highlighted .wide #example-1}",
+            "<pre><code class=\"language-rust highlighted wide\" id=\"example-1\">",
+        ),
+        ("{#example-1}", "<pre><code id=\"example-1\">"),
+        ("{#example-1 .rust}", "<pre><code class=\"language-rust\" id=\"example-1\">"),
+        ("{}", "<pre><code>"),
+        ("{1,3}", "<pre><code><span class=\"highlighted\">x</span>\n</code></pre>\n"),
+        ("{.rust foo}", "<pre><code>"),
+    ];
+
+    for &(info, open) in &cases {
+        let input = format!("``` {}\nx\n```\n", info);
+        let expected = if open.ends_with('\n') {
+            open.to_string()
+        } else {
+            format!("{}x\n</code></pre>\n", open)
+        };
+        html_opts(&input, &expected, |opts| opts.ext_code_block_attrs = true);
+    }
+
+    html_opts(
+        "``` {.rust .highlighted #example-1}\nx\n```\n",
+        "<pre lang=\"rust\"><code class=\"highlighted\" id=\"example-1\">x\n</code></pre>\n",
+        |opts| {
+            opts.ext_code_block_attrs = true;
+            opts.github_pre_lang = true;
+        },
+    );
+
+    html(
+        "``` {.rust .highlighted #example-1}\nx\n```\n",
+        "<pre><code>x\n</code></pre>\n",
+    );
+}
+
+#[test]
+fn raw_attribute_passthrough() {
+    html_opts(
+        concat!(
+            "# Intro {#intro .lead .big data-level=1}\n",
+            "\n",
+            "Setext {class=\"a b\" title=\"Some <title>\"}\n",
+            "======\n",
+            "\n",
+            "A *paragraph*\n",
+            "over lines.\n",
+            "{.note data-empty=\"\"}\n",
+            "\n",
+            "> Quoted.\n",
+            "> {#q}\n"
+        ),
+        concat!(
+            "<h1 id=\"intro\" class=\"lead big\" data-level=\"1\">Intro</h1>\n",
+            "<h1 class=\"a b\" title=\"Some &lt;title&gt;\">Setext</h1>\n",
+            "<p class=\"note\" data-empty=\"\">A <em>paragraph</em>\nover lines.</p>\n",
+            "<blockquote>\n",
+            "<p id=\"q\">Quoted.</p>\n",
+            "</blockquote>\n"
+        ),
+        |opts| opts.ext_raw_attribute_passthrough = true,
+    );
+
+    // Attribute blocks must follow some text, and be well-formed.
+    html_opts(
+        concat!(
+            "# {#only}\n",
+            "\n",
+            "# Glued{#id}\n",
+            "\n",
+            "{.alone}\n",
+            "\n",
+            "Inline {.x} text.\n",
+            "\n",
+            "Bad {#}\n",
+            "\n",
+            "Bad {key=\"unclosed}\n"
+        ),
+        concat!(
+            "<h1>{#only}</h1>\n",
+            "<h1>Glued{#id}</h1>\n",
+            "<p>{.alone}</p>\n",
+            "<p>Inline {.x} text.</p>\n",
+            "<p>Bad {#}</p>\n",
+            "<p>Bad {key=&quot;unclosed}</p>\n"
+        ),
+        |opts| opts.ext_raw_attribute_passthrough = true,
+    );
+
+    html("# Intro {#intro}\n", "<h1>Intro {#intro}</h1>\n");
+}
+
+#[test]
+fn wrap_element() {
+    html_opts(
+        "# Title\n\nText.\n",
+        concat!(
+            "<div class=\"markdown-body\" data-x=\"&quot;q&quot;\">\n",
+            "<section>\n",
+            "<h1>Title</h1>\n",
+            "<p>Text.</p>\n",
+            "</section>\n",
+            "</div>\n"
+        ),
+        |opts| {
+            opts.ext_html_sections = true;
+            opts.wrap_element = Some((
+                "div".to_string(),
+                vec![
+                    ("class".to_string(), "markdown-body".to_string()),
+                    ("data-x".to_string(), "\"q\"".to_string()),
+                ],
+            ));
+        },
+    );
+
+    html_opts(
+        "",
+        "<article>\n</article>\n",
+        |opts| opts.wrap_element = Some(("article".to_string(), vec![])),
+    );
+
+    html_opts(
+        "Text.\n",
+        "<div data-ok=\"1\">\n<p>Text.</p>\n</div>\n",
+        |opts| {
+            opts.wrap_element = Some((
+                "div".to_string(),
+                vec![
+                    ("onload=alert(1) x".to_string(), "2".to_string()),
+                    ("data-ok".to_string(), "1".to_string()),
+                    ("".to_string(), "3".to_string()),
+                ],
+            ))
+        },
+    );
+    html_opts(
+        "Text.\n",
+        "<p>Text.</p>\n",
+        |opts| opts.wrap_element = Some(("div><script".to_string(), vec![])),
+    );
+}
+
+#[test]
+fn document_parser_chunks() {
+    let input = concat!(
+        "# Title\r\n",
+        "\r\n",
+        "Some *text*\rwith [a link].\n",
+        "\n",
+        "> - item\n",
+        ">\tcontinued\n",
+        "\n",
+        "```rust\n",
+        "code\u{0}\n",
+        "```\n",
+        "\n",
+        "[a link]: /url\n",
+        "Last line"
+    );
+
+    let options = ComrakOptions::default();
+    let arena = Arena::new();
+    let expected = ::html::format_document(parse_document(&arena, input, &options), &options);
+    assert_eq!(
+        expected,
+        concat!(
+            "<h1>Title</h1>\n",
+            "<p>Some <em>text</em>\nwith <a href=\"/url\">a link</a>.</p>\n",
+            "<blockquote>\n<ul>\n<li>item\ncontinued</li>\n</ul>\n</blockquote>\n",
+            "<pre><code class=\"language-rust\">code\u{fffd}\n</code></pre>\n",
+            "<p>Last line</p>\n"
+        )
+    );
+
+    let boundaries = input.char_indices().map(|(i, _)| i).collect::<Vec<_>>();
+    for (n, &i) in boundaries.iter().enumerate() {
+        for &j in &boundaries[n..] {
+            let arena = Arena::new();
+            let mut parser = DocumentParser::new(&arena, &options);
+            parser.feed(&input[..i]);
+            parser.feed(&input[i..j]);
+            parser.feed(&input[j..]);
+            let root = parser.finish();
+            assert_eq!(
+                ::html::format_document(root, &options),
+                expected,
+                "split at {} and {}",
+                i,
+                j
+            );
+        }
+    }
+}
+
+#[test]
+fn empty_documents() {
+    let options = ComrakOptions::default();
+    for input in &["", " ", "\n", "   \n\n", "\t\n \n", "\r\n\r\n", "[ref]: /url\n"] {
+        let arena = Arena::new();
+        let root = parse_document(&arena, input, &options);
+        assert!(root.first_child().is_none(), "{:?}", input);
+        assert_eq!(::html::format_document(root, &options), "", "{:?}", input);
+        assert_eq!(cm::format_document(root, &options), "", "{:?}", input);
+
+        let mut output = vec![];
+        ::html::format_document_to(root, &options, &mut output).unwrap();
+        assert!(output.is_empty(), "{:?}", input);
+    }
+}
+
+#[test]
+fn options_from_vars() {
+    let mut vars = HashMap::new();
+    vars.insert("COMRAK_EXTENSIONS", "strikethrough, table,,autolink");
+    vars.insert("COMRAK_HARDBREAKS", "1");
+    vars.insert("COMRAK_PRESERVE_TABS", "false");
+    vars.insert("COMRAK_WIDTH", "72");
+    vars.insert("COMRAK_MAX_REFERENCES", "many");
+    let options = ComrakOptions::from_vars(|name| vars.get(name).map(|v| v.to_string())).unwrap();
+    assert!(options.ext_strikethrough && options.ext_table && options.ext_autolink);
+    assert!(!options.ext_tasklist);
+    assert!(options.hardbreaks && !options.preserve_tabs && !options.github_pre_lang);
+    assert_eq!(options.width, 72);
+    assert_eq!(options.max_references, 0);
+
+    vars.insert("COMRAK_EXTENSIONS", "table,tables");
+    let unknown = ComrakOptions::from_vars(|name| vars.get(name).map(|v| v.to_string()));
+    assert_eq!(unknown.unwrap_err(), UnknownExtension("tables".to_string()));
+}
+
+#[test]
+fn commonmark_empty_documents() {
+    let names = Extension::all().iter().map(|e| e.name()).collect::<Vec<_>>();
+    let all = ComrakOptions::with_extensions(&names).unwrap();
+    let variants = vec![
+        ComrakOptions::default(),
+        ComrakOptions {
+            width: 20,
+            ..ComrakOptions::default()
+        },
+        ComrakOptions {
+            hardbreaks: true,
+            ..all.clone()
+        },
+        all,
+    ];
+
+    for options in &variants {
+        for input in &["", "\n\n\n", " \t \n", "[ref]: /url\n\n"] {
+            let arena = Arena::new();
+            let root = parse_document(&arena, input, options);
+            assert_eq!(cm::format_document(root, options), "", "{:?}", input);
+        }
+    }
+}
+
+#[test]
+fn commonmark_preserve_source_style() {
+    let input = concat!(
+        "Title\n",
+        "=====\n",
+        "\n",
+        "Subtitle\n",
+        "--------\n",
+        "\n",
+        "## ATX\n",
+        "\n",
+        "* one\n",
+        "* two\n",
+        "\n",
+        "\n",
+        "+ three\n",
+        "\n",
+        "  four\n",
+        "\n",
+        "1) a\n",
+        "2) b\n",
+        "\n",
+        "10. x\n",
+        "11. y\n",
+        "\n",
+        "~~~~ rust\n",
+        "fn main() {}\n",
+        "~~~~\n",
+        "\n",
+        "    indented\n",
+        "\n",
+        "```\n",
+        "fenced\n",
+        "```\n",
+        "\n",
+        "> quote\n",
+        ">\n",
+        "> ````\n",
+        "> ```\n",
+        "> ````\n",
+        "\n",
+        "-   wide\n",
+        "-   items\n"
+    );
+
+    let mut options = ComrakOptions::default();
+    options.preserve_source_style = true;
+    let arena = Arena::new();
+    let root = parse_document(&arena, input, &options);
+    let output = cm::format_document(root, &options);
+    compare_strs(&output, input, "preserved");
+
+    let root = parse_document(&arena, &output, &options);
+    compare_strs(&cm::format_document(root, &options), &output, "idempotent");
+
+    // Without the option, fences are normalized to backticks.
+    options.preserve_source_style = false;
+    let root = parse_document(&arena, "~~~~ rust\nfn main() {}\n~~~~\n", &options);
+    assert_eq!(cm::format_document(root, &options), "``` rust\nfn main() {}\n```\n");
+}
+
+#[test]
+fn commonmark_min_fence_length() {
+    let mut options = ComrakOptions::default();
+    let arena = Arena::new();
+    let root = parse_document(&arena, "```c\ncode\n```\n\n````c\n```\n````\n", &options);
+    assert_eq!(
+        cm::format_document(root, &options),
+        "``` c\ncode\n```\n\n```` c\n```\n````\n"
+    );
+
+    options.min_fence_length = 5;
+    assert_eq!(
+        cm::format_document(root, &options),
+        "````` c\ncode\n`````\n\n````` c\n```\n`````\n"
+    );
+
+    let root = parse_document(&arena, "```c\n`````` x\n```\n", &options);
+    assert_eq!(cm::format_document(root, &options), "``````` c\n`````` x\n```````\n");
+
+    options.preserve_source_style = true;
+    let root = parse_document(&arena, "~~~c\ncode\n~~~\n\n~~~~~~c\ncode\n~~~~~~\n", &options);
+    assert_eq!(
+        cm::format_document(root, &options),
+        "~~~~~ c\ncode\n~~~~~\n\n~~~~~~ c\ncode\n~~~~~~\n"
+    );
+}
+
+#[test]
+fn commonmark_footnotes() {
+    let mut options = ComrakOptions::default();
+    options.ext_footnotes = true;
+    let arena = Arena::new();
+    let root = parse_document(
+        &arena,
+        concat!(
+            "[^a]: Defined first,\n",
+            "but referenced second.\n",
+            "\n",
+            "    With more.\n",
+            "[^a]: Ignored.\n",
+            "\n",
+            "Text[^b], then[^A] and[^c].\n",
+            "\n",
+            "[^b]: > Quoted.\n"
+        ),
+        &options,
+    );
+    assert_eq!(
+        cm::format_document(root, &options),
+        concat!(
+            "Text[^b], then[^A] and\\[^c\\].\n",
+            "\n",
+            "[^b]: > Quoted.\n",
+            "\n",
+            "[^a]: Defined first,\n",
+            "    but referenced second.\n",
+            "    \n",
+            "    With more.\n"
+        )
+    );
+}
+
+#[test]
+fn commonmark_idempotent() {
+    let corpus = [
+        "# Heading\n\nSetext\n======\n\nPara with *emph*, **strong**, `code` and [link](/u \"t\").\n",
+        "![img](/i.png \"title\") and <http://auto.link> and <a@b.c>\n",
+        "> quote\n> more\n>\n> > nested\n",
+        "- a\n- b\n  - c\n  - d\n\n1. one\n2. two\n",
+        "- loose\n\n- list\n\n\n- c\n\n1) paren\n2) list\n\n10. ten\n11. eleven\n",
+        "```rust\nfn main() {}\n```\n\n    indented\n\n\n    more\n",
+        "<div>\nhtml block\n</div>\n\ninline <span>html</span> <!-- c -->\n",
+        "***\n\nline  \nbreak\\\nhard\n",
+        "Text with \\* escapes \\_ and &amp; entities &copy; &#42;\n",
+        "\\# escaped\n\n1\\. escaped\n\n\\+ escaped\n",
+        "*foo*bar _foo_bar x_y_z foo* **foo* ***both***\n",
+        "[not a link] [a]: not a def ![](/x) [](/x) <not html `\n",
+        "[link](</a b>) [link](/a \"q\\\"uote\") [link](/a(b)) [link *emph*](/u)\n",
+        "[ref]\n\n[ref]: /url \"title\"\n",
+        "- a\n-\n- c\n\n- - -\n",
+        "1. a\n\n   para\n\n       code in item\n\n2. > quote in list\n",
+        "| a | b |\n|:--|--:|\n| c \\| d | `e|f` |\n",
+        "~~strike~~ and ~under~ and ^sup^\n",
+        "- [ ] task\n- [x] done\n",
+        "www.example.com and https://x.y/z_a_b and +1-555-123-4567\n",
+        "@alice and #tag -- and ---\n",
+        "::: warning Title\ncontent\n:::\n\n+++ Summary\nbody\n+++\n",
+        "||| a ||| b |||\n\n@@macro arg\n",
+        "# Heading {#id .class}\n\n``` rust {.x #y}\ncode\n```\n",
+        "xxxxxxxxxxxxxxxxxxx - a\n\nxxxxxxxxxxxxxxxxx ~~~ a\n\nxxxxxxxxxxxxxxx <div> a\n",
+        "Notes[^b] and[^a] and[^none].\n\n[^a]: First note, which is long enough to wrap.\n\n\
+         [^b]: Second.\n\n    > quoted\n\n    - listed\n",
+    ];
+    let names = Extension::all().iter().map(|e| e.name()).collect::<Vec<_>>();
+    let all = ComrakOptions::with_extensions(&names).unwrap();
+    let variants = vec![
+        ComrakOptions::default(),
+        all.clone(),
+        ComrakOptions {
+            width: 20,
+            ..all.clone()
+        },
+        ComrakOptions {
+            preserve_source_style: true,
+            ..all
+        },
+    ];
+
+    for options in &variants {
+        for input in &corpus {
+            let arena = Arena::new();
+            let once = cm::format_document(parse_document(&arena, input, options), options);
+            let twice = cm::format_document(parse_document(&arena, &once, options), options);
+            assert_eq!(once, twice, "{:?}", input);
+
+            // Wrapping may only change soft line breaks into spaces and back.
+            let html = |md: &str| {
+                ::html::format_document(parse_document(&arena, md, options), options)
+                    .replace("\n", " ")
+            };
+            assert_eq!(html(input), html(&once), "{:?}", input);
+        }
+    }
+}
+
+#[cfg(feature = "unicode-width")]
+#[test]
+fn commonmark_wrap_display_width() {
+    let options = ComrakOptions {
+        width: 14,
+        ..ComrakOptions::default()
+    };
+
+    for &(input, expected) in &[
+        ("日本語 日本語 日本語 日本語\n", "日本語 日本語\n日本語 日本語\n"),
+        ("> 한국 한국 한국 한국\n", "> 한국 한국\n> 한국 한국\n"),
+        (
+            "e\u{301}e\u{301}e\u{301}e\u{301} abcd efgh ijkl\n",
+            "e\u{301}e\u{301}e\u{301}e\u{301} abcd efgh\nijkl\n",
+        ),
+    ]
+    {
+        let arena = Arena::new();
+        let root = parse_document(&arena, input, &options);
+        assert_eq!(cm::format_document(root, &options), expected);
+    }
+}
+
+#[cfg(feature = "unicode-normalization")]
+#[test]
+fn unicode_normalization() {
+    use NormalizationForm;
+
+    let decomposed = "# Cafe\u{301}\n\n\u{fb01}ne\n";
+    let composed = "# Caf\u{e9}\n\n\u{fb01}ne\n";
+
+    html(decomposed, "<h1>Cafe\u{301}</h1>\n<p>\u{fb01}ne</p>\n");
+    html_opts(
+        decomposed,
+        "<h1>Caf\u{e9}</h1>\n<p>\u{fb01}ne</p>\n",
+        |opts| opts.unicode_normalization = Some(NormalizationForm::Nfc),
+    );
+    html_opts(
+        composed,
+        "<h1>Caf\u{e9}</h1>\n<p>\u{fb01}ne</p>\n",
+        |opts| opts.unicode_normalization = Some(NormalizationForm::Nfc),
+    );
+    html_opts(
+        decomposed,
+        "<h1>Caf\u{e9}</h1>\n<p>fine</p>\n",
+        |opts| opts.unicode_normalization = Some(NormalizationForm::Nfkc),
+    );
+
+    let mut options = ComrakOptions::default();
+    options.unicode_normalization = Some(NormalizationForm::Nfc);
+    let arena = Arena::new();
+    let mut parser = DocumentParser::new(&arena, &options);
+    parser.feed("# Cafe");
+    parser.feed("\u{301}\n\n\u{fb01}");
+    parser.feed("ne");
+    let root = parser.finish();
+    assert_eq!(
+        ::html::format_document(root, &options),
+        "<h1>Caf\u{e9}</h1>\n<p>\u{fb01}ne</p>\n"
+    );
+}
+
+#[test]
+fn unwrap_single_paragraph() {
+    let unwrap = |opts: &mut ComrakOptions| opts.unwrap_single_paragraph = true;
+
+    html_opts("**bold**\n", "<strong>bold</strong>", unwrap);
+    html_opts("A *short*\ncomment.\n", "A <em>short</em>\ncomment.", unwrap);
+    html_opts("One.\n\nTwo.\n", "<p>One.</p>\n<p>Two.</p>\n", unwrap);
+    html_opts("# Title\n\nText.\n", "<h1>Title</h1>\n<p>Text.</p>\n", unwrap);
+    html_opts("> Quoted.\n", "<blockquote>\n<p>Quoted.</p>\n</blockquote>\n", unwrap);
+    html_opts("", "", unwrap);
+    html_opts(
+        "Plain text.\n",
+        "Plain text.",
+        |opts| {
+            opts.unwrap_single_paragraph = true;
+            opts.optimize_simple_paragraphs = true;
+        },
+    );
+}
+
+#[test]
+fn unwrap_blockquote_paragraph() {
+    let unwrap = |opts: &mut ComrakOptions| opts.unwrap_blockquote_paragraph = true;
+
+    html_opts("> Quoted.\n", "<blockquote>\nQuoted.\n</blockquote>\n", unwrap);
+    html_opts(
+        concat!("> Quoted\n", "> over *two* lines.\n"),
+        "<blockquote>\nQuoted\nover <em>two</em> lines.\n</blockquote>\n",
+        unwrap,
+    );
+    html_opts(
+        concat!("> One.\n", ">\n", "> Two.\n"),
+        "<blockquote>\n<p>One.</p>\n<p>Two.</p>\n</blockquote>\n",
+        unwrap,
+    );
+    html_opts(
+        concat!("> # Title\n", "> Text.\n"),
+        "<blockquote>\n<h1>Title</h1>\n<p>Text.</p>\n</blockquote>\n",
+        unwrap,
+    );
+    html_opts(
+        concat!("> > Nested.\n", "\n", "Outside.\n"),
+        concat!(
+            "<blockquote>\n",
+            "<blockquote>\n",
+            "Nested.\n",
+            "</blockquote>\n",
+            "</blockquote>\n",
+            "<p>Outside.</p>\n"
+        ),
+        unwrap,
+    );
+}
+
+#[test]
+fn strip_trailing_newline() {
+    let strip = |opts: &mut ComrakOptions| opts.strip_trailing_newline = true;
+
+    html_opts("Hello.\n", "<p>Hello.</p>", strip);
+    html_opts("# Hello\n", "<h1>Hello</h1>", strip);
+    html_opts("```\ncode\n\n```\n", "<pre><code>code\n\n</code></pre>", strip);
+    html_opts("<div>\nraw\n</div>\n", "<div>\nraw\n</div>", strip);
+    html_opts("- a\n- b\n", "<ul>\n<li>a</li>\n<li>b</li>\n</ul>", strip);
+    html_opts("> ---\n", "<blockquote>\n<hr />\n</blockquote>", strip);
+    html_opts("", "", strip);
+    html_opts(
+        "Hello.\n",
+        "<article>\n<p>Hello.</p>\n</article>",
+        |opts| {
+            opts.strip_trailing_newline = true;
+            opts.wrap_element = Some(("article".to_string(), vec![]));
+        },
+    );
+
+    let arena = Arena::new();
+    let mut options = ComrakOptions::default();
+    options.strip_trailing_newline = true;
+    let root = parse_document(&arena, "A\n\n    code\n", &options);
+
+    let mut output = vec![];
+    ::html::format_document_to(root, &options, &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "<p>A</p>\n<pre><code>code\n</code></pre>");
+    assert_eq!(cm::format_document(root, &options), "A\n\n    code");
+}
+
+#[test]
+fn image_figures() {
+    html_opts(
+        concat!(
+            "![A *cat*](cat.png \"My <cat>\")\n",
+            "\n",
+            "Look: ![A cat](cat.png \"My cat\")\n",
+            "\n",
+            "![Untitled](cat.png)\n",
+            "\n",
+            "![One](1.png \"1\") ![Two](2.png \"2\")\n",
+            "\n",
+            "> ![Quoted](q.png \"Quoted\")\n"
+        ),
+        concat!(
+            "<figure>\n",
+            "<img src=\"cat.png\" alt=\"A cat\" />\n",
+            "<figcaption>My &lt;cat&gt;</figcaption>\n",
+            "</figure>\n",
+            "<p>Look: <img src=\"cat.png\" alt=\"A cat\" title=\"My cat\" /></p>\n",
+            "<p><img src=\"cat.png\" alt=\"Untitled\" /></p>\n",
+            "<p><img src=\"1.png\" alt=\"One\" title=\"1\" /> ",
+            "<img src=\"2.png\" alt=\"Two\" title=\"2\" /></p>\n",
+            "<blockquote>\n",
+            "<figure>\n",
+            "<img src=\"q.png\" alt=\"Quoted\" />\n",
+            "<figcaption>Quoted</figcaption>\n",
+            "</figure>\n",
+            "</blockquote>\n"
+        ),
+        |opts| opts.image_figures = true,
+    );
+
+    html(
+        "![A cat](cat.png \"My cat\")\n",
+        "<p><img src=\"cat.png\" alt=\"A cat\" title=\"My cat\" /></p>\n",
+    );
+}
+
+#[test]
+fn citations() {
+    html_opts(
+        concat!(
+            "See [@Smith2021, p. 3] and [@Smith2021].\n",
+            "\n",
+            "Also [@Jones:2019/a], [see [@Doe_1]], and [@Doe](http://example.com).\n"
+        ),
+        concat!(
+            "<p>See [@Smith2021, p. 3] and <cite data-ref=\"Smith2021\">[@Smith2021]</cite>.</p>\n",
+            "<p>Also <cite data-ref=\"Jones:2019/a\">[@Jones:2019/a]</cite>, ",
+            "[see <cite data-ref=\"Doe_1\">[@Doe_1]</cite>], and ",
+            "<a href=\"http://example.com\">@Doe</a>.</p>\n"
+        ),
+        |opts| opts.ext_citations = true,
+    );
+
+    html("See [@Smith2021].\n", "<p>See [@Smith2021].</p>\n");
+}
+
+#[test]
+fn citations_bibliography() {
+    let mut bibliography = HashMap::new();
+    bibliography.insert(
+        "Smith2021".to_string(),
+        BibEntry { text: "Smith, J. (2021). On citations.".to_string() },
+    );
+    bibliography.insert(
+        "Jones2019".to_string(),
+        BibEntry { text: "Jones, A. & B. (2019). <Unescaped>.".to_string() },
+    );
+    bibliography.insert(
+        "Uncited".to_string(),
+        BibEntry { text: "Nobody.".to_string() },
+    );
+
+    html_opts(
+        concat!(
+            "First [@Jones2019], then [@Smith2021].\n",
+            "\n",
+            "Again [@Jones2019], and [@Unknown].\n"
+        ),
+        concat!(
+            "<p>First <cite><a href=\"#ref-Jones2019\">[1]</a></cite>, ",
+            "then <cite><a href=\"#ref-Smith2021\">[2]</a></cite>.</p>\n",
+            "<p>Again <cite><a href=\"#ref-Jones2019\">[1]</a></cite>, ",
+            "and <cite data-ref=\"Unknown\">[@Unknown]</cite>.</p>\n",
+            "<section class=\"bibliography\">\n",
+            "<ol>\n",
+            "<li id=\"ref-Jones2019\">Jones, A. &amp; B. (2019). &lt;Unescaped&gt;.</li>\n",
+            "<li id=\"ref-Smith2021\">Smith, J. (2021). On citations.</li>\n",
+            "</ol>\n",
+            "</section>\n"
+        ),
+        |opts| {
+            opts.ext_citations = true;
+            opts.bibliography = Some(bibliography.clone());
+        },
+    );
+
+    html_opts(
+        "No citations.\n",
+        "<p>No citations.</p>\n",
+        |opts| {
+            opts.ext_citations = true;
+            opts.bibliography = Some(bibliography.clone());
+        },
+    );
+
+    // A section on its own keeps the document's numbering, and is followed by the bibliography.
+    let arena = Arena::new();
+    let mut options = ComrakOptions::default();
+    options.ext_citations = true;
+    options.ext_html_sections = true;
+    options.bibliography = Some(bibliography);
+    options.wrap_element = Some(("article".to_string(), vec![]));
+    let root = parse_document(
+        &arena,
+        "# A\n\n[@Jones2019]\n\n# B\n\n[@Smith2021], [@Jones2019]\n",
+        &options,
+    );
+    let bibliography = concat!(
+        "<section class=\"bibliography\">\n",
+        "<ol>\n",
+        "<li id=\"ref-Jones2019\">Jones, A. &amp; B. (2019). &lt;Unescaped&gt;.</li>\n",
+        "<li id=\"ref-Smith2021\">Smith, J. (2021). On citations.</li>\n",
+        "</ol>\n",
+        "</section>\n"
+    );
+    let a = concat!(
+        "<section>\n",
+        "<h1>A</h1>\n",
+        "<p><cite><a href=\"#ref-Jones2019\">[1]</a></cite></p>\n",
+        "</section>\n"
+    );
+    let b = concat!(
+        "<section>\n",
+        "<h1>B</h1>\n",
+        "<p><cite><a href=\"#ref-Smith2021\">[2]</a></cite>, ",
+        "<cite><a href=\"#ref-Jones2019\">[1]</a></cite></p>\n",
+        "</section>\n"
+    );
+    assert_eq!(
+        ::html::format_document(root, &options),
+        ["<article>\n", a, b, bibliography, "</article>\n"].concat()
+    );
+    assert_eq!(
+        ::html::format_section(root, 1, 0, &options).unwrap(),
+        ["<article>\n", a, bibliography, "</article>\n"].concat()
+    );
+    assert_eq!(
+        ::html::format_section(root, 1, 1, &options).unwrap(),
+        ["<article>\n", b, bibliography, "</article>\n"].concat()
+    );
+}
+
+#[test]
+fn smart_dashes() {
+    html_opts(
+        concat!(
+            "en--dash, em---dash, and a - hyphen.\n",
+            "\n",
+            "4 ---- 5 ----- 6 ------ 7 ------- 10 ----------\n",
+            "\n",
+            "\"Quotes\" and 'apostrophes'... stay. `code -- spans` too.\n",
+            "\n",
+            "---\n"
+        ),
+        concat!(
+            "<p>en\u{2013}dash, em\u{2014}dash, and a - hyphen.</p>\n",
+            "<p>4 \u{2013}\u{2013} 5 \u{2014}\u{2013} 6 \u{2014}\u{2014} ",
+            "7 \u{2014}\u{2013}\u{2013} 10 \u{2013}\u{2013}\u{2013}\u{2013}\u{2013}</p>\n",
+            "<p>&quot;Quotes&quot; and 'apostrophes'... stay. <code>code -- spans</code> too.</p>\n",
+            "<hr />\n"
+        ),
+        |opts| opts.ext_smart_dashes = true,
+    );
+
+    html_opts(
+        "See http://a--b.com/x--y, www.c--d.org or e--f@g.com -- [a--b](/x).\n",
+        concat!(
+            "<p>See <a href=\"http://a--b.com/x--y\">http://a--b.com/x--y</a>, ",
+            "<a href=\"http://www.c--d.org\">www.c--d.org</a> or ",
+            "<a href=\"mailto:e--f@g.com\">e--f@g.com</a> \u{2013} ",
+            "<a href=\"/x\">a\u{2013}b</a>.</p>\n"
+        ),
+        |opts| {
+            opts.ext_smart_dashes = true;
+            opts.ext_autolink = true;
+        },
+    );
+}
+
+#[test]
+fn optimize_simple_paragraphs() {
+    let input = concat!(
+        "Plain text.\n",
+        "\n",
+        "> Quoted & plain.\n",
+        "\n",
+        "- tight\n",
+        "- list\n",
+        "\n",
+        "Between.\n",
+        "\n",
+        "1. loose\n",
+        "\n",
+        "2. list\n",
+        "\n",
+        "With *emphasis*.\n",
+        "\n",
+        "Two\n",
+        "lines.\n"
+    );
+    let expected = concat!(
+        "<p>Plain text.</p>\n",
+        "<blockquote>\n",
+        "<p>Quoted &amp; plain.</p>\n",
+        "</blockquote>\n",
+        "<ul>\n",
+        "<li>tight</li>\n",
+        "<li>list</li>\n",
+        "</ul>\n",
+        "<p>Between.</p>\n",
+        "<ol>\n",
+        "<li>\n",
+        "<p>loose</p>\n",
+        "</li>\n",
+        "<li>\n",
+        "<p>list</p>\n",
+        "</li>\n",
+        "</ol>\n",
+        "<p>With <em>emphasis</em>.</p>\n",
+        "<p>Two\n",
+        "lines.</p>\n"
+    );
+    html(input, expected);
+    html_opts(input, expected, |opts| opts.optimize_simple_paragraphs = true);
+
+    let arena = Arena::new();
+    let mut options = ComrakOptions::default();
+    options.optimize_simple_paragraphs = true;
+    let root = parse_document(&arena, input, &options);
+    let simple = root.descendants()
+        .filter_map(|n| match n.data.borrow().value {
+            NodeValue::SimpleParagraph(ref literal) => Some(literal.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        simple,
+        vec!["Plain text.", "Quoted & plain.", "tight", "list", "Between.", "loose", "list"]
+    );
+    assert!(root.descendants().all(|n| match n.data.borrow().value {
+        NodeValue::SimpleParagraph(..) => n.first_child().is_none(),
+        _ => true,
+    }));
+}
+
+#[test]
+fn inline_extension_elements() {
+    html_opts(
+        "~~gone~~ ~under~ e = mc^2^\n",
+        concat!(
+            "<p><span class=\"deleted\">gone</span> <ins>under</ins> ",
+            "e = mc<span class=\"sup\">2</span></p>\n"
+        ),
+        |opts| {
+            opts.ext_strikethrough = true;
+            opts.ext_underline = true;
+            opts.ext_superscript = true;
+            opts.strikethrough_element = Some("span".to_string());
+            opts.strikethrough_class = Some("deleted".to_string());
+            opts.underline_element = Some("ins".to_string());
+            opts.superscript_element = Some("span".to_string());
+            opts.superscript_class = Some("sup".to_string());
+        },
+    );
+
+    html_opts(
+        "~~gone~~ ~under~ e = mc^2^\n",
+        "<p><del class=\"a&amp;b\">gone</del> <u>under</u> e = mc<sup>2</sup></p>\n",
+        |opts| {
+            opts.ext_strikethrough = true;
+            opts.ext_underline = true;
+            opts.ext_superscript = true;
+            opts.strikethrough_class = Some("a&b".to_string());
+        },
+    );
+}
+
+#[test]
+fn superscript() {
+    html_opts(concat!("e = mc^2^.\n"),
+              concat!("<p>e = mc<sup>2</sup>.</p>\n"),
+              |opts| opts.ext_superscript = true);
+}
+
+#[test]
+fn highlight() {
+    html_opts(
+        concat!(
+            "A ==key *point*==, x==y==z.\n",
+            "\n",
+            "=one= ===three=== a == b == c ==open\n",
+            "\n",
+            "Title\n",
+            "===\n"
+        ),
+        concat!(
+            "<p>A <mark>key <em>point</em></mark>, x<mark>y</mark>z.</p>\n",
+            "<p>=one= ===three=== a == b == c ==open</p>\n",
+            "<h1>Title</h1>\n"
+        ),
+        |opts| opts.ext_highlight = true,
+    );
+
+    html_opts(
+        "==key==\n",
+        "<p><span class=\"hl\">key</span></p>\n",
+        |opts| {
+            opts.ext_highlight = true;
+            opts.highlight_element = Some("span".to_string());
+            opts.highlight_class = Some("hl".to_string());
+        },
+    );
+
+    html("==key==\n", "<p>==key==</p>\n");
+}
+
+#[test]
+fn collapsible() {
+    html_opts(
+        concat!(
+            ":::details Click *here*\n",
+            "Hidden **content**.\n",
+            ":::\n"
+        ),
+        concat!(
+            "<details>\n",
+            "<summary>Click *here*</summary>\n",
+            "<p>Hidden <strong>content</strong>.</p>\n",
+            "</details>\n"
+        ),
+        |opts| opts.ext_collapsible = true,
+    );
+}
+
+#[test]
+fn collapsible_nested() {
+    html_opts(
+        concat!(
+            "::::details Outer\n",
+            "One.\n",
+            "\n",
+            ":::details Inner\n",
+            "Two.\n",
+            ":::\n",
+            "Three.\n",
+            "::::\n",
+            "Four.\n"
+        ),
+        concat!(
+            "<details>\n",
+            "<summary>Outer</summary>\n",
+            "<p>One.</p>\n",
+            "<details>\n",
+            "<summary>Inner</summary>\n",
+            "<p>Two.</p>\n",
+            "</details>\n",
+            "<p>Three.</p>\n",
+            "</details>\n",
+            "<p>Four.</p>\n"
+        ),
+        |opts| opts.ext_collapsible = true,
+    );
+
+    html_opts(
+        concat!(
+            ":::details Outer\n",
+            ":::details Inner\n",
+            "```\n",
+            ":::\n",
+            "```\n",
+            ":::\n",
+            ":::\n"
+        ),
+        concat!(
+            "<details>\n",
+            "<summary>Outer</summary>\n",
+            "<details>\n",
+            "<summary>Inner</summary>\n",
+            "<pre><code>:::\n",
+            "</code></pre>\n",
+            "</details>\n",
+            "</details>\n"
+        ),
+        |opts| opts.ext_collapsible = true,
+    );
+}
+
+#[test]
+fn collapsible_disabled() {
+    html(
+        concat!(":::details Title\n", "Text.\n", ":::\n"),
+        concat!("<p>:::details Title\n", "Text.\n", ":::</p>\n"),
+    );
+}
+
+#[test]
+fn fenced_divs() {
+    html_opts(
+        concat!("::: warning\n", "Be *careful*.\n", ":::\n"),
+        concat!(
+            "<div class=\"warning\">\n",
+            "<p>Be <em>careful</em>.</p>\n",
+            "</div>\n"
+        ),
+        |opts| opts.ext_fenced_divs = true,
+    );
+}
+
+#[test]
+fn fenced_divs_nested() {
+    html_opts(
+        concat!(
+            "::::: outer\n",
+            "One.\n",
+            "\n",
+            ":::: middle\n",
+            "::: inner\n",
+            "Two.\n",
+            ":::\n",
+            "Three.\n",
+            "::::\n",
+            ":::::\n",
+            "Four.\n"
+        ),
+        concat!(
+            "<div class=\"outer\">\n",
+            "<p>One.</p>\n",
+            "<div class=\"middle\">\n",
+            "<div class=\"inner\">\n",
+            "<p>Two.</p>\n",
+            "</div>\n",
+            "<p>Three.</p>\n",
+            "</div>\n",
+            "</div>\n",
+            "<p>Four.</p>\n"
+        ),
+        |opts| opts.ext_fenced_divs = true,
+    );
+}
+
+#[test]
+fn fenced_divs_attributes() {
+    html_opts(
+        concat!("::: {.a #b key=\"some value\"}\n", "Text.\n", ":::\n"),
+        concat!(
+            "<div id=\"b\" class=\"a\" key=\"some value\">\n",
+            "<p>Text.</p>\n",
+            "</div>\n"
+        ),
+        |opts| opts.ext_fenced_divs = true,
+    );
+}
+
+#[test]
+fn fenced_divs_trailing_text() {
+    html_opts(
+        concat!("::: note Some title\n", "Text.\n", ":::\n"),
+        concat!("<p>::: note Some title\n", "Text.\n", ":::</p>\n"),
+        |opts| opts.ext_fenced_divs = true,
+    );
+    html_opts(
+        concat!("::: details Title\n", "Text.\n", ":::\n"),
+        concat!("<p>::: details Title\n", "Text.\n", ":::</p>\n"),
+        |opts| opts.ext_fenced_divs = true,
+    );
+    html_opts(
+        concat!("::: {.a} b\n", "Text.\n", ":::\n"),
+        concat!("<p>::: {.a} b\n", "Text.\n", ":::</p>\n"),
+        |opts| opts.ext_fenced_divs = true,
+    );
+}
+
+#[test]
+fn fenced_divs_with_collapsible() {
+    html_opts(
+        concat!(
+            ":::: note\n",
+            ":::details More\n",
+            "Hidden.\n",
+            ":::\n",
+            "::::\n"
+        ),
+        concat!(
+            "<div class=\"note\">\n",
+            "<details>\n",
+            "<summary>More</summary>\n",
+            "<p>Hidden.</p>\n",
+            "</details>\n",
+            "</div>\n"
+        ),
+        |opts| {
+            opts.ext_fenced_divs = true;
+            opts.ext_collapsible = true;
+        },
+    );
+}
+
+#[test]
+fn diff() {
+    let arena = Arena::new();
+    let options = ComrakOptions::default();
+    let old = parse_document(
+        &arena,
+        "# Title\n\nFirst *para*.\n\nGone.\n\n* a\n* b\n",
+        &options,
+    );
+    let new = parse_document(
+        &arena,
+        "# Title\n\nFirst **para**.\n\n* a\n* b\n\n---\n",
+        &options,
+    );
+
+    let summary = diff_ast(old, new)
+        .iter()
+        .map(|d| match *d {
+            AstDiff::Added(_) => "+",
+            AstDiff::Removed(_) => "-",
+            AstDiff::Changed { .. } => "~",
+            AstDiff::Unchanged(_) => "=",
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    // Heading; paragraph (text, emph changed to strong, text); removed
+    // paragraph; list; added thematic break.
+    assert_eq!(summary, "= ~ = - + = - = +");
+}
+
+#[test]
+fn document_statistics() {
+    let input = concat!(
+        "# A *fine* title\n",
+        "\n",
+        "Setext\n",
+        "---\n",
+        "\n",
+        "One two  three\tfour.\n",
+        "\n",
+        "- [link](/a) and ![image](/b.png)\n",
+        "- > quoted words `not counted`\n",
+        "\n",
+        "```\nnot counted either\n```\n",
+        "\n",
+        "<div>raw html</div>\n"
+    );
+
+    let arena = Arena::new();
+    let mut options = ComrakOptions::default();
+    let stats = ::document_statistics(parse_document(&arena, input, &options));
+    assert_eq!(
+        stats,
+        ::DocumentStats {
+            word_count: 13,
+            paragraph_count: 3,
+            heading_count: 2,
+            code_block_count: 1,
+            link_count: 1,
+            image_count: 1,
+            estimated_reading_time_minutes: 0.065,
+        }
+    );
+
+    options.optimize_simple_paragraphs = true;
+    let arena = Arena::new();
+    assert_eq!(::document_statistics(parse_document(&arena, input, &options)), stats);
+
+    let arena = Arena::new();
+    assert_eq!(::document_statistics(parse_document(&arena, "", &options)), Default::default());
+}
+
+#[test]
+fn unwrap_children() {
+    let arena = Arena::new();
+    let options = ComrakOptions::default();
+    let root = parse_document(&arena, "Before.\n\n> One.\n>\n> Two.\n\nAfter.\n", &options);
+
+    let quote = root.children()
+        .find(|n| matches!(n.data.borrow().value, NodeValue::BlockQuote))
+        .unwrap();
+    quote.unwrap_children();
+
+    assert!(quote.parent().is_none());
+    assert!(quote.first_child().is_none());
+    assert_eq!(
+        root.children()
+            .map(|n| matches!(n.data.borrow().value, NodeValue::Paragraph))
+            .collect::<Vec<_>>(),
+        [true, true, true, true]
+    );
+    assert_eq!(
+        ::html::format_document(root, &options),
+        "<p>Before.</p>\n<p>One.</p>\n<p>Two.</p>\n<p>After.</p>\n"
+    );
+
+    // A node without children simply disappears.
+    let first = root.first_child().unwrap();
+    first.first_child().unwrap().unwrap_children();
+    assert!(first.first_child().is_none());
+
+    // Nor does a node without a parent lose its children.
+    root.unwrap_children();
+    assert_eq!(root.children().count(), 4);
+}
+
+#[test]
+fn html_block_types() {
+    use nodes::{node_html_block_type, HtmlBlockType};
+    use std::convert::TryFrom;
+
+    let arena = Arena::new();
+    let options = ComrakOptions::default();
+    let root = parse_document(
+        &arena,
+        concat!(
+            "<pre>\nx\n</pre>\n\n",
+            "<!-- x -->\n\n",
+            "<?x ?>\n\n",
+            "<!DOCTYPE html>\n\n",
+            "<![CDATA[x]]>\n\n",
+            "<div>\n\n",
+            "<custom-tag>\n\n",
+            "Text.\n"
+        ),
+        &options,
+    );
+
+    let types = root.children().map(node_html_block_type).collect::<Vec<_>>();
+    assert_eq!(
+        types,
+        [
+            Some(HtmlBlockType::ScriptPreStyle),
+            Some(HtmlBlockType::Comment),
+            Some(HtmlBlockType::ProcessingInstruction),
+            Some(HtmlBlockType::Declaration),
+            Some(HtmlBlockType::CdataSection),
+            Some(HtmlBlockType::BlockLevelTag),
+            Some(HtmlBlockType::CompleteTag),
+            None,
+        ]
+    );
+
+    for n in 1..8 {
+        assert_eq!(HtmlBlockType::try_from(n).unwrap() as u8, n);
+    }
+    assert_eq!(HtmlBlockType::try_from(0), Err(0));
+    assert_eq!(HtmlBlockType::try_from(8), Err(8));
+}
+
+#[test]
+fn table_alignments() {
+    html_opts(
+        concat!("| a | b | c | d |\n", "| --- | :-- | --: | :-: |\n", "| e | f | g | h |\n"),
+        concat!(
+            "<table>\n",
+            "<thead>\n",
+            "<tr>\n",
+            "<th>a</th>\n",
+            "<th align=\"left\">b</th>\n",
+            "<th align=\"right\">c</th>\n",
+            "<th align=\"center\">d</th>\n",
+            "</tr>\n",
+            "</thead>\n",
+            "<tbody>\n",
+            "<tr>\n",
+            "<td>e</td>\n",
+            "<td align=\"left\">f</td>\n",
+            "<td align=\"right\">g</td>\n",
+            "<td align=\"center\">h</td>\n",
+            "</tr></tbody></table>\n"
+        ),
+        |opts| opts.ext_table = true,
+    );
+}
+
+#[test]
+fn table_alignment_classes() {
+    html_opts(
+        concat!("| a | b | c | d |\n", "| --- | :-- | --: | :-: |\n", "| e | f | g | h |\n"),
+        concat!(
+            "<table>\n",
+            "<thead>\n",
+            "<tr>\n",
+            "<th>a</th>\n",
+            "<th class=\"align-left\">b</th>\n",
+            "<th class=\"align-right\">c</th>\n",
+            "<th class=\"align-center\">d</th>\n",
+            "</tr>\n",
+            "</thead>\n",
+            "<tbody>\n",
+            "<tr>\n",
+            "<td>e</td>\n",
+            "<td class=\"align-left\">f</td>\n",
+            "<td class=\"align-right\">g</td>\n",
+            "<td class=\"align-center\">h</td>\n",
+            "</tr></tbody></table>\n"
+        ),
+        |opts| {
+            opts.ext_table = true;
+            opts.table_alignment_class_prefix = Some("align-".to_string());
+        },
+    );
+}
+
+#[test]
+fn code_line_numbers() {
+    // Without code_linenos_token, linenos is a language like any other.
+    html(
+        concat!("```linenos\n", "one\n", "```\n"),
+        "<pre><code class=\"language-linenos\">one\n</code></pre>\n",
+    );
+
+    html_opts(
+        concat!("``` rust linenos\n", "fn main() {\n", "    a < b;\n", "}\n", "```\n"),
+        concat!(
+            "<pre><code class=\"language-rust\">",
+            "<span class=\"line\" data-line=\"1\">fn main() {</span>\n",
+            "<span class=\"line\" data-line=\"2\">    a &lt; b;</span>\n",
+            "<span class=\"line\" data-line=\"3\">}</span>\n",
+            "</code></pre>\n"
+        ),
+        |opts| opts.code_linenos_token = true,
+    );
+
+    html_opts(
+        concat!("```linenos\n", "one\n", "\n", "three\n", "```\n"),
+        concat!(
+            "<pre><code>",
+            "<span class=\"line\" data-line=\"1\">one</span>\n",
+            "<span class=\"line\" data-line=\"2\"></span>\n",
+            "<span class=\"line\" data-line=\"3\">three</span>\n",
+            "</code></pre>\n"
+        ),
+        |opts| {
+            opts.github_pre_lang = true;
+            opts.code_linenos_token = true;
+        },
+    );
+
+    html_opts(
+        concat!("``` rust\n", "one\n", "two\n", "```\n"),
+        concat!(
+            "<pre lang=\"rust\"><code>",
+            "<span class=\"line\" data-line=\"1\">one</span>\n",
+            "<span class=\"line\" data-line=\"2\">two</span>\n",
+            "</code></pre>\n"
+        ),
+        |opts| {
+            opts.github_pre_lang = true;
+            opts.code_line_numbers = true;
+        },
+    );
+}
+
+#[test]
+fn gfm_quirks() {
+    let input = concat!(
+        "Visit www.github.com ~~now~~.\n",
+        "\n",
+        "- [x] done\n",
+        "\n",
+        "| a |\n",
+        "|---|\n",
+        "| b |\n",
+        "\n",
+        "<xmp>\n",
+        "\n",
+        "```rust\n",
+        "x\n",
+        "```\n"
+    );
+
+    html(
+        input,
+        concat!(
+            "<p>Visit www.github.com ~~now~~.</p>\n",
+            "<ul>\n",
+            "<li>[x] done</li>\n",
+            "</ul>\n",
+            "<p>| a |\n",
+            "|---|\n",
+            "| b |</p>\n",
+            "<xmp>\n",
+            "<pre><code class=\"language-rust\">x\n",
+            "</code></pre>\n"
+        ),
+    );
+
+    html_opts(
+        input,
+        concat!(
+            "<p>Visit <a href=\"http://www.github.com\">www.github.com</a> <del>now</del>.</p>\n",
+            "<ul>\n",
+            "<li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> done</li>\n",
+            "</ul>\n",
+            "<table>\n",
+            "<thead>\n",
+            "<tr>\n",
+            "<th>a</th>\n",
+            "</tr>\n",
+            "</thead>\n",
+            "<tbody>\n",
+            "<tr>\n",
+            "<td>b</td>\n",
+            "</tr></tbody></table>\n",
+            "&lt;xmp>\n",
+            "<pre lang=\"rust\"><code>x\n",
+            "</code></pre>\n"
+        ),
+        |opts| opts.gfm_quirks = true,
+    );
+}
+
+#[test]
+fn github_pre_lang_multiple_tokens() {
+    html_opts(
+        concat!(
+            "```rust,no_run\nfn main() {}\n```\n",
+            "``` rust,ignore extra words\nfn main() {}\n```\n",
+            "```a<b>\"c,d\n-\n```\n"
+        ),
+        concat!(
+            "<pre lang=\"rust\"><code>fn main() {}\n</code></pre>\n",
+            "<pre lang=\"rust\"><code>fn main() {}\n</code></pre>\n",
+            "<pre lang=\"a&lt;b&gt;&quot;c\"><code>-\n</code></pre>\n"
+        ),
+        |opts| opts.github_pre_lang = true,
+    );
+
+    // Without github_pre_lang, the whole first word is used as in cmark.
+    html(
+        "```rust,no_run\nfn main() {}\n```\n",
+        "<pre><code class=\"language-rust,no_run\">fn main() {}\n</code></pre>\n",
+    );
+}
+
+#[test]
+fn hardbreaks_paragraph_only() {
+    let arena = Arena::new();
+    let mut options = ComrakOptions::default();
+    options.hardbreaks = true;
+    options.hardbreaks_paragraph_only = true;
+
+    let root = parse_document(
+        &arena,
+        concat!(
+            "Setext\n",
+            "heading\n",
+            "---\n",
+            "\n",
+            "> A [quoted\n",
+            "> link](/url).\n"
+        ),
+        &options,
+    );
+    compare_strs(
+        &::html::format_document(root, &options),
+        concat!(
+            "<h2>Setext\n",
+            "heading</h2>\n",
+            "<blockquote>\n",
+            "<p>A <a href=\"/url\">quoted<br />\n",
+            "link</a>.</p>\n",
+            "</blockquote>\n"
+        ),
+        "regular",
+    );
+}
+
+#[test]
+fn ignore_trailing_space_breaks() {
+    let input = concat!(
+        "Two spaces  \n",
+        "three spaces   \n",
+        "backslash\\\n",
+        "end  \n",
+        "# Heading\n",
+        "Code `span  \n",
+        "across` lines.\n"
+    );
+
+    html(
+        input,
+        concat!(
+            "<p>Two spaces<br />\n",
+            "three spaces<br />\n",
+            "backslash<br />\n",
+            "end</p>\n",
+            "<h1>Heading</h1>\n",
+            "<p>Code <code>span across</code> lines.</p>\n"
+        ),
+    );
+    html_opts(
+        input,
+        concat!(
+            "<p>Two spaces\n",
+            "three spaces\n",
+            "backslash<br />\n",
+            "end</p>\n",
+            "<h1>Heading</h1>\n",
+            "<p>Code <code>span across</code> lines.</p>\n"
+        ),
+        |opts| opts.ignore_trailing_space_breaks = true,
+    );
+}
+
+#[test]
+fn code_highlighted_lines() {
+    html(
+        concat!("``` rust {2}\n", "one\n", "two\n", "three\n", "```\n"),
+        concat!(
+            "<pre><code class=\"language-rust\">",
+            "one\n",
+            "<span class=\"highlighted\">two</span>\n",
+            "three\n",
+            "</code></pre>\n"
+        ),
+    );
+
+    html(
+        concat!("```{1,3-4,9,x,5-2}\n", "one\n", "two\n", "three\n", "four\n", "```\n"),
+        concat!(
+            "<pre><code>",
+            "<span class=\"highlighted\">one</span>\n",
+            "two\n",
+            "<span class=\"highlighted\">three</span>\n",
+            "<span class=\"highlighted\">four</span>\n",
+            "</code></pre>\n"
+        ),
+    );
+
+    html_opts(
+        concat!("``` rust linenos {1-2}\n", "one\n", "two\n", "three\n", "```\n"),
+        concat!(
+            "<pre><code class=\"language-rust\">",
+            "<span class=\"line highlighted\" data-line=\"1\">one</span>\n",
+            "<span class=\"line highlighted\" data-line=\"2\">two</span>\n",
+            "<span class=\"line\" data-line=\"3\">three</span>\n",
+            "</code></pre>\n"
+        ),
+        |opts| opts.code_linenos_token = true,
+    );
+}
+
+#[test]
+fn known_languages() {
+    let known = || Some(vec!["rust".to_string(), "Python".to_string()]);
+
+    html_opts(
+        concat!("``` rust\n", "fn main() {}\n", "```\n"),
+        concat!(
+            "<pre><code class=\"language-rust\">fn main() {}\n",
+            "</code></pre>\n"
+        ),
+        |opts| opts.known_languages = known(),
+    );
+
+    html_opts(
+        concat!("```python\n", "pass\n", "```\n"),
+        concat!("<pre><code class=\"language-python\">pass\n", "</code></pre>\n"),
+        |opts| opts.known_languages = known(),
+    );
+
+    html_opts(
+        concat!("``` console output\n", "$ ls\n", "```\n"),
+        concat!("<pre><code>$ ls\n", "</code></pre>\n"),
+        |opts| opts.known_languages = known(),
+    );
+
+    html_opts(
+        concat!("``` console\n", "$ ls\n", "```\n"),
+        concat!("<pre><code>$ ls\n", "</code></pre>\n"),
+        |opts| {
+            opts.known_languages = known();
+            opts.github_pre_lang = true;
+        },
+    );
+}
+
+#[test]
+fn code_language_aliases() {
+    let aliases = |opts: &mut ComrakOptions| {
+        opts.code_language_aliases.insert("js".to_string(), "javascript".to_string());
+        opts.code_language_aliases.insert("py".to_string(), "python".to_string());
+    };
+
+    html_opts(
+        concat!(
+            "```js\nf()\n```\n",
+            "```javascript\nf()\n```\n",
+            "```JavaScript\nf()\n```\n",
+            "```rust\nf()\n```\n"
+        ),
+        concat!(
+            "<pre><code class=\"language-javascript\">f()\n</code></pre>\n",
+            "<pre><code class=\"language-javascript\">f()\n</code></pre>\n",
+            "<pre><code class=\"language-javascript\">f()\n</code></pre>\n",
+            "<pre><code class=\"language-rust\">f()\n</code></pre>\n"
+        ),
+        aliases,
+    );
+
+    html_opts(
+        concat!("``` PY\npass\n```\n", "```js\nf()\n```\n"),
+        concat!(
+            "<pre lang=\"python\"><code>pass\n</code></pre>\n",
+            "<pre><code>f()\n</code></pre>\n"
+        ),
+        |opts| {
+            aliases(opts);
+            opts.known_languages = Some(vec!["python".to_string()]);
+            opts.github_pre_lang = true;
+        },
+    );
+}
+
+#[test]
+fn raw_parse() {
+    let arena = Arena::new();
+    let root = parse_document_raw(
+        &arena,
+        concat!(
+            "# A *heading*\n",
+            "\n",
+            "Some **strong**\n",
+            "  [text][ref].\n",
+            "\n",
+            "[ref]: /url\n"
+        ),
+        &ComrakOptions::default(),
+    );
+
+    let blocks = root.children().collect::<Vec<_>>();
+    assert_eq!(blocks.len(), 2);
+    for block in &blocks {
+        assert!(block.first_child().is_none());
+    }
+
+    let heading = blocks[0].data.borrow();
+    match heading.value {
+        NodeValue::Heading(ref nh) => assert_eq!(nh.level, 1),
+        _ => panic!("expected a heading"),
+    }
+    assert_eq!(heading.content, "A *heading*");
+
+    let paragraph = blocks[1].data.borrow();
+    match paragraph.value {
+        NodeValue::Paragraph => (),
+        _ => panic!("expected a paragraph"),
+    }
+    assert_eq!(paragraph.content, "Some **strong**\n[text][ref].\n");
+}
+
+#[test]
+fn nbsp_and_soft_hyphen_preserved() {
+    html(
+        concat!(
+            "a\u{a0}b soft\u{ad}hyphen `x\u{a0}\u{a0}y`\n",
+            "\n",
+            "\u{a0}leading and trailing\u{a0}\n"
+        ),
+        concat!(
+            "<p>a\u{a0}b soft\u{ad}hyphen <code>x\u{a0}\u{a0}y</code></p>\n",
+            "<p>\u{a0}leading and trailing\u{a0}</p>\n"
+        ),
+    );
+
+    // Only ASCII whitespace is collapsed when normalizing link labels.
+    html(
+        "[a\u{a0}\u{a0}b] [a\u{a0}b]\n\n[a\u{a0}\u{a0}b]: /u\n",
+        "<p><a href=\"/u\">a\u{a0}\u{a0}b</a> [a\u{a0}b]</p>\n",
+    );
+}
+
+#[test]
+fn reference_label_brackets() {
+    // Unescaped brackets are never allowed in a link label, balanced or not.
+    html(
+        "[foo [bar]]\n\n[foo [bar]]: /url\n",
+        "<p>[foo [bar]]</p>\n<p>[foo [bar]]: /url</p>\n",
+    );
+    html(
+        "[foo][ref[]\n\n[ref[]: /uri\n",
+        "<p>[foo][ref[]</p>\n<p>[ref[]: /uri</p>\n",
+    );
+
+    html(
+        concat!(
+            "[Smith \\[2021\\]], [smith \\[2021\\]][], and [see [Smith \\[2021\\]] here]\n",
+            "\n",
+            "[Smith \\[2021\\]]: /smith\n"
+        ),
+        concat!(
+            "<p><a href=\"/smith\">Smith [2021]</a>, <a href=\"/smith\">smith [2021]</a>, ",
+            "and [see <a href=\"/smith\">Smith [2021]</a> here]</p>\n"
+        ),
+    );
+    html(
+        "[foo][ref\\[]\n\n[ref\\[]: /uri\n",
+        "<p><a href=\"/uri\">foo</a></p>\n",
+    );
+    html(
+        "[bar\\\\]: /uri\n\n[bar\\\\]\n",
+        "<p><a href=\"/uri\">bar\\</a></p>\n",
+    );
+
+    // Balanced brackets are allowed in link text, though not in the label.
+    html(
+        "[link [foo [bar]]][ref]\n\n[ref]: /uri\n",
+        "<p><a href=\"/uri\">link [foo [bar]]</a></p>\n",
+    );
+}
+
+#[test]
+fn link_resolver() {
+    html_opts(
+        concat!(
+            "[*Docs* page](/docs) [out](http://e.org) [kept](/kept \"Keep\")\n",
+            "\n",
+            "![A `cat`](/cat.png)\n"
+        ),
+        concat!(
+            "<p><a href=\"https://example.com/docs#Docs%20page\"><em>Docs</em> page</a> ",
+            "<a href=\"http://e.org\">out</a> <a href=\"/kept\" title=\"Keep\">kept</a></p>\n",
+            "<p><img src=\"https://example.com/cat.png#A%20cat\" alt=\"A cat\" /></p>\n"
+        ),
+        |opts| {
+            opts.link_resolver = Some(LinkResolver::new(|url, title, text| {
+                if url.starts_with('/') && title != "Keep" {
+                    Some(format!("https://example.com{}#{}", url, text))
+                } else {
+                    None
+                }
+            }))
+        },
+    );
+}
+
+#[test]
+fn block_macros() {
+    html_opts(
+        concat!(
+            "@@version-badge 1.0.0\n",
+            "@@include-snippet   src/lib.rs:10-20  \n",
+            "Some text\n",
+            "@@toc\n",
+            "\n",
+            "    @@version-badge 2.0\n",
+            "\n",
+            "@@ not-a-macro @@1 @@x!\n"
+        ),
+        concat!(
+            "<span class=\"badge\">v1.0.0</span>\n",
+            "<pre>src/lib.rs:10-20</pre>\n",
+            "<p>Some text</p>\n",
+            "<!-- unknown macro: toc -->\n",
+            "<pre><code>@@version-badge 2.0\n",
+            "</code></pre>\n",
+            "<p>@@ not-a-macro @@1 @@x!</p>\n"
+        ),
+        |opts| {
+            opts.ext_block_macros = true;
+            opts.block_macro_handlers.insert(
+                "version-badge".to_string(),
+                MacroHandler::new(|args| format!("<span class=\"badge\">v{}</span>", args)),
+            );
+            opts.block_macro_handlers.insert(
+                "include-snippet".to_string(),
+                MacroHandler::new(|args| format!("<pre>{}</pre>\n", args)),
+            );
+        },
+    );
+
+    html("@@toc\n", "<p>@@toc</p>\n");
+}
+
+#[test]
+fn empty_titles_omitted() {
+    html(
+        "[a](/u \"T\") [b](/u) [c](/u \"\") [d][ref]\n\n[ref]: /r ''\n",
+        concat!(
+            "<p><a href=\"/u\" title=\"T\">a</a> <a href=\"/u\">b</a> ",
+            "<a href=\"/u\">c</a> <a href=\"/r\">d</a></p>\n"
+        ),
+    );
+    html(
+        "![a](/i \"T\") ![b](/i) ![c](/i ())\n",
+        concat!(
+            "<p><img src=\"/i\" alt=\"a\" title=\"T\" /> <img src=\"/i\" alt=\"b\" /> ",
+            "<img src=\"/i\" alt=\"c\" /></p>\n"
+        ),
+    );
+}
+
+#[test]
+fn inline_macros() {
+    let setup = |opts: &mut ComrakOptions| {
+        opts.ext_inline_macros = true;
+        opts.inline_macro_handlers.insert(
+            "abbr".to_string(),
+            MacroHandler::new(|args| {
+                let mut parts = args.splitn(2, '|');
+                let text = parts.next().unwrap();
+                let title = parts.next().unwrap_or("");
+                format!("<abbr title=\"{}\">{}</abbr>", title, text)
+            }),
+        );
+        opts.inline_macro_handlers.insert(
+            "args".to_string(),
+            MacroHandler::new(|args| format!("[{}]", args.len())),
+        );
+    };
+
+    html_opts(
+        "The {abbr:WWW|World Wide Web} and {args:}.\n",
+        "<p>The <abbr title=\"World Wide Web\">WWW</abbr> and [0].</p>\n",
+        setup,
+    );
+    html_opts(
+        "{args:a *b* <c> & `d`} {args:[x](y)}\n",
+        "<p>[15] [6]</p>\n",
+        setup,
+    );
+    html_opts(
+        "An {unknown:macro} here.\n",
+        "<p>An <!-- unknown macro: unknown --> here.</p>\n",
+        setup,
+    );
+    html_opts(
+        "Not {1:x}, {abbr}, {abbr:\nx}, {:x} or {abbr:unclosed.\n",
+        "<p>Not {1:x}, {abbr}, {abbr:\nx}, {:x} or {abbr:unclosed.</p>\n",
+        setup,
+    );
+    html("{abbr:WWW|World Wide Web}\n", "<p>{abbr:WWW|World Wide Web}</p>\n");
+}
+
+#[test]
+fn escaped_blockquote_marker_roundtrip() {
+    html(
+        "\\> Not a blockquote\n",
+        "<p>&gt; Not a blockquote</p>\n",
+    );
+
+    let arena = Arena::new();
+    let options = ComrakOptions::default();
+    let root = parse_document(&arena, "\\> Not a blockquote\n", &options);
+    let md = cm::format_document(root, &options);
+    assert_eq!(md, "\\> Not a blockquote\n");
+
+    let root = parse_document(&arena, &md, &options);
+    let block = root.first_child().unwrap();
+    match block.data.borrow().value {
+        NodeValue::Paragraph => (),
+        ref other => panic!("expected a paragraph, got {:?}", other),
+    };
+}
+
+#[test]
+fn link_destination_angle_brackets() {
+    html(
+        "[a](<foo)bar>) ![b](<foo(bar>)\n",
+        "<p><a href=\"foo)bar\">a</a> <img src=\"foo(bar\" alt=\"b\" /></p>\n",
+    );
+    html("[a](a&#32;b)\n", "<p><a href=\"a%20b\">a</a></p>\n");
+
+    let arena = Arena::new();
+    let options = ComrakOptions::default();
+    for &(input, expected) in &[
+        ("[a](<foo)bar>)\n", "[a](<foo)bar>)\n"),
+        ("[a](foo(bar))\n", "[a](<foo(bar)>)\n"),
+        ("[a](<x(y> \"t\")\n", "[a](<x(y> \"t\")\n"),
+        ("[a](a&#32;b)\n", "[a](a%20b)\n"),
+        ("[a](a&#32;(b))\n", "[a](<a%20(b)>)\n"),
+    ] {
+        let root = parse_document(&arena, input, &options);
+        let md = cm::format_document(root, &options);
+        assert_eq!(md, expected);
+        let root = parse_document(&arena, &md, &options);
+        assert_eq!(cm::format_document(root, &options), expected);
+    }
+
+    html("[a](<1\n2>)\n", "<p>[a](&lt;1\n2&gt;)</p>\n");
+}
+
+#[test]
+fn column_blocks() {
+    html_opts(
+        concat!(
+            "||| ## Left    ||| ## Right |||\n",
+            "||| Some *text* ||| * one   |||\n",
+            "||| continued   ||| * two   |||\n",
+            "|||             |||         |||\n",
+            "||| Second.     ||| [link]  |||\n",
+            "\n",
+            "After.\n",
+            "\n",
+            "[link]: /url\n"
+        ),
+        concat!(
+            "<div class=\"columns\">\n",
+            "<div class=\"column\">\n",
+            "<h2>Left</h2>\n",
+            "<p>Some <em>text</em>\n",
+            "continued</p>\n",
+            "<p>Second.</p>\n",
+            "</div>\n",
+            "<div class=\"column\">\n",
+            "<h2>Right</h2>\n",
+            "<ul>\n",
+            "<li>one</li>\n",
+            "<li>two</li>\n",
+            "</ul>\n",
+            "<p><a href=\"/url\">link</a></p>\n",
+            "</div>\n",
+            "</div>\n",
+            "<p>After.</p>\n"
+        ),
+        |opts| opts.ext_column_blocks = true,
+    );
+}
+
+#[test]
+fn column_blocks_row_shapes() {
+    html_opts(
+        concat!(
+            "||| a ||| b |||\n",
+            "||| c ||| d ||| e |||\n",
+            "> ||| f |||\n"
+        ),
+        concat!(
+            "<div class=\"columns\">\n",
+            "<div class=\"column\">\n",
+            "<p>a</p>\n",
+            "</div>\n",
+            "<div class=\"column\">\n",
+            "<p>b</p>\n",
+            "</div>\n",
+            "</div>\n",
+            "<div class=\"columns\">\n",
+            "<div class=\"column\">\n",
+            "<p>c</p>\n",
+            "</div>\n",
+            "<div class=\"column\">\n",
+            "<p>d</p>\n",
+            "</div>\n",
+            "<div class=\"column\">\n",
+            "<p>e</p>\n",
+            "</div>\n",
+            "</div>\n",
+            "<blockquote>\n",
+            "<div class=\"columns\">\n",
+            "<div class=\"column\">\n",
+            "<p>f</p>\n",
+            "</div>\n",
+            "</div>\n",
+            "</blockquote>\n"
+        ),
+        |opts| opts.ext_column_blocks = true,
+    );
+}
+
+#[test]
+fn column_blocks_disabled() {
+    html(
+        "||| a ||| b |||\n",
+        "<p>||| a ||| b |||</p>\n",
+    );
+}
+
+#[test]
+fn max_references() {
+    html_opts(
+        concat!(
+            "[one]: /1\n",
+            "[two]: /2\n",
+            "[one]: /ignored\n",
+            "[three]: /3\n",
+            "\n",
+            "[one] [two] [three]\n"
+        ),
+        concat!(
+            "<p><a href=\"/1\">one</a> <a href=\"/2\">two</a> [three]</p>\n"
+        ),
+        |opts| opts.max_references = 2,
+    );
+}
+
+#[test]
+fn keep_reference_definitions() {
+    html_opts(
+        concat!(
+            "[one]: /1\n",
+            "[Two]: </a(b)> 'Title'\n",
+            "Text [one] [two].\n"
+        ),
+        "<p>Text <a href=\"/1\">one</a> <a href=\"/a(b)\" title=\"Title\">two</a>.</p>\n",
+        |opts| opts.keep_reference_definitions = true,
+    );
+
+    let arena = Arena::new();
+    let mut options = ComrakOptions::default();
+    options.keep_reference_definitions = true;
+    let root = parse_document(
+        &arena,
+        "Intro.\n\n[one]: /1\n[Two]:\n  /2 \"Title\"\n\n[one]\n",
+        &options,
+    );
+    let children = root.children()
+        .map(|n| {
+            let ast = n.data.borrow();
+            match ast.value {
+                NodeValue::ReferenceDef { ref label, ref url, ref title } => {
+                    format!("{} def {} {} {:?}", ast.start_line, label, url, title)
+                }
+                NodeValue::Paragraph => format!("{} para", ast.start_line),
+                ref other => panic!("unexpected {:?}", other),
+            }
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        children,
+        vec!["1 para", "3 def one /1 \"\"", "4 def Two /2 \"Title\"", "7 para"]
+    );
+}
+
+#[test]
+fn autolink_contexts() {
+    let input = concat!(
+        "# See www.github.com\n",
+        "\n",
+        "Visit www.github.com.\n",
+        "\n",
+        "| www.github.com |\n",
+        "|----------------|\n",
+        "| www.github.com |\n"
+    );
+
+    html_opts(
+        input,
+        concat!(
+            "<h1>See www.github.com</h1>\n",
+            "<p>Visit <a href=\"http://www.github.com\">www.github.com</a>.</p>\n",
+            "<table>\n",
+            "<thead>\n",
+            "<tr>\n",
+            "<th>www.github.com</th>\n",
+            "</tr>\n",
+            "</thead>\n",
+            "<tbody>\n",
+            "<tr>\n",
+            "<td>www.github.com</td>\n",
+            "</tr></tbody></table>\n"
+        ),
+        |opts| {
+            opts.ext_autolink = true;
+            opts.ext_table = true;
+            opts.autolink_contexts = Some(vec![AutolinkContext::Paragraph]);
+        },
+    );
+
+    html_opts(
+        input,
+        concat!(
+            "<h1>See <a href=\"http://www.github.com\">www.github.com</a></h1>\n",
+            "<p>Visit www.github.com.</p>\n",
+            "<table>\n",
+            "<thead>\n",
+            "<tr>\n",
+            "<th><a href=\"http://www.github.com\">www.github.com</a></th>\n",
+            "</tr>\n",
+            "</thead>\n",
+            "<tbody>\n",
+            "<tr>\n",
+            "<td><a href=\"http://www.github.com\">www.github.com</a></td>\n",
+            "</tr></tbody></table>\n"
+        ),
+        |opts| {
+            opts.ext_autolink = true;
+            opts.ext_table = true;
+            opts.autolink_contexts =
+                Some(vec![AutolinkContext::Heading, AutolinkContext::TableCell]);
+        },
+    );
+}
+
+#[test]
+fn autolink_known_tlds() {
+    html_opts(
+        concat!(
+            "Visit www.GitHub.COM, http://localhost.localdomain/x or www.example.org.\n",
+            "\n",
+            "Mail me@example.com or me@host.internal.\n"
+        ),
+        concat!(
+            "<p>Visit <a href=\"http://www.GitHub.COM\">www.GitHub.COM</a>, ",
+            "http://localhost.localdomain/x or ",
+            "<a href=\"http://www.example.org\">www.example.org</a>.</p>\n",
+            "<p>Mail <a href=\"mailto:me@example.com\">me@example.com</a> or me@host.internal.</p>\n"
+        ),
+        |opts| {
+            opts.ext_autolink = true;
+            opts.autolink_known_tlds =
+                Some(["com", "org"].iter().map(|s| s.to_string()).collect());
+        },
+    );
+}
+
+#[test]
+fn mentions_and_hashtags() {
+    html_opts(
+        concat!(
+            "@alice likes #rust and #Rust_2018, says @bob_1.\n",
+            "\n",
+            "Mail alice@example.com or a@b@c; see `@code #code`, [@link](/x) and /p#frag.\n",
+            "\n",
+            "Issue #12, @@double and ##double.\n"
+        ),
+        concat!(
+            "<p><a href=\"/users/alice\">@alice</a> likes <a href=\"/tags/rust\">#rust</a> and ",
+            "<a href=\"/tags/Rust_2018\">#Rust_2018</a>, says ",
+            "<a href=\"/users/bob_1\">@bob_1</a>.</p>\n",
+            "<p>Mail alice@example.com or a@b@c; see <code>@code #code</code>, ",
+            "<a href=\"/x\">@link</a> and /p#frag.</p>\n",
+            "<p>Issue #12, @@double and ##double.</p>\n"
+        ),
+        |opts| {
+            opts.ext_mentions = true;
+            opts.ext_hashtags = true;
+        },
+    );
+
+    html_opts(
+        "@alice #rust\n",
+        "<p><a href=\"https://u.example/alice\">@alice</a> #rust</p>\n",
+        |opts| {
+            opts.ext_mentions = true;
+            opts.mention_url_template = Some("https://u.example/{}".to_string());
+        },
+    );
+}
+
+#[test]
+fn abbreviations() {
+    html_opts(
+        concat!(
+            "The HTML spec, not `HTML`, [HTML](/x), HTML5 or XHTML; the W3C and NASA.\n",
+            "\n",
+            "# HTML\n",
+            "\n",
+            "*[HTML]: HyperText <Markup> Language\n",
+            "*[W3C]: World Wide Web Consortium\n"
+        ),
+        concat!(
+            "<p>The <abbr title=\"HyperText &lt;Markup&gt; Language\">HTML</abbr> spec, not ",
+            "<code>HTML</code>, <a href=\"/x\">HTML</a>, HTML5 or XHTML; the ",
+            "<abbr title=\"World Wide Web Consortium\">W3C</abbr> and NASA.</p>\n",
+            "<h1><abbr title=\"HyperText &lt;Markup&gt; Language\">HTML</abbr></h1>\n"
+        ),
+        |opts| opts.ext_abbreviations = true,
+    );
+
+    html(
+        "The HTML spec.\n\n*[HTML]: HyperText Markup Language\n",
+        "<p>The HTML spec.</p>\n<p>*[HTML]: HyperText Markup Language</p>\n",
+    );
+}
+
+#[test]
+fn footnotes() {
+    html_opts(
+        concat!(
+            "Hi[^1], there[^Big] and[^nope], again[^1].\n",
+            "\n",
+            "[^big]: Second *note*.\n",
+            "\n",
+            "    With more.\n",
+            "\n",
+            "[^1]: First.\n",
+            "[^1]: Ignored.\n",
+            "\n",
+            "[^aside]: Never referenced.\n",
+            "\n",
+            "After.\n"
+        ),
+        concat!(
+            "<p>Hi<sup class=\"footnote-ref\">",
+            "<a href=\"#fn1\" id=\"fnref1\" role=\"doc-noteref\">1</a></sup>, ",
+            "there<sup class=\"footnote-ref\">",
+            "<a href=\"#fn2\" id=\"fnref2\" role=\"doc-noteref\">2</a></sup> ",
+            "and[^nope], again<sup class=\"footnote-ref\">",
+            "<a href=\"#fn1\" role=\"doc-noteref\">1</a></sup>.</p>\n",
+            "<p>After.</p>\n",
+            "<section class=\"footnotes\" role=\"doc-endnotes\">\n",
+            "<ol>\n",
+            "<li id=\"fn1\">\n",
+            "<p>First. <a href=\"#fnref1\" class=\"footnote-backref\" role=\"doc-backlink\" ",
+            "aria-label=\"Back to reference 1\">↩</a></p>\n",
+            "</li>\n",
+            "<li id=\"fn2\">\n",
+            "<p>Second <em>note</em>.</p>\n",
+            "<p>With more. <a href=\"#fnref2\" class=\"footnote-backref\" role=\"doc-backlink\" ",
+            "aria-label=\"Back to reference 2\">↩</a></p>\n",
+            "</li>\n",
+            "<li id=\"fn3\">\n",
+            "<p>Never referenced.</p>\n",
+            "</li>\n",
+            "</ol>\n",
+            "</section>\n"
+        ),
+        |opts| opts.ext_footnotes = true,
+    );
+
+    html_opts(
+        "A[^x].\n\n[^x]: - A list.\n",
+        concat!(
+            "<p>A<sup class=\"footnote-ref\">",
+            "<a href=\"#fn1\" id=\"fnref1\" role=\"doc-noteref\">1</a></sup>.</p>\n",
+            "<section class=\"footnotes\" role=\"doc-endnotes\">\n",
+            "<ol>\n",
+            "<li id=\"fn1\">\n",
+            "<ul>\n",
+            "<li>A list.</li>\n",
+            "</ul>\n",
+            "<a href=\"#fnref1\" class=\"footnote-backref\" role=\"doc-backlink\" ",
+            "aria-label=\"Back to reference 1\">↩</a>\n",
+            "</li>\n",
+            "</ol>\n",
+            "</section>\n"
+        ),
+        |opts| opts.ext_footnotes = true,
+    );
+
+    html("A[^x].\n", "<p>A[^x].</p>\n");
+
+    let mut options = ComrakOptions::default();
+    options.ext_footnotes = true;
+    options.ext_html_sections = true;
+    let arena = Arena::new();
+    let root = parse_document(
+        &arena,
+        "# A\n\nOne[^a].\n\n# B\n\nTwo[^b].\n\n[^a]: Note a.\n[^b]: Note b.\n",
+        &options,
+    );
+    let footnotes = concat!(
+        "<section class=\"footnotes\" role=\"doc-endnotes\">\n",
+        "<ol>\n",
+        "<li id=\"fn1\">\n",
+        "<p>Note a. <a href=\"#fnref1\" class=\"footnote-backref\" role=\"doc-backlink\" ",
+        "aria-label=\"Back to reference 1\">↩</a></p>\n",
+        "</li>\n",
+        "<li id=\"fn2\">\n",
+        "<p>Note b. <a href=\"#fnref2\" class=\"footnote-backref\" role=\"doc-backlink\" ",
+        "aria-label=\"Back to reference 2\">↩</a></p>\n",
+        "</li>\n",
+        "</ol>\n",
+        "</section>\n"
+    );
+    let b = concat!(
+        "<section>\n",
+        "<h1>B</h1>\n",
+        "<p>Two<sup class=\"footnote-ref\">",
+        "<a href=\"#fn2\" id=\"fnref2\" role=\"doc-noteref\">2</a></sup>.</p>\n",
+        "</section>\n"
+    );
+    assert_eq!(
+        ::html::format_document(root, &options),
+        [
+            "<section>\n<h1>A</h1>\n",
+            "<p>One<sup class=\"footnote-ref\">",
+            "<a href=\"#fn1\" id=\"fnref1\" role=\"doc-noteref\">1</a></sup>.</p>\n",
+            "</section>\n",
+            b,
+            footnotes,
+        ].concat()
+    );
+    assert_eq!(::html::format_section(root, 1, 1, &options).unwrap(), [b, footnotes].concat());
+}
+
+#[test]
+fn footnote_backrefs() {
+    html_opts(
+        "A[^x].\n\n[^x]: Note.\n",
+        concat!(
+            "<p>A<sup class=\"footnote-ref\">",
+            "<a href=\"#fn1\" id=\"fnref1\" role=\"doc-noteref\">1</a></sup>.</p>\n",
+            "<section class=\"footnotes\" role=\"doc-endnotes\">\n",
+            "<ol>\n",
+            "<li id=\"fn1\">\n",
+            "<p>Note. <a href=\"#fnref1\" class=\"footnote-backref\" role=\"doc-backlink\" ",
+            "aria-label=\"Back to reference 1\" aria-hidden=\"true\" ",
+            "style=\"display:none\">&lt;back&gt;</a></p>\n",
+            "</li>\n",
+            "</ol>\n",
+            "</section>\n"
+        ),
+        |opts| {
+            opts.ext_footnotes = true;
+            opts.footnote_backref_symbol = Some("<back>".to_string());
+            opts.footnote_backref_hidden = true;
+        },
+    );
+
+    html_opts(
+        "A[^x].\n\n[^x]: Note.\n",
+        concat!(
+            "<p>A<sup class=\"footnote-ref\">",
+            "<a href=\"#fn1\" id=\"fnref1\" role=\"doc-noteref\">1</a></sup>.</p>\n",
+            "<section class=\"footnotes\" role=\"doc-endnotes\">\n",
+            "<ol>\n",
+            "<li id=\"fn1\">\n",
+            "<p>Note. <a href=\"#fnref1\" class=\"footnote-backref\" role=\"doc-backlink\" ",
+            "aria-label=\"Back to reference 1\"></a></p>\n",
+            "</li>\n",
+            "</ol>\n",
+            "</section>\n"
+        ),
+        |opts| {
+            opts.ext_footnotes = true;
+            opts.footnote_backref_symbol = Some(String::new());
+        },
+    );
+}
+
+#[test]
+fn footnote_numbering() {
+    let input = concat!(
+        "Intro[^i].\n",
+        "\n",
+        "## A\n",
+        "\n",
+        "One[^a], again[^i].\n",
+        "\n",
+        "## B\n",
+        "\n",
+        "Two[^b].\n",
+        "\n",
+        "[^i]: I.\n",
+        "\n",
+        "[^a]: A.\n",
+        "\n",
+        "[^b]: B.\n",
+        "\n",
+        "[^u]: U.\n"
+    );
+    let note = |id: &str, text: &str, number: Option<&str>| match number {
+        Some(number) => format!(
+            concat!(
+                "<li id=\"fn{}\">\n<p>{} <a href=\"#fnref{}\" class=\"footnote-backref\" ",
+                "role=\"doc-backlink\" aria-label=\"Back to reference {}\">↩</a></p>\n</li>\n"
+            ),
+            id,
+            text,
+            id,
+            number
+        ),
+        None => format!("<li id=\"fn{}\">\n<p>{}</p>\n</li>\n", id, text),
+    };
+    let reference = |id: &str, number: &str, first: bool| if first {
+        format!(
+            "<sup class=\"footnote-ref\"><a href=\"#fn{}\" id=\"fnref{}\" \
+             role=\"doc-noteref\">{}</a></sup>",
+            id,
+            id,
+            number
+        )
+    } else {
+        format!(
+            "<sup class=\"footnote-ref\"><a href=\"#fn{}\" role=\"doc-noteref\">{}</a></sup>",
+            id,
+            number
+        )
+    };
+    let open = "<section class=\"footnotes\" role=\"doc-endnotes\">\n<ol>\n";
+    let close = "</ol>\n</section>\n";
+
+    html_opts(
+        input,
+        &[
+            format!("<p>Intro{}.</p>\n", reference("1", "1", true)),
+            "<h2>A</h2>\n".to_string(),
+            format!(
+                "<p>One{}, again{}.</p>\n",
+                reference("2", "2", true),
+                reference("1", "1", false)
+            ),
+            "<h2>B</h2>\n".to_string(),
+            format!("<p>Two{}.</p>\n", reference("3", "3", true)),
+            open.to_string(),
+            note("1", "I.", Some("1")),
+            note("2", "A.", Some("2")),
+            note("3", "B.", Some("3")),
+            note("4", "U.", None),
+            close.to_string(),
+        ].concat(),
+        |opts| opts.ext_footnotes = true,
+    );
+
+    // Each section's footnotes follow it, numbered from 1.  An unreferenced footnote joins the
+    // last section, and a reference back to an earlier section keeps that section's number.
+    html_opts(
+        input,
+        &[
+            format!("<p>Intro{}.</p>\n", reference("-section-0-note-1", "1", true)),
+            open.to_string(),
+            note("-section-0-note-1", "I.", Some("1")),
+            close.to_string(),
+            "<h2>A</h2>\n".to_string(),
+            format!(
+                "<p>One{}, again{}.</p>\n",
+                reference("-section-1-note-1", "1", true),
+                reference("-section-0-note-1", "1", false)
+            ),
+            open.to_string(),
+            note("-section-1-note-1", "A.", Some("1")),
+            close.to_string(),
+            "<h2>B</h2>\n".to_string(),
+            format!("<p>Two{}.</p>\n", reference("-section-2-note-1", "1", true)),
+            open.to_string(),
+            note("-section-2-note-1", "B.", Some("1")),
+            note("-section-2-note-2", "U.", None),
+            close.to_string(),
+        ].concat(),
+        |opts| {
+            opts.ext_footnotes = true;
+            opts.footnote_numbering = FootnoteNumbering::PerSection { level: 2 };
+        },
+    );
+
+    // Deeper headings do not start a section.
+    html_opts(
+        "# T\n\nA[^a].\n\n### S\n\nB[^b].\n\n[^a]: A.\n\n[^b]: B.\n",
+        &[
+            "<h1>T</h1>\n",
+            &format!("<p>A{}.</p>\n", reference("-section-1-note-1", "1", true)),
+            "<h3>S</h3>\n",
+            &format!("<p>B{}.</p>\n", reference("-section-1-note-2", "2", true)),
+            open,
+            &note("-section-1-note-1", "A.", Some("1")),
+            &note("-section-1-note-2", "B.", Some("2")),
+            close,
+        ].concat(),
+        |opts| {
+            opts.ext_footnotes = true;
+            opts.footnote_numbering = FootnoteNumbering::PerSection { level: 2 };
+        },
+    );
+}
+
+#[test]
+fn autolink_phone() {
+    html_opts(
+        concat!(
+            "Call +1-555-234-5678, (555) 234-5678 or 555.234.5678.\n",
+            "\n",
+            "Abroad: +44 203 555 0123 or +442035550123.\n"
+        ),
+        concat!(
+            "<p>Call <a href=\"tel:+15552345678\">+1-555-234-5678</a>, ",
+            "<a href=\"tel:5552345678\">(555) 234-5678</a> or ",
+            "<a href=\"tel:5552345678\">555.234.5678</a>.</p>\n",
+            "<p>Abroad: <a href=\"tel:+442035550123\">+44 203 555 0123</a> or ",
+            "<a href=\"tel:+442035550123\">+442035550123</a>.</p>\n"
+        ),
+        |opts| opts.ext_autolink_phone = true,
+    );
+}
+
+#[test]
+fn autolink_phone_false_positives() {
+    html_opts(
+        concat!(
+            "Version 1.0.0, 10.255.255.1, 2017-06-30 and 555-1234.\n",
+            "\n",
+            "Order 1555.234.5678 or 555.234.5678.9, id x555-234-5678.\n"
+        ),
+        concat!(
+            "<p>Version 1.0.0, 10.255.255.1, 2017-06-30 and 555-1234.</p>\n",
+            "<p>Order 1555.234.5678 or 555.234.5678.9, id x555-234-5678.</p>\n"
+        ),
+        |opts| opts.ext_autolink_phone = true,
+    );
+}
+
+fn block_sourcepos(input: &str) -> Vec<(u32, usize, u32, usize)> {
+    let arena = Arena::new();
+    let root = parse_document(&arena, input, &ComrakOptions::default());
+    root.descendants()
+        .filter(|n| n.data.borrow().value.block())
+        .map(|n| {
+            let ast = n.data.borrow();
+            (ast.start_line, ast.start_column, ast.end_line, ast.end_column)
+        })
+        .collect()
+}
+
+#[test]
+fn cr_line_endings() {
+    let expected = vec![
+        (0, 0, 8, 3),
+        (1, 1, 2, 8),
+        (4, 1, 4, 6),
+        (6, 1, 8, 3),
+    ];
+
+    let lf = "Para one\nline two\n\n# Head\n\n```\ncode\n```\n";
+    assert_eq!(block_sourcepos(lf), expected);
+    assert_eq!(block_sourcepos(&lf.replace("\n", "\r")), expected);
+    assert_eq!(block_sourcepos(&lf.replace("\n", "\r\n")), expected);
+    assert_eq!(block_sourcepos(lf.trim_end()), expected);
+    assert_eq!(block_sourcepos(lf.replace("\n", "\r").trim_end()), expected);
+
+    let expected = vec![(0, 0, 2, 1), (1, 1, 2, 1)];
+    assert_eq!(block_sourcepos("a\rb"), expected);
+    assert_eq!(block_sourcepos("a\rb\r"), expected);
+    assert_eq!(block_sourcepos("a\r\nb"), expected);
+
+    html("a\rb\r\rc\r", "<p>a\nb</p>\n<p>c</p>\n");
+}
+
+#[test]
+fn sourcepos_tab_columns() {
+    assert_eq!(
+        block_sourcepos(">\t# Head\n"),
+        vec![(0, 0, 1, 10), (1, 1, 1, 10), (1, 5, 1, 10)]
+    );
+    assert_eq!(
+        block_sourcepos("-\t# Head\n\n\tpara\tx\n"),
+        vec![
+            (0, 0, 3, 13),
+            (1, 1, 3, 13),
+            (1, 1, 3, 13),
+            (1, 5, 1, 10),
+            (3, 5, 3, 13),
+        ]
+    );
+    assert_eq!(block_sourcepos("  \tcode\n"), vec![(0, 0, 1, 8), (1, 5, 1, 8)]);
+}
+
+#[test]
+fn code_block_content_lines() {
+    let arena = Arena::new();
+    let root = parse_document(
+        &arena,
+        concat!(
+            "Text\n",
+            "\n",
+            "```rust\n",
+            "a\n",
+            "b\n",
+            "```\n",
+            "\n",
+            "    x\n",
+            "    y\n",
+            "\n",
+            "\n",
+            "```\n",
+            "```\n",
+            "\n",
+            "> ~~~\n",
+            "> unclosed\n"
+        ),
+        &ComrakOptions::default(),
+    );
+    let lines = root.descendants()
+        .filter_map(|n| match n.data.borrow().value {
+            NodeValue::CodeBlock(ref ncb) => Some(ncb.content_lines),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(lines, vec![Some((4, 5)), Some((8, 9)), None, Some((16, 16))]);
+
+    // Like other source positions, the lines are ignored when diffing.
+    let options = ComrakOptions::default();
+    let old = parse_document(&arena, "```c\nx\n```\n", &options);
+    let new = parse_document(&arena, "\n\n```c\nx\n```\n", &options);
+    match diff_ast(old, new)[..] {
+        [AstDiff::Unchanged(_)] => (),
+        _ => panic!("code block reported as changed"),
+    }
+}
+
+#[test]
+fn first_image() {
+    use nodes;
+
+    let arena = Arena::new();
+    let root = parse_document(
+        &arena,
+        concat!(
+            "```\n",
+            "![code](/code.png)\n",
+            "```\n",
+            "\n",
+            "<div>\n",
+            "![html](/html.png)\n",
+            "</div>\n",
+            "\n",
+            "> Intro <img src=\"/raw.png\">, then\n",
+            "> [![A **bold**\n",
+            "> cat](/cat.png \"Cat\")](/link) and ![dog](/dog.png).\n"
+        ),
+        &ComrakOptions::default(),
+    );
+
+    assert_eq!(
+        nodes::first_image(root),
+        Some(("/cat.png".to_string(), "A bold cat".to_string()))
+    );
+
+    let root = parse_document(&arena, "No images.\n", &ComrakOptions::default());
+    assert_eq!(nodes::first_image(root), None);
+}
+
+#[test]
+fn node_search() {
+    use nodes;
+
+    let arena = Arena::new();
+    let root = parse_document(
+        &arena,
+        concat!(
+            "# One\n",
+            "\n",
+            "[a](/a) ![b](/b.png)\n",
+            "\n",
+            "Two\n",
+            "---\n",
+            "\n",
+            "> [c](/c)\n"
+        ),
+        &ComrakOptions::default(),
+    );
+
+    assert_eq!(nodes::headings(root).count(), 2);
+    assert_eq!(nodes::links(root).count(), 2);
+    assert_eq!(nodes::images(root).count(), 1);
+
+    let quote = nodes::find_first(root, |n| match n.data.borrow().value {
+        NodeValue::BlockQuote => true,
+        _ => false,
+    }).unwrap();
+    assert_eq!(quote.data.borrow().start_line, 8);
+    assert_eq!(nodes::links(quote).count(), 1);
+
+    assert!(nodes::find_first(root, |n| match n.data.borrow().value {
+        NodeValue::Table(..) => true,
+        _ => false,
+    }).is_none());
+    assert_eq!(
+        nodes::find_all(root, |n| n.data.borrow().value.block()).len(),
+        6
+    );
+}
+
+#[test]
+fn node_accessors() {
+    use nodes;
+
+    let arena = Arena::new();
+    let root = parse_document(
+        &arena,
+        concat!(
+            "## `code` and ![img](/i.png)\n",
+            "\n",
+            "``` rust\n",
+            "fn main() {}\n",
+            "```\n"
+        ),
+        &ComrakOptions::default(),
+    );
+
+    let heading = root.first_child().unwrap();
+    assert_eq!(nodes::node_heading_level(heading), Some(2));
+    assert_eq!(nodes::node_heading_level(root), None);
+
+    let code = heading.first_child().unwrap();
+    assert_eq!(&*nodes::node_literal(code).unwrap(), "code");
+    assert!(nodes::node_link_url(code).is_none());
+
+    let image = heading.last_child().unwrap();
+    assert_eq!(&*nodes::node_link_url(image).unwrap(), "/i.png");
+    assert_eq!(&*nodes::node_link_title(image).unwrap(), "");
+
+    let code_block = root.last_child().unwrap();
+    assert_eq!(&*nodes::node_code_block_info(code_block).unwrap(), "rust");
+    assert_eq!(&*nodes::node_code_block_literal(code_block).unwrap(), "fn main() {}\n");
+    assert!(nodes::node_html_block_literal(code_block).is_none());
+
+    // The returned borrow is released once dropped.
+    code_block.data.borrow_mut().content.clear();
+}
+
+#[test]
+fn html_sections() {
+    html_opts(
+        concat!(
+            "Preamble.\n",
+            "\n",
+            "# One\n",
+            "\n",
+            "## Two\n",
+            "\n",
+            "### Three\n",
+            "\n",
+            "## Four\n",
+            "\n",
+            "> # Quoted\n",
+            "\n",
+            "# Five\n"
+        ),
+        concat!(
+            "<p>Preamble.</p>\n",
+            "<section>\n",
+            "<h1>One</h1>\n",
+            "<section>\n",
+            "<h2>Two</h2>\n",
+            "<section>\n",
+            "<h3>Three</h3>\n",
+            "</section>\n",
+            "</section>\n",
+            "<section>\n",
+            "<h2>Four</h2>\n",
+            "<blockquote>\n",
+            "<h1>Quoted</h1>\n",
+            "</blockquote>\n",
+            "</section>\n",
+            "</section>\n",
+            "<section>\n",
+            "<h1>Five</h1>\n",
+            "</section>\n"
+        ),
+        |opts| opts.ext_html_sections = true,
+    );
+}
+
+#[test]
+fn render_many_independent() {
+    let mut options = ComrakOptions::default();
+    options.gfm_quirks = true;
+    let docs = [
+        "[r]: /one\n\n[r] ~~x~~\n",
+        "[r] www.example.com\n",
+        "[r]: /three\n\n[r]\n",
+    ];
+    let expected = docs.iter()
+        .map(|md| ::markdown_to_html(md, &options))
+        .collect::<Vec<_>>();
+    assert_eq!(render_many(&docs, &options), expected);
+    assert_eq!(
+        expected,
+        vec![
+            "<p><a href=\"/one\">r</a> <del>x</del></p>\n",
+            "<p>[r] <a href=\"http://www.example.com\">www.example.com</a></p>\n",
+            "<p><a href=\"/three\">r</a></p>\n",
+        ]
+    );
+    assert!(render_many(&[], &options).is_empty());
+}
+
+#[test]
+fn format_json_structure() {
+    let arena = Arena::new();
+    let options = ComrakOptions::default();
+    let input = "# Hi\n\n```c\nx\ty\n```\n\n* \"a\"\\\n  [b](/u)\n";
+    let root = parse_document(&arena, input, &options);
+    compare_strs(
+        &format_json(root, &options),
+        concat!(
+            "{\"type\":\"document\",\"children\":[",
+            "{\"type\":\"heading\",\"sourcepos\":[1,1,1,4],\"level\":1,\"setext\":false,",
+            "\"children\":[{\"type\":\"text\",\"literal\":\"Hi\",\"children\":[]}]},",
+            "{\"type\":\"code_block\",\"sourcepos\":[3,1,5,3],\"fenced\":true,\"info\":\"c\",",
+            "\"literal\":\"x\\ty\\n\",\"content_lines\":[4,4],\"children\":[]},",
+            "{\"type\":\"list\",\"sourcepos\":[7,1,8,9],\"list_type\":\"bullet\",",
+            "\"bullet_char\":\"*\",\"tight\":true,\"children\":[",
+            "{\"type\":\"item\",\"sourcepos\":[7,1,8,9],\"list_type\":\"bullet\",",
+            "\"bullet_char\":\"*\",\"children\":[",
+            "{\"type\":\"paragraph\",\"sourcepos\":[7,3,8,9],\"children\":[",
+            "{\"type\":\"text\",\"literal\":\"\\\"a\\\"\",\"children\":[]},",
+            "{\"type\":\"linebreak\",\"children\":[]},",
+            "{\"type\":\"link\",\"url\":\"/u\",\"title\":\"\",\"children\":[",
+            "{\"type\":\"text\",\"literal\":\"b\",\"children\":[]}]}]}]}]}]}\n"
+        ),
+        "json",
+    );
+}
+
+#[test]
+fn normalize_fragmented_text() {
+    let arena = Arena::new();
+    let options = ComrakOptions::default();
+    let root = parse_document(&arena, "x *y* z `c` w\n", &options);
+    let paragraph = root.first_child().unwrap();
+
+    let emph = paragraph.children().nth(1).unwrap();
+    for child in emph.children().collect::<Vec<_>>() {
+        emph.insert_before(child);
+    }
+    emph.detach();
+    *paragraph
+        .last_child()
+        .unwrap()
+        .data
+        .borrow_mut()
+        .value
+        .text_mut()
+        .unwrap() = String::new();
+    assert_eq!(paragraph.children().count(), 5);
+
+    normalize(&arena, root, &options);
+    let children = paragraph
+        .children()
+        .map(|n| n.data.borrow().value.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        children,
+        vec![
+            NodeValue::Text("x y z ".to_string()),
+            NodeValue::Code("c".to_string()),
+        ]
+    );
+
+    let mut options = ComrakOptions::default();
+    options.ext_abbreviations = true;
+    let root = parse_document(&arena, "*[HTML]: HyperText\n\nx\n", &options);
+    let text = root.last_child().unwrap().first_child().unwrap();
+    text.data.borrow_mut().value = NodeValue::Text("See HTML.".to_string());
+    normalize(&arena, root, &options);
+    assert_eq!(
+        ::html::format_document(root, &options),
+        "<p>See <abbr title=\"HyperText\">HTML</abbr>.</p>\n"
+    );
+}
+
+#[test]
+fn format_section() {
+    let input = concat!(
+        "Preamble.\n",
+        "\n",
+        "# One\n",
+        "\n",
+        "Intro.\n",
+        "\n",
+        "## Two\n",
+        "\n",
+        "### Three\n",
+        "\n",
+        "Deep.\n",
+        "\n",
+        "## Four\n",
+        "\n",
+        "> # Quoted\n",
+        "\n",
+        "# Five\n",
+        "\n",
+        "The end.\n"
+    );
+
+    let arena = Arena::new();
+    let mut options = ComrakOptions::default();
+    let root = parse_document(&arena, input, &options);
+
+    assert_eq!(
+        ::html::format_section(root, 1, 0, &options).unwrap(),
+        concat!(
+            "<h1>One</h1>\n",
+            "<p>Intro.</p>\n",
+            "<h2>Two</h2>\n",
+            "<h3>Three</h3>\n",
+            "<p>Deep.</p>\n",
+            "<h2>Four</h2>\n",
+            "<blockquote>\n",
+            "<h1>Quoted</h1>\n",
+            "</blockquote>\n"
+        )
+    );
+    assert_eq!(
+        ::html::format_section(root, 2, 0, &options).unwrap(),
+        concat!("<h2>Two</h2>\n", "<h3>Three</h3>\n", "<p>Deep.</p>\n")
+    );
+    assert_eq!(
+        ::html::format_section(root, 2, 1, &options).unwrap(),
+        concat!("<h2>Four</h2>\n", "<blockquote>\n", "<h1>Quoted</h1>\n", "</blockquote>\n")
+    );
+    assert_eq!(
+        ::html::format_section(root, 1, 1, &options).unwrap(),
+        concat!("<h1>Five</h1>\n", "<p>The end.</p>\n")
+    );
+    assert_eq!(::html::format_section(root, 1, 2, &options), None);
+    assert_eq!(::html::format_section(root, 4, 0, &options), None);
+
+    options.ext_html_sections = true;
+    assert_eq!(
+        ::html::format_section(root, 2, 0, &options).unwrap(),
+        concat!(
+            "<section>\n",
+            "<h2>Two</h2>\n",
+            "<section>\n",
+            "<h3>Three</h3>\n",
+            "<p>Deep.</p>\n",
+            "</section>\n",
+            "</section>\n"
+        )
+    );
+}
+
+#[test]
+fn table_rows() {
+    use nodes;
+
+    let arena = Arena::new();
+    let mut options = ComrakOptions::default();
+    options.ext_table = true;
+    let root = parse_document(
+        &arena,
+        concat!(
+            "| Name | Notes |\n",
+            "| ---- | ----- |\n",
+            "| **comrak** | A [parser](/comrak) in `Rust` |\n",
+            "| *cmark* |\n"
+        ),
+        &options,
+    );
+
+    assert_eq!(
+        nodes::table_to_rows(root.first_child().unwrap()),
+        vec![
+            vec!["Name".to_string(), "Notes".to_string()],
+            vec!["comrak".to_string(), "A parser in Rust".to_string()],
+            vec!["cmark".to_string(), "".to_string()],
+        ]
+    );
+    assert!(nodes::table_to_rows(root).is_empty());
+}
+
+#[test]
+fn trim_ascii_whitespace() {
+    use strings;
+
+    let mut s = "\t\n\x0b\x0c\r x \t\n\x0b\x0c\r ".to_string();
+    strings::trim(&mut s);
+    assert_eq!(s, "x");
+
+    let mut s = " \x0b\u{a0}é\x0c ".to_string();
+    strings::trim(&mut s);
+    assert_eq!(s, "\u{a0}é");
+
+    assert_eq!(strings::trim_ascii_start("\x0c\x0b a b "), "a b ");
+    assert_eq!(strings::trim_ascii_end(" a b \x0b\x0c"), " a b");
+    assert_eq!(strings::trim_slice(" \t\r\n"), "");
+}
+
+#[test]
+fn extension_names() {
+    for &extension in Extension::all() {
+        assert_eq!(extension.name().parse(), Ok(extension));
+        assert_eq!(extension.to_string(), extension.name());
+    }
+
+    assert_eq!("table".parse(), Ok(Extension::Table));
+    assert_eq!("fenced-divs".parse(), Ok(Extension::FencedDivs));
+    assert_eq!(
+        "Table".parse::<Extension>(),
+        Err(UnknownExtension("Table".to_string()))
+    );
+    assert_eq!(
+        "fenced_divs".parse::<Extension>(),
+        Err(UnknownExtension("fenced_divs".to_string()))
+    );
+    assert_eq!(
+        "".parse::<Extension>().unwrap_err().to_string(),
+        "unknown extension: "
+    );
+
+    let mut options = ComrakOptions::default();
+    options.enable_extension(Extension::Table);
+    options.enable_extension(Extension::AutolinkPhone);
+    assert!(options.ext_table && options.ext_autolink_phone);
+    assert!(!options.ext_autolink && !options.ext_strikethrough);
+}
+
+#[test]
+fn with_extensions() {
+    let gfm = ComrakOptions::with_extensions(
+        &["table", "strikethrough", "autolink", "tagfilter", "tasklist"],
+    ).unwrap();
+    assert!(gfm.ext_table && gfm.ext_strikethrough && gfm.ext_autolink);
+    assert!(gfm.ext_tagfilter && gfm.ext_tasklist);
+    assert!(!gfm.ext_superscript && !gfm.ext_collapsible && !gfm.hardbreaks);
+
+    let names = vec!["table".to_string()];
+    assert!(ComrakOptions::with_extensions(&names).unwrap().ext_table);
+    assert!(!ComrakOptions::with_extensions::<&str>(&[]).unwrap().ext_table);
+
+    assert_eq!(
+        ComrakOptions::with_extensions(&["table", "emoji", "tables"]).unwrap_err(),
+        UnknownExtension("emoji".to_string())
+    );
+}
+
+/// Renders `input` with the `cmark` binary at `cmark`, or returns `None` if it could not be run.
+fn cmark_html(cmark: &str, input: &str) -> Option<String> {
+    let mut child = Command::new(cmark)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .ok()?;
+    child.stdin.take()?.write_all(input.as_bytes()).ok()?;
+    let mut output = String::new();
+    child.stdout.take()?.read_to_string(&mut output).ok()?;
+    if !child.wait().ok()?.success() {
+        return None;
+    }
+    Some(output)
+}
+
+/// Compares our HTML output against that of the reference `cmark` binary for every `.md` file in
+/// the directory named by `COMRAK_CMARK_CORPUS`.  The binary is found on the `PATH` unless given
+/// by `COMRAK_CMARK`.  Skipped when the corpus is not set or `cmark` cannot be run.
+#[test]
+fn cmark_conformance() {
+    let corpus = match env::var("COMRAK_CMARK_CORPUS") {
+        Ok(corpus) => corpus,
+        Err(_) => return,
+    };
+    let cmark = env::var("COMRAK_CMARK").unwrap_or_else(|_| "cmark".to_string());
+    if cmark_html(&cmark, "").is_none() {
+        println!("skipping cmark conformance: could not run {}", cmark);
+        return;
+    }
+
+    let mut paths = fs::read_dir(&corpus)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "md"))
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let options = ComrakOptions::default();
+    let mut mismatches = vec![];
+    for path in &paths {
+        let input = fs::read_to_string(path).unwrap();
+        let expected = cmark_html(&cmark, &input).unwrap();
+
+        let arena = Arena::new();
+        let root = parse_document(&arena, &input, &options);
+        let output = ::html::format_document(root, &options);
+        if output != expected {
+            println!("Mismatch in {}", path.display());
+            println!("comrak:");
+            println!("==============================");
+            println!("{}", output);
+            println!("==============================");
+            println!("cmark:");
+            println!("==============================");
+            println!("{}", expected);
+            println!("==============================");
+            println!();
+            mismatches.push(path.display().to_string());
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "{} of {} files differ from cmark: {}",
+        mismatches.len(),
+        paths.len(),
+        mismatches.join(", ")
+    );
 }